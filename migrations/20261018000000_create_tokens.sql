@@ -0,0 +1,75 @@
+-- Balances and nanosecond timestamps don't fit in BIGINT, so they're stored as NUMERIC
+
+CREATE TABLE tokens (
+    account_id TEXT PRIMARY KEY,
+    name TEXT,
+    symbol TEXT,
+    decimals SMALLINT,
+    icon TEXT,
+    reference TEXT,
+    reference_hash TEXT,
+    spec TEXT,
+    owner_id TEXT,
+    total_supply NUMERIC(39, 0),
+    meme_id BIGINT,
+    risk JSONB,
+    metadata_issues JSONB NOT NULL DEFAULT '[]',
+    metadata_block_height BIGINT,
+
+    transaction_id TEXT NOT NULL,
+    receipt_id TEXT NOT NULL,
+    block_height BIGINT NOT NULL,
+    block_timestamp_nanosec NUMERIC(39, 0) NOT NULL,
+    signer_id TEXT NOT NULL,
+    predecessor_id TEXT NOT NULL,
+    attached_deposit NUMERIC(39, 0) NOT NULL,
+    factory_id TEXT
+);
+
+CREATE INDEX tokens_block_height ON tokens (block_height);
+CREATE INDEX tokens_factory_id ON tokens (factory_id);
+
+CREATE TABLE meme_cooking_memes (
+    meme_id BIGINT PRIMARY KEY,
+    owner TEXT NOT NULL,
+    end_timestamp_ms BIGINT NOT NULL,
+    name TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    decimals INTEGER NOT NULL,
+    total_supply NUMERIC(39, 0) NOT NULL,
+    reference TEXT NOT NULL,
+    reference_hash TEXT NOT NULL,
+    reference_document JSONB,
+    deposit_token_id TEXT NOT NULL,
+    soft_cap NUMERIC(39, 0) NOT NULL,
+    hard_cap NUMERIC(39, 0),
+    -- 'reached_soft_cap', 'reached_hard_cap' or 'expired_without_token'
+    outcome TEXT,
+    total_deposited NUMERIC(39, 0),
+    participants INTEGER,
+
+    transaction_id TEXT NOT NULL,
+    receipt_id TEXT NOT NULL,
+    block_height BIGINT NOT NULL,
+    block_timestamp_nanosec NUMERIC(39, 0) NOT NULL,
+    signer_id TEXT NOT NULL,
+    predecessor_id TEXT NOT NULL,
+    attached_deposit NUMERIC(39, 0) NOT NULL,
+    factory_id TEXT
+);
+
+CREATE TABLE meme_cooking_tokens (
+    meme_id BIGINT PRIMARY KEY,
+    token_id TEXT NOT NULL UNIQUE,
+    total_supply NUMERIC(39, 0) NOT NULL,
+    pool_id BIGINT NOT NULL,
+
+    transaction_id TEXT NOT NULL,
+    receipt_id TEXT NOT NULL,
+    block_height BIGINT NOT NULL,
+    block_timestamp_nanosec NUMERIC(39, 0) NOT NULL,
+    signer_id TEXT NOT NULL,
+    predecessor_id TEXT NOT NULL,
+    attached_deposit NUMERIC(39, 0) NOT NULL,
+    factory_id TEXT
+);
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use inindexer::near_indexer_primitives::types::{AccountId, BlockHeight};
+use serde::{Deserialize, Serialize};
+
+use crate::new_nep141::TokenRegistry;
+use crate::IndexerStatus;
+
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+
+#[derive(Clone)]
+struct ApiState {
+    tokens: Arc<dyn TokenRegistry>,
+    status: Arc<IndexerStatus>,
+    testnet: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub testnet: bool,
+    pub last_block_height: Option<BlockHeight>,
+    pub last_block_timestamp_nanosec: Option<u64>,
+    /// Time between the last processed block and now
+    pub seconds_behind: Option<u64>,
+    pub known_tokens: usize,
+}
+
+#[derive(Deserialize)]
+struct RangeQuery {
+    from_block: Option<BlockHeight>,
+    to_block: Option<BlockHeight>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    symbol: String,
+    limit: Option<usize>,
+}
+
+/// Read-only HTTP API over the storage of known tokens:
+///
+/// - `GET /tokens/{account_id}`: the token, or 404 if it's not known
+/// - `GET /tokens?from_block=&to_block=&limit=`: tokens first seen in this range of blocks
+///   (both ends included), oldest first
+/// - `GET /tokens/search?symbol=&limit=`: tokens with this text in their symbol, exact
+///   matches first
+/// - `GET /status`: last processed block and the number of known tokens
+///
+/// `limit` defaults to [`DEFAULT_LIMIT`] and can't be more than [`MAX_LIMIT`].
+pub fn api_router(
+    tokens: Arc<dyn TokenRegistry>,
+    status: Arc<IndexerStatus>,
+    testnet: bool,
+) -> Router {
+    Router::new()
+        .route("/tokens", get(tokens_in_range))
+        .route("/tokens/search", get(search_tokens))
+        .route("/tokens/{account_id}", get(get_token))
+        .route("/status", get(get_status))
+        .with_state(ApiState {
+            tokens,
+            status,
+            testnet,
+        })
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(serde_json::json!({ "error": message.into() }))).into_response()
+}
+
+fn limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+}
+
+async fn get_token(State(state): State<ApiState>, Path(account_id): Path<String>) -> Response {
+    let Ok(account_id) = account_id.parse::<AccountId>() else {
+        return error(StatusCode::BAD_REQUEST, "Invalid account id");
+    };
+    match state.tokens.get_token(&account_id).await {
+        Some(token) => Json(token).into_response(),
+        None => error(StatusCode::NOT_FOUND, "Token not found"),
+    }
+}
+
+async fn tokens_in_range(
+    State(state): State<ApiState>,
+    Query(query): Query<RangeQuery>,
+) -> Response {
+    Json(
+        state
+            .tokens
+            .tokens_first_seen_between(
+                query.from_block.unwrap_or(0),
+                query.to_block.unwrap_or(BlockHeight::MAX),
+                limit(query.limit),
+            )
+            .await,
+    )
+    .into_response()
+}
+
+async fn search_tokens(
+    State(state): State<ApiState>,
+    Query(query): Query<SearchQuery>,
+) -> Response {
+    if query.symbol.is_empty() {
+        return error(StatusCode::BAD_REQUEST, "Empty symbol");
+    }
+    Json(
+        state
+            .tokens
+            .search_by_symbol(&query.symbol, limit(query.limit))
+            .await,
+    )
+    .into_response()
+}
+
+async fn get_status(State(state): State<ApiState>) -> Json<StatusResponse> {
+    let last_block_timestamp_nanosec = state.status.last_block_timestamp_nanosec();
+    Json(StatusResponse {
+        testnet: state.testnet,
+        last_block_height: state.status.last_block_height(),
+        last_block_timestamp_nanosec,
+        seconds_behind: last_block_timestamp_nanosec.map(|timestamp| {
+            let now = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+            now.saturating_sub(timestamp) / 1_000_000_000
+        }),
+        known_tokens: state.tokens.token_count().await,
+    })
+}
@@ -0,0 +1,218 @@
+use std::sync::Arc;
+
+use inindexer::{
+    near_indexer_primitives::{
+        types::AccountId,
+        views::{ActionView, ReceiptEnumView},
+        StreamerMessage,
+    },
+    IncompleteTransaction, TransactionReceipt,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{new_nep141::HandledTokensStorage, ContractEventHandler, EventContext};
+
+/// Detects ERC-20s (and other foreign tokens) that are deployed as sub-accounts of bridge
+/// factories. The token itself still goes through the regular NEP-141 detection.
+pub struct BridgeIndexer {
+    storage: Arc<dyn HandledTokensStorage>,
+    pub factories: Vec<BridgeFactory>,
+}
+
+impl BridgeIndexer {
+    pub fn new(storage: Arc<dyn HandledTokensStorage>, factories: Vec<BridgeFactory>) -> Self {
+        Self { storage, factories }
+    }
+
+    pub async fn detect_bridged_tokens<T: ContractEventHandler>(
+        &mut self,
+        receipt: &TransactionReceipt,
+        tx: &IncompleteTransaction,
+        block: &StreamerMessage,
+        handler: Arc<T>,
+    ) {
+        let token_id = &receipt.receipt.receipt.receiver_id;
+        let Some(factory) = self
+            .factories
+            .iter()
+            .find(|factory| factory.is_factory_of(token_id))
+        else {
+            return;
+        };
+        let ReceiptEnumView::Action { actions, .. } = &receipt.receipt.receipt.receipt else {
+            return;
+        };
+        if !actions
+            .iter()
+            .any(|action| matches!(action, ActionView::DeployContract { .. }))
+        {
+            return;
+        }
+        // Bridge factories redeploy token contracts when they're upgraded
+        if self.storage.is_already_indexed(token_id).await {
+            return;
+        }
+
+        let Some(origin) = factory
+            .origin_from_account_id(token_id)
+            .or_else(|| factory.origin_from_deploy_args(tx))
+        else {
+            log::warn!("Couldn't find origin of bridged token {token_id}");
+            return;
+        };
+        log::info!(
+            "Found bridged token: {token_id} from {} {:?}",
+            origin.chain,
+            origin.address
+        );
+        let context = EventContext::new(receipt, tx, block);
+        handler
+            .handle_bridged_token_created(
+                BridgedTokenCreatedEvent {
+                    token_id: token_id.clone(),
+                    bridge: factory.bridge,
+                    factory_id: factory.account_id.clone(),
+                    origin_chain: origin.chain,
+                    origin_address: origin.address,
+                },
+                context,
+            )
+            .await;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Bridge {
+    /// `{erc20 address without 0x}.factory.bridge.near`
+    Rainbow,
+    /// `{chain}-{address}.{factory}`
+    Omni,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BridgeFactory {
+    pub bridge: Bridge,
+    pub account_id: AccountId,
+}
+
+impl BridgeFactory {
+    /// Testnet factories change too often to have defaults, use
+    /// [`NewTokenIndexer::with_bridge_factories`](crate::NewTokenIndexer::with_bridge_factories)
+    pub fn defaults(testnet: bool) -> Vec<Self> {
+        if testnet {
+            Vec::new()
+        } else {
+            vec![
+                BridgeFactory {
+                    bridge: Bridge::Rainbow,
+                    account_id: "factory.bridge.near".parse().unwrap(),
+                },
+                BridgeFactory {
+                    bridge: Bridge::Omni,
+                    account_id: "omdep.near".parse().unwrap(),
+                },
+            ]
+        }
+    }
+
+    fn is_factory_of(&self, token_id: &AccountId) -> bool {
+        self.token_prefix(token_id).is_some()
+    }
+
+    fn token_prefix<'a>(&self, token_id: &'a AccountId) -> Option<&'a str> {
+        token_id
+            .as_str()
+            .strip_suffix(self.account_id.as_str())?
+            .strip_suffix('.')
+            .filter(|prefix| !prefix.contains('.'))
+    }
+
+    pub fn origin_from_account_id(&self, token_id: &AccountId) -> Option<BridgedTokenOrigin> {
+        let prefix = self.token_prefix(token_id)?;
+        match self.bridge {
+            Bridge::Rainbow => Some(BridgedTokenOrigin {
+                chain: "ethereum".to_string(),
+                address: Some(evm_address(prefix)?),
+            }),
+            Bridge::Omni => {
+                let (chain, address) = prefix.split_once('-')?;
+                let (chain, evm) = match chain {
+                    "eth" => ("ethereum", true),
+                    "arb" => ("arbitrum", true),
+                    "base" => ("base", true),
+                    "sol" => ("solana", false),
+                    "btc" => ("bitcoin", false),
+                    other => (other, false),
+                };
+                Some(BridgedTokenOrigin {
+                    chain: chain.to_string(),
+                    // Non-EVM addresses are hashed, because they can't be a part of an account id
+                    address: evm
+                        .then(|| evm_address(address.trim_start_matches("0x")))
+                        .flatten(),
+                })
+            }
+        }
+    }
+
+    /// Rainbow bridge tokens are created with `deploy_bridge_token({"address": "..."})`
+    pub fn origin_from_deploy_args(
+        &self,
+        tx: &IncompleteTransaction,
+    ) -> Option<BridgedTokenOrigin> {
+        if self.bridge != Bridge::Rainbow {
+            return None;
+        }
+        tx.receipts
+            .values()
+            .flatten()
+            .filter(|receipt| receipt.receipt.receipt.receiver_id == self.account_id)
+            .find_map(|receipt| {
+                let ReceiptEnumView::Action { actions, .. } = &receipt.receipt.receipt.receipt
+                else {
+                    return None;
+                };
+                actions.iter().find_map(|action| match action {
+                    ActionView::FunctionCall {
+                        method_name, args, ..
+                    } if method_name == "deploy_bridge_token" => {
+                        serde_json::from_slice::<DeployBridgeTokenArgs>(args).ok()
+                    }
+                    _ => None,
+                })
+            })
+            .and_then(|args| {
+                Some(BridgedTokenOrigin {
+                    chain: "ethereum".to_string(),
+                    address: Some(evm_address(args.address.trim_start_matches("0x"))?),
+                })
+            })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DeployBridgeTokenArgs {
+    address: String,
+}
+
+fn evm_address(hex: &str) -> Option<String> {
+    (hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| format!("0x{}", hex.to_ascii_lowercase()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BridgedTokenOrigin {
+    pub chain: String,
+    /// Address of the token on the origin chain, if it can be derived from NEAR data
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BridgedTokenCreatedEvent {
+    pub token_id: AccountId,
+    pub bridge: Bridge,
+    pub factory_id: AccountId,
+    pub origin_chain: String,
+    pub origin_address: Option<String>,
+}
@@ -0,0 +1,441 @@
+//! Payloads that handlers send for each event, for events that don't have a definition
+//! in `intear_events` yet or have more information than it.
+
+use inindexer::near_indexer_primitives::types::{AccountId, Balance, BlockHeight};
+use inindexer::near_utils::dec_format;
+use intear_events::events::newcontract::{
+    meme_cooking_meme::NewMemeCookingMemeEvent, meme_cooking_token::NewMemeCookingTokenEvent,
+    nep141::NewContractNep141Event,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::bridge::{self, Bridge};
+use crate::holders;
+use crate::initial_distribution::{self, MintRecipient};
+use crate::launch_timeline::MemeCookingOrigin;
+use crate::launchpad;
+use crate::meme_cooking::{self, MemeCookingCreateMemeEvent, MemeCookingCreateTokenEvent};
+use crate::meme_cooking_reference::MemeCookingReference;
+use crate::metadata_updates;
+use crate::metadata_updates::MetadataChange;
+use crate::metadata_validation::MetadataIssue;
+use crate::new_nep141::FtMetadata;
+use crate::new_nep141::NewNep141Event;
+use crate::ref_finance::{self, PoolTokenAmount, RefPoolKind};
+use crate::risk::RiskAssessment;
+use crate::supply;
+use crate::{BlockContext, EventContext};
+
+/// Ids of all events that are sent by handlers
+pub const EVENT_IDS: &[&str] = &[
+    NewContractNep141Event::ID,
+    NewMemeCookingMemeEvent::ID,
+    NewMemeCookingTokenEvent::ID,
+    MemeReachedSoftCapEvent::ID,
+    MemeReachedHardCapEvent::ID,
+    MemeExpiredWithoutTokenEvent::ID,
+    LaunchpadTokenCreatedEvent::ID,
+    BridgedTokenCreatedEvent::ID,
+    TokenFirstPoolEvent::ID,
+    TokenInitialDistributionEvent::ID,
+    TokenSupplyChangedEvent::ID,
+    TokenHoldersSnapshotEvent::ID,
+    TokenMetadataUpdatedEvent::ID,
+];
+
+/// Same as `intear_events`' `NewContractNep141EventData`, with additional information
+/// about the token. Consumers that only know the original fields can still read it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewContractNep141EventData {
+    pub account_id: AccountId,
+
+    #[serde(flatten)]
+    pub context: EventContext,
+
+    pub meme_cooking: Option<MemeCookingOrigin>,
+    pub metadata: Option<FtMetadata>,
+    pub owner_id: Option<AccountId>,
+    #[serde(with = "dec_format")]
+    pub total_supply: Option<Balance>,
+    pub risk: Option<RiskAssessment>,
+    pub metadata_issues: Vec<MetadataIssue>,
+}
+
+impl NewContractNep141EventData {
+    pub fn new(event: NewNep141Event, context: EventContext) -> Self {
+        Self {
+            account_id: event.account_id,
+
+            context,
+
+            meme_cooking: event.meme_cooking,
+            metadata: event.metadata,
+            owner_id: event.owner_id,
+            total_supply: event.total_supply,
+            risk: event.risk,
+            metadata_issues: event.metadata_issues,
+        }
+    }
+}
+
+/// Same as `intear_events`' `NewMemeCookingTokenEventData`, with the accounts that created the token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewMemeCookingTokenEventData {
+    #[serde(flatten)]
+    pub context: EventContext,
+
+    pub meme_id: u64,
+    pub token_id: AccountId,
+    #[serde(with = "dec_format")]
+    pub total_supply: Balance,
+    pub pool_id: u64,
+}
+
+impl NewMemeCookingTokenEventData {
+    pub fn new(event: MemeCookingCreateTokenEvent, context: EventContext) -> Self {
+        Self {
+            context,
+
+            meme_id: event.meme_id,
+            token_id: event.token_id,
+            total_supply: event.total_supply,
+            pool_id: event.pool_id,
+        }
+    }
+}
+
+/// Same as `intear_events`' `NewMemeCookingMemeEventData`, with the downloaded `reference`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewMemeCookingMemeEventData {
+    #[serde(flatten)]
+    pub context: EventContext,
+
+    pub meme_id: u64,
+    pub owner: AccountId,
+    #[serde(with = "dec_format")]
+    pub end_timestamp_ms: u64,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u32,
+    #[serde(with = "dec_format")]
+    pub total_supply: Balance,
+    pub reference: String,
+    pub reference_hash: String,
+    pub deposit_token_id: AccountId,
+    #[serde(with = "dec_format")]
+    pub soft_cap: Balance,
+    #[serde(with = "dec_format")]
+    pub hard_cap: Option<Balance>,
+
+    pub reference_document: Option<MemeCookingReference>,
+}
+
+impl NewMemeCookingMemeEventData {
+    pub fn new(event: MemeCookingCreateMemeEvent, context: EventContext) -> Self {
+        Self {
+            context,
+
+            meme_id: event.meme_id,
+            owner: event.owner,
+            end_timestamp_ms: event.end_timestamp_ms,
+            name: event.name,
+            symbol: event.symbol,
+            decimals: event.decimals,
+            total_supply: event.total_supply,
+            reference: event.reference,
+            reference_hash: event.reference_hash,
+            deposit_token_id: event.deposit_token_id,
+            soft_cap: event.soft_cap,
+            hard_cap: event.hard_cap,
+
+            reference_document: event.reference_document,
+        }
+    }
+}
+
+pub struct MemeReachedSoftCapEvent;
+
+impl MemeReachedSoftCapEvent {
+    pub const ID: &'static str = "meme_reached_soft_cap";
+}
+
+pub struct MemeReachedHardCapEvent;
+
+impl MemeReachedHardCapEvent {
+    pub const ID: &'static str = "meme_reached_hard_cap";
+}
+
+pub struct MemeExpiredWithoutTokenEvent;
+
+impl MemeExpiredWithoutTokenEvent {
+    pub const ID: &'static str = "meme_expired_without_token";
+}
+
+/// Shared by [`MemeReachedSoftCapEvent`], [`MemeReachedHardCapEvent`] and [`MemeExpiredWithoutTokenEvent`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemeCookingCampaignOutcomeEventData {
+    #[serde(flatten)]
+    pub context: BlockContext,
+
+    pub meme_id: u64,
+    #[serde(with = "dec_format")]
+    pub end_timestamp_ms: u64,
+    #[serde(with = "dec_format")]
+    pub soft_cap: Balance,
+    #[serde(with = "dec_format")]
+    pub hard_cap: Option<Balance>,
+    #[serde(with = "dec_format")]
+    pub total_deposited: Balance,
+    pub participants: usize,
+}
+
+impl MemeCookingCampaignOutcomeEventData {
+    pub fn new(
+        event: meme_cooking::MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) -> Self {
+        Self {
+            context,
+
+            meme_id: event.meme_id,
+            end_timestamp_ms: event.end_timestamp_ms,
+            soft_cap: event.soft_cap,
+            hard_cap: event.hard_cap,
+            total_deposited: event.total_deposited,
+            participants: event.participants,
+        }
+    }
+}
+
+pub struct LaunchpadTokenCreatedEvent;
+
+impl LaunchpadTokenCreatedEvent {
+    pub const ID: &'static str = "launchpad_token_created";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LaunchpadTokenCreatedEventData {
+    #[serde(flatten)]
+    pub context: EventContext,
+
+    pub launchpad: String,
+    pub contract_id: AccountId,
+    pub token_id: AccountId,
+    pub owner: Option<AccountId>,
+    #[serde(with = "dec_format")]
+    pub total_supply: Option<Balance>,
+}
+
+impl LaunchpadTokenCreatedEventData {
+    pub fn new(event: launchpad::LaunchpadTokenCreatedEvent, context: EventContext) -> Self {
+        Self {
+            context,
+
+            launchpad: event.launchpad,
+            contract_id: event.contract_id,
+            token_id: event.token_id,
+            owner: event.owner,
+            total_supply: event.total_supply,
+        }
+    }
+}
+
+pub struct BridgedTokenCreatedEvent;
+
+impl BridgedTokenCreatedEvent {
+    pub const ID: &'static str = "bridged_token_created";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BridgedTokenCreatedEventData {
+    /// `factory_id` is always the bridge factory
+    #[serde(flatten)]
+    pub context: EventContext,
+
+    pub token_id: AccountId,
+    pub bridge: Bridge,
+    pub origin_chain: String,
+    pub origin_address: Option<String>,
+}
+
+impl BridgedTokenCreatedEventData {
+    pub fn new(event: bridge::BridgedTokenCreatedEvent, context: EventContext) -> Self {
+        Self {
+            context: EventContext {
+                factory_id: Some(event.factory_id),
+                ..context
+            },
+
+            token_id: event.token_id,
+            bridge: event.bridge,
+            origin_chain: event.origin_chain,
+            origin_address: event.origin_address,
+        }
+    }
+}
+
+pub struct TokenFirstPoolEvent;
+
+impl TokenFirstPoolEvent {
+    pub const ID: &'static str = "token_first_pool";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenFirstPoolEventData {
+    #[serde(flatten)]
+    pub context: EventContext,
+
+    pub token_id: AccountId,
+    pub pool_id: u64,
+    pub pool_kind: RefPoolKind,
+    pub counter_tokens: Vec<AccountId>,
+    pub initial_liquidity: Vec<PoolTokenAmount>,
+}
+
+impl TokenFirstPoolEventData {
+    pub fn new(event: ref_finance::TokenFirstPoolEvent, context: EventContext) -> Self {
+        Self {
+            context,
+
+            token_id: event.token_id,
+            pool_id: event.pool_id,
+            pool_kind: event.pool_kind,
+            counter_tokens: event.counter_tokens,
+            initial_liquidity: event.initial_liquidity,
+        }
+    }
+}
+
+pub struct TokenInitialDistributionEvent;
+
+impl TokenInitialDistributionEvent {
+    pub const ID: &'static str = "token_initial_distribution";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenInitialDistributionEventData {
+    #[serde(flatten)]
+    pub context: EventContext,
+
+    pub token_id: AccountId,
+    #[serde(with = "dec_format")]
+    pub total_minted: Balance,
+    pub recipients: usize,
+    pub top_recipients: Vec<MintRecipient>,
+}
+
+impl TokenInitialDistributionEventData {
+    pub fn new(
+        event: initial_distribution::TokenInitialDistributionEvent,
+        context: EventContext,
+    ) -> Self {
+        Self {
+            context,
+
+            token_id: event.token_id,
+            total_minted: event.total_minted,
+            recipients: event.recipients,
+            top_recipients: event.top_recipients,
+        }
+    }
+}
+
+pub struct TokenSupplyChangedEvent;
+
+impl TokenSupplyChangedEvent {
+    pub const ID: &'static str = "token_supply_changed";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenSupplyChangedEventData {
+    #[serde(flatten)]
+    pub context: BlockContext,
+
+    pub token_id: AccountId,
+    #[serde(with = "dec_format")]
+    pub minted: Balance,
+    #[serde(with = "dec_format")]
+    pub burned: Balance,
+    #[serde(with = "dec_format")]
+    pub total_supply: Option<Balance>,
+    #[serde(with = "dec_format")]
+    pub rpc_total_supply: Option<Balance>,
+    pub diverged: bool,
+}
+
+impl TokenSupplyChangedEventData {
+    pub fn new(event: supply::TokenSupplyChangedEvent, context: BlockContext) -> Self {
+        Self {
+            context,
+
+            token_id: event.token_id,
+            minted: event.minted,
+            burned: event.burned,
+            total_supply: event.total_supply,
+            rpc_total_supply: event.rpc_total_supply,
+            diverged: event.diverged,
+        }
+    }
+}
+
+pub struct TokenHoldersSnapshotEvent;
+
+impl TokenHoldersSnapshotEvent {
+    pub const ID: &'static str = "token_holders_snapshot";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenHoldersSnapshotEventData {
+    #[serde(flatten)]
+    pub context: BlockContext,
+
+    pub token_id: AccountId,
+    pub holders: usize,
+    pub blocks_since_launch: BlockHeight,
+    pub is_final: bool,
+}
+
+impl TokenHoldersSnapshotEventData {
+    pub fn new(event: holders::TokenHoldersSnapshotEvent, context: BlockContext) -> Self {
+        Self {
+            context,
+
+            token_id: event.token_id,
+            holders: event.holders,
+            blocks_since_launch: event.blocks_since_launch,
+            is_final: event.is_final,
+        }
+    }
+}
+
+pub struct TokenMetadataUpdatedEvent;
+
+impl TokenMetadataUpdatedEvent {
+    pub const ID: &'static str = "token_metadata_updated";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenMetadataUpdatedEventData {
+    #[serde(flatten)]
+    pub context: BlockContext,
+
+    pub token_id: AccountId,
+    pub changes: Vec<MetadataChange>,
+    pub previous_hash: String,
+    pub hash: String,
+    pub metadata: FtMetadata,
+}
+
+impl TokenMetadataUpdatedEventData {
+    pub fn new(event: metadata_updates::TokenMetadataUpdatedEvent, context: BlockContext) -> Self {
+        Self {
+            context,
+
+            token_id: event.token_id,
+            changes: event.changes,
+            previous_hash: event.previous_hash,
+            hash: event.hash,
+            metadata: event.metadata,
+        }
+    }
+}
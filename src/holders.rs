@@ -0,0 +1,182 @@
+use std::{collections::HashMap, sync::Arc};
+
+use inindexer::{
+    near_indexer_primitives::{
+        types::{AccountId, Balance, BlockHeight},
+        StreamerMessage,
+    },
+    near_utils::{EventLogData, FtBurnLog, FtMintLog, FtTransferLog},
+    TransactionReceipt,
+};
+use tokio::sync::mpsc;
+
+use crate::{initial_distribution::FirstMint, BlockContext, ContractEventHandler};
+
+pub struct HoldersTrackerOptions {
+    /// How often `token_holders_snapshot` events are emitted for each token
+    pub snapshot_interval_blocks: BlockHeight,
+    /// How long a token is tracked after its first mint
+    pub lifetime_blocks: BlockHeight,
+}
+
+impl Default for HoldersTrackerOptions {
+    fn default() -> Self {
+        Self {
+            snapshot_interval_blocks: 100,
+            lifetime_blocks: 10_000,
+        }
+    }
+}
+
+/// Counts accounts with a positive balance of newly launched tokens. Tracking starts with
+/// the first mint reported by [`InitialDistributions`](crate::initial_distribution::InitialDistributions),
+/// since balances are only known for tokens that were followed from the beginning.
+pub struct HoldersTracker {
+    first_mints: mpsc::UnboundedReceiver<FirstMint>,
+    options: HoldersTrackerOptions,
+    tokens: HashMap<AccountId, TrackedHolders>,
+}
+
+impl HoldersTracker {
+    pub fn new(
+        first_mints: mpsc::UnboundedReceiver<FirstMint>,
+        options: HoldersTrackerOptions,
+    ) -> Self {
+        Self {
+            first_mints,
+            options,
+            tokens: HashMap::new(),
+        }
+    }
+
+    fn start_new_tokens(&mut self) {
+        while let Ok(mint) = self.first_mints.try_recv() {
+            self.tokens.insert(
+                mint.token_id,
+                TrackedHolders {
+                    started_at: mint.block_height,
+                    last_snapshot: mint.block_height,
+                    balances: mint.balances,
+                },
+            );
+        }
+    }
+
+    /// Must be called before the receipt is passed to `InitialDistributions`, so that
+    /// the first mint is not counted twice
+    pub fn track_holders(&mut self, receipt: &TransactionReceipt) {
+        self.start_new_tokens();
+        if let Some(holders) = self.tokens.get_mut(&receipt.receipt.receipt.receiver_id) {
+            for log in receipt.receipt.execution_outcome.outcome.logs.iter() {
+                holders.apply_log(log);
+            }
+        }
+    }
+
+    pub async fn process_block_end<T: ContractEventHandler>(
+        &mut self,
+        block: &StreamerMessage,
+        handler: Arc<T>,
+    ) {
+        self.start_new_tokens();
+        let context = BlockContext {
+            block_height: block.block.header.height,
+            block_timestamp_nanosec: block.block.header.timestamp_nanosec as u128,
+        };
+        let mut finished = Vec::new();
+        for (token_id, holders) in self.tokens.iter_mut() {
+            let is_final =
+                context.block_height >= holders.started_at + self.options.lifetime_blocks;
+            if !is_final
+                && context.block_height
+                    < holders.last_snapshot + self.options.snapshot_interval_blocks
+            {
+                continue;
+            }
+            holders.last_snapshot = context.block_height;
+            handler
+                .handle_token_holders_snapshot(
+                    TokenHoldersSnapshotEvent {
+                        token_id: token_id.clone(),
+                        holders: holders.count(),
+                        blocks_since_launch: context.block_height - holders.started_at,
+                        is_final,
+                    },
+                    context.clone(),
+                )
+                .await;
+            if is_final {
+                finished.push(token_id.clone());
+            }
+        }
+        for token_id in finished {
+            self.tokens.remove(&token_id);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackedHolders {
+    pub started_at: BlockHeight,
+    pub last_snapshot: BlockHeight,
+    pub balances: HashMap<AccountId, Balance>,
+}
+
+impl TrackedHolders {
+    pub fn apply_log(&mut self, log: &str) {
+        // Mint and burn events have the same format, so the event name has to be checked
+        if let Ok(event) = EventLogData::<FtMintLog>::deserialize(log) {
+            if event.standard == "nep141" && event.event == "ft_mint" {
+                for mint in event.data {
+                    self.add(mint.owner_id, mint.amount);
+                }
+            }
+        }
+        if let Ok(event) = EventLogData::<FtBurnLog>::deserialize(log) {
+            if event.standard == "nep141" && event.event == "ft_burn" {
+                for burn in event.data {
+                    self.subtract(&burn.owner_id, burn.amount);
+                }
+            }
+        }
+        if let Ok(event) = EventLogData::<FtTransferLog>::deserialize(log) {
+            if event.standard == "nep141" && event.event == "ft_transfer" {
+                for transfer in event.data {
+                    self.subtract(&transfer.old_owner_id, transfer.amount);
+                    self.add(transfer.new_owner_id, transfer.amount);
+                }
+            }
+        }
+    }
+
+    fn add(&mut self, account_id: AccountId, amount: Balance) {
+        let balance = self.balances.entry(account_id).or_default();
+        *balance = balance.saturating_add(amount);
+    }
+
+    fn subtract(&mut self, account_id: &AccountId, amount: Balance) {
+        if let Some(balance) = self.balances.get_mut(account_id) {
+            *balance = balance.saturating_sub(amount);
+            if *balance == 0 {
+                self.balances.remove(account_id);
+            }
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.balances
+            .values()
+            .filter(|balance| **balance > 0)
+            .count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenHoldersSnapshotEvent {
+    pub token_id: AccountId,
+    /// Number of accounts with a positive balance
+    pub holders: usize,
+    pub blocks_since_launch: BlockHeight,
+    /// This is the last snapshot of this token
+    pub is_final: bool,
+}
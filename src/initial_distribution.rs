@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use inindexer::{
+    near_indexer_primitives::{
+        types::{AccountId, Balance, BlockHeight},
+        views::{ActionView, ReceiptEnumView},
+        StreamerMessage,
+    },
+    near_utils::{dec_format, EventLogData, FtMintLog},
+    IncompleteTransaction, TransactionReceipt,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::{ContractEventHandler, EventContext};
+
+const TOP_RECIPIENTS: usize = 10;
+/// How long to wait for the first `ft_mint` of a token after it was detected
+const AWAIT_MINT_BLOCKS: BlockHeight = 1000;
+/// How long to keep mints of a deployment that hasn't been detected as NEP-141 yet (RPC can lag behind)
+const UNCLAIMED_MINT_BLOCKS: BlockHeight = 100;
+
+/// Reports who received the supply of newly deployed tokens. The first `ft_mint` usually
+/// happens in the same receipt as the deployment, but NEP-141 detection can be delayed
+/// by RPC, so mints can arrive either before or after the token is detected.
+#[derive(Default)]
+pub struct InitialDistributions {
+    awaiting: Mutex<HashMap<AccountId, BlockHeight>>,
+    unclaimed: Mutex<HashMap<AccountId, (FirstMint, EventContext)>>,
+    subscribers: Mutex<Vec<mpsc::UnboundedSender<FirstMint>>>,
+}
+
+impl InitialDistributions {
+    /// Called when a newly deployed token is detected. Tokens that are detected by
+    /// their transfers are not expected, since their first mint has already happened.
+    pub async fn expect_mint<T: ContractEventHandler>(
+        &self,
+        token_id: &AccountId,
+        block_height: BlockHeight,
+        handler: &T,
+    ) {
+        let unclaimed = self.unclaimed.lock().await.remove(token_id);
+        if let Some((mint, context)) = unclaimed {
+            self.emit(mint, context, handler).await;
+        } else {
+            self.awaiting
+                .lock()
+                .await
+                .insert(token_id.clone(), block_height);
+        }
+    }
+
+    pub async fn detect_mints<T: ContractEventHandler>(
+        &self,
+        receipt: &TransactionReceipt,
+        tx: &IncompleteTransaction,
+        block: &StreamerMessage,
+        handler: &T,
+    ) {
+        let token_id = &receipt.receipt.receipt.receiver_id;
+        let balances = mints_from_logs(&receipt.receipt.execution_outcome.outcome.logs);
+        if balances.is_empty() {
+            return;
+        }
+        let mint = FirstMint {
+            token_id: token_id.clone(),
+            balances,
+            block_height: block.block.header.height,
+        };
+        let context = EventContext::new(receipt, tx, block);
+        let awaited = self.awaiting.lock().await.remove(token_id).is_some();
+        if awaited {
+            self.emit(mint, context, handler).await;
+            return;
+        }
+        let ReceiptEnumView::Action { actions, .. } = &receipt.receipt.receipt.receipt else {
+            return;
+        };
+        if actions
+            .iter()
+            .any(|action| matches!(action, ActionView::DeployContract { .. }))
+        {
+            self.unclaimed
+                .lock()
+                .await
+                .entry(token_id.clone())
+                .or_insert((mint, context));
+        }
+    }
+
+    /// Receives every first mint that is reported, with all recipients
+    pub async fn subscribe(&self) -> mpsc::UnboundedReceiver<FirstMint> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.subscribers.lock().await.push(sender);
+        receiver
+    }
+
+    async fn emit<T: ContractEventHandler>(
+        &self,
+        mint: FirstMint,
+        context: EventContext,
+        handler: &T,
+    ) {
+        self.subscribers
+            .lock()
+            .await
+            .retain(|subscriber| subscriber.send(mint.clone()).is_ok());
+        handler
+            .handle_token_initial_distribution(
+                TokenInitialDistributionEvent::new(mint.token_id, mint.balances),
+                context,
+            )
+            .await;
+    }
+
+    pub async fn prune(&self, block_height: BlockHeight) {
+        self.awaiting
+            .lock()
+            .await
+            .retain(|_, detected_at| *detected_at + AWAIT_MINT_BLOCKS > block_height);
+        self.unclaimed
+            .lock()
+            .await
+            .retain(|_, (mint, _)| mint.block_height + UNCLAIMED_MINT_BLOCKS > block_height);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenInitialDistributionEvent {
+    pub token_id: AccountId,
+    pub total_minted: Balance,
+    pub recipients: usize,
+    /// Largest recipients, sorted by amount
+    pub top_recipients: Vec<MintRecipient>,
+}
+
+impl TokenInitialDistributionEvent {
+    pub fn new(token_id: AccountId, balances: HashMap<AccountId, Balance>) -> Self {
+        let mut recipients = balances
+            .into_iter()
+            .map(|(account_id, amount)| MintRecipient { account_id, amount })
+            .collect::<Vec<_>>();
+        recipients.sort_by(|a, b| {
+            b.amount
+                .cmp(&a.amount)
+                .then_with(|| a.account_id.cmp(&b.account_id))
+        });
+        Self {
+            token_id,
+            total_minted: recipients.iter().fold(0, |total: Balance, recipient| {
+                total.saturating_add(recipient.amount)
+            }),
+            recipients: recipients.len(),
+            top_recipients: recipients.into_iter().take(TOP_RECIPIENTS).collect(),
+        }
+    }
+}
+
+/// Aggregates all `ft_mint` events in the logs of a receipt
+pub fn mints_from_logs(logs: &[String]) -> HashMap<AccountId, Balance> {
+    let mut amounts = HashMap::<AccountId, Balance>::new();
+    for log in logs {
+        let Ok(event) = EventLogData::<FtMintLog>::deserialize(log) else {
+            continue;
+        };
+        // Burn events have the same format
+        if event.standard != "nep141" || event.event != "ft_mint" {
+            continue;
+        }
+        for mint in event.data {
+            let amount = amounts.entry(mint.owner_id).or_default();
+            *amount = amount.saturating_add(mint.amount);
+        }
+    }
+    amounts
+}
+
+/// The first minting receipt of a new token
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirstMint {
+    pub token_id: AccountId,
+    pub balances: HashMap<AccountId, Balance>,
+    pub block_height: BlockHeight,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MintRecipient {
+    pub account_id: AccountId,
+    #[serde(with = "dec_format")]
+    pub amount: Balance,
+}
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use async_trait::async_trait;
+use inindexer::near_indexer_primitives::types::{AccountId, BlockHeight};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::launch_timeline::{LaunchTimeline, LaunchTimelineStorage};
+use crate::meme_cooking::{MemeCookingCampaign, MemeCookingCampaignStorage};
+use crate::metadata_updates::{StoredMetadata, TokenMetadataStorage};
+use crate::ref_finance::{RefPool, RefPoolStorage, RefPools};
+use crate::supply::{TokenSupply, TokenSupplyStorage};
+use crate::webhook_handler::{UndeliveredWebhookStorage, WebhookDelivery};
+
+/// State that is small enough to be kept in memory and rewritten as a whole
+/// JSON document on every change, or on [`flush`](Self::flush) for state that
+/// changes many times per block. Without a path, nothing is persisted.
+pub struct JsonFileStorage<T> {
+    path: Option<PathBuf>,
+    data: RwLock<T>,
+    /// Set when `data` changed since the last flush
+    dirty: AtomicBool,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> JsonFileStorage<T> {
+    pub async fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let data = match tokio::fs::read(&path).await {
+            Ok(contents) => match serde_json::from_slice(&contents) {
+                Ok(data) => data,
+                Err(err) => {
+                    // Kept aside, since the next change overwrites the file
+                    let invalid_path = path.with_extension("invalid");
+                    log::error!(
+                        "Invalid {}, starting empty and moving it to {}: {err}",
+                        path.display(),
+                        invalid_path.display()
+                    );
+                    if let Err(err) = tokio::fs::rename(&path, &invalid_path).await {
+                        log::error!("Failed to move {}: {err}", path.display());
+                    }
+                    T::default()
+                }
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => T::default(),
+            Err(err) => {
+                log::error!("Failed to read {}, starting empty: {err}", path.display());
+                T::default()
+            }
+        };
+        Self {
+            path: Some(path),
+            data: RwLock::new(data),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    async fn persist(&self, data: &T) {
+        if let Some(path) = &self.path {
+            // Write to a temporary file first so that a crash doesn't leave a truncated file
+            let temp_path = path.with_extension("tmp");
+            let contents = serde_json::to_vec(data).unwrap();
+            let result = async {
+                tokio::fs::write(&temp_path, contents).await?;
+                tokio::fs::rename(&temp_path, path).await
+            }
+            .await;
+            if let Err(err) = result {
+                log::error!("Failed to write {}: {err}", path.display());
+            }
+        }
+    }
+
+    /// Persists changes that were only made in memory
+    pub async fn flush(&self) {
+        if self.dirty.swap(false, Ordering::Relaxed) {
+            let data = self.data.read().await;
+            self.persist(&data).await;
+        }
+    }
+}
+
+impl<T: Default> Default for JsonFileStorage<T> {
+    fn default() -> Self {
+        Self {
+            path: None,
+            data: RwLock::new(T::default()),
+            dirty: AtomicBool::new(false),
+        }
+    }
+}
+
+#[async_trait]
+impl MemeCookingCampaignStorage for JsonFileStorage<HashMap<u64, MemeCookingCampaign>> {
+    async fn get_campaign(&self, meme_id: u64) -> Option<MemeCookingCampaign> {
+        self.data.read().await.get(&meme_id).cloned()
+    }
+
+    async fn save_campaign(&self, campaign: MemeCookingCampaign) {
+        let mut data = self.data.write().await;
+        data.insert(campaign.meme_id, campaign);
+        self.persist(&data).await;
+    }
+
+    async fn save_campaigns(&self, campaigns: Vec<MemeCookingCampaign>) {
+        let mut data = self.data.write().await;
+        for campaign in campaigns {
+            data.insert(campaign.meme_id, campaign);
+        }
+        self.persist(&data).await;
+    }
+
+    async fn remove_campaign(&self, meme_id: u64) {
+        let mut data = self.data.write().await;
+        if data.remove(&meme_id).is_some() {
+            self.persist(&data).await;
+        }
+    }
+
+    async fn campaigns_ended_by(&self, timestamp_ms: u64) -> Vec<MemeCookingCampaign> {
+        let mut campaigns = self
+            .data
+            .read()
+            .await
+            .values()
+            .filter(|campaign| campaign.end_timestamp_ms <= timestamp_ms)
+            .cloned()
+            .collect::<Vec<_>>();
+        campaigns.sort_by_key(|campaign| campaign.meme_id);
+        campaigns
+    }
+}
+
+#[async_trait]
+impl LaunchTimelineStorage for JsonFileStorage<HashMap<u64, LaunchTimeline>> {
+    async fn get_by_meme(&self, meme_id: u64) -> Option<LaunchTimeline> {
+        self.data.read().await.get(&meme_id).cloned()
+    }
+
+    async fn get_by_token(&self, token_id: &AccountId) -> Option<LaunchTimeline> {
+        self.data
+            .read()
+            .await
+            .values()
+            .find(|timeline| timeline.token_id.as_ref() == Some(token_id))
+            .cloned()
+    }
+
+    async fn save(&self, timeline: LaunchTimeline) {
+        let mut data = self.data.write().await;
+        data.insert(timeline.meme_id, timeline);
+        self.persist(&data).await;
+    }
+}
+
+#[async_trait]
+impl RefPoolStorage for JsonFileStorage<RefPools> {
+    async fn has_pool(&self, token_id: &AccountId) -> bool {
+        self.data.read().await.tokens_with_pool.contains(token_id)
+    }
+
+    async fn mark_has_pool(&self, token_id: AccountId) {
+        let mut data = self.data.write().await;
+        if data.tokens_with_pool.insert(token_id) {
+            self.persist(&data).await;
+        }
+    }
+
+    async fn save_pending_pool(&self, pool: RefPool) {
+        let mut data = self.data.write().await;
+        data.pending.insert(pool.pool_id, pool);
+        self.persist(&data).await;
+    }
+
+    async fn get_pending_pool(&self, pool_id: u64) -> Option<RefPool> {
+        self.data.read().await.pending.get(&pool_id).cloned()
+    }
+
+    async fn remove_pending_pool(&self, pool_id: u64) {
+        let mut data = self.data.write().await;
+        if data.pending.remove(&pool_id).is_some() {
+            self.persist(&data).await;
+        }
+    }
+
+    async fn remove_pending_pools_before(&self, block_height: BlockHeight) {
+        let mut data = self.data.write().await;
+        let before = data.pending.len();
+        data.pending
+            .retain(|_, pool| pool.created_at_block >= block_height);
+        if data.pending.len() != before {
+            self.persist(&data).await;
+        }
+    }
+}
+
+#[async_trait]
+impl TokenSupplyStorage for JsonFileStorage<HashMap<AccountId, TokenSupply>> {
+    async fn get_supply(&self, token_id: &AccountId) -> Option<TokenSupply> {
+        self.data.read().await.get(token_id).cloned()
+    }
+
+    async fn save_supply(&self, token_id: AccountId, supply: TokenSupply) {
+        let mut data = self.data.write().await;
+        data.insert(token_id, supply);
+        self.persist(&data).await;
+    }
+
+    async fn save_supplies(&self, supplies: Vec<(AccountId, TokenSupply)>) {
+        if supplies.is_empty() {
+            return;
+        }
+        let mut data = self.data.write().await;
+        data.extend(supplies);
+        self.persist(&data).await;
+    }
+}
+
+#[async_trait]
+impl TokenMetadataStorage for JsonFileStorage<HashMap<AccountId, StoredMetadata>> {
+    async fn get_metadata(&self, token_id: &AccountId) -> Option<StoredMetadata> {
+        self.data.read().await.get(token_id).cloned()
+    }
+
+    async fn save_metadata(&self, token_id: AccountId, metadata: StoredMetadata) {
+        self.data.write().await.insert(token_id, metadata);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    async fn flush(&self) {
+        JsonFileStorage::flush(self).await
+    }
+}
+
+/// Undelivered webhooks, kept in memory and appended to a JSON Lines log of saved and
+/// removed deliveries, so each delivery costs two short appends instead of rewriting the
+/// whole file. The log is compacted when it's opened and when removed deliveries make up
+/// most of it.
+pub struct JsonlWebhookStorage {
+    path: PathBuf,
+    log: Mutex<WebhookLog>,
+}
+
+struct WebhookLog {
+    deliveries: Vec<WebhookDelivery>,
+    /// Lines in the file, to know when it's worth compacting
+    lines: usize,
+    file: File,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum WebhookLogEntry {
+    Save(WebhookDelivery),
+    Remove { id: String, endpoint: usize },
+}
+
+impl JsonlWebhookStorage {
+    pub async fn new(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let mut deliveries = Vec::new();
+        if let Ok(contents) = tokio::fs::read_to_string(&path).await {
+            for (index, line) in contents.lines().enumerate() {
+                match serde_json::from_str(line) {
+                    Ok(WebhookLogEntry::Save(delivery)) => deliveries.push(delivery),
+                    Ok(WebhookLogEntry::Remove { id, endpoint }) => {
+                        deliveries.retain(|saved| saved.id != id || saved.endpoint != endpoint)
+                    }
+                    // The last line can be cut off by a crash
+                    Err(err) => {
+                        log::warn!("Skipping line {} of {}: {err}", index + 1, path.display())
+                    }
+                }
+            }
+        }
+        let file = compact(&path, &deliveries).await?;
+        Ok(Self {
+            log: Mutex::new(WebhookLog {
+                lines: deliveries.len(),
+                deliveries,
+                file,
+            }),
+            path,
+        })
+    }
+
+    async fn append(&self, log: &mut WebhookLog, entry: &WebhookLogEntry) {
+        let mut line = serde_json::to_vec(entry).unwrap();
+        line.push(b'\n');
+        let result = async {
+            log.file.write_all(&line).await?;
+            log.file.flush().await
+        }
+        .await;
+        if let Err(err) = result {
+            log::error!("Failed to write to {}: {err}", self.path.display());
+        }
+        log.lines += 1;
+        if log.lines > 2 * log.deliveries.len() + 1000 {
+            match compact(&self.path, &log.deliveries).await {
+                Ok(file) => {
+                    log.file = file;
+                    log.lines = log.deliveries.len();
+                }
+                Err(err) => log::error!("Failed to compact {}: {err}", self.path.display()),
+            }
+        }
+    }
+}
+
+/// Rewrites the log with only the deliveries that are still saved, and opens it for appending
+async fn compact(path: &Path, deliveries: &[WebhookDelivery]) -> std::io::Result<File> {
+    let mut contents = Vec::new();
+    for delivery in deliveries {
+        serde_json::to_writer(&mut contents, &WebhookLogEntry::Save(delivery.clone())).unwrap();
+        contents.push(b'\n');
+    }
+    let temp_path = path.with_extension("tmp");
+    tokio::fs::write(&temp_path, contents).await?;
+    tokio::fs::rename(&temp_path, path).await?;
+    OpenOptions::new().append(true).open(path).await
+}
+
+#[async_trait]
+impl UndeliveredWebhookStorage for JsonlWebhookStorage {
+    async fn save_undelivered(&self, delivery: WebhookDelivery) {
+        let mut log = self.log.lock().await;
+        let entry = WebhookLogEntry::Save(delivery.clone());
+        log.deliveries.push(delivery);
+        self.append(&mut log, &entry).await;
+    }
+
+    async fn remove_undelivered(&self, delivery: &WebhookDelivery) {
+        let mut log = self.log.lock().await;
+        let before = log.deliveries.len();
+        log.deliveries
+            .retain(|saved| saved.id != delivery.id || saved.endpoint != delivery.endpoint);
+        if log.deliveries.len() != before {
+            let entry = WebhookLogEntry::Remove {
+                id: delivery.id.clone(),
+                endpoint: delivery.endpoint,
+            };
+            self.append(&mut log, &entry).await;
+        }
+    }
+
+    async fn undelivered(&self) -> Vec<WebhookDelivery> {
+        self.log.lock().await.deliveries.clone()
+    }
+}
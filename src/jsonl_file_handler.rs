@@ -0,0 +1,398 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use flate2::{write::GzEncoder, Compression};
+use inindexer::near_indexer_primitives::types::AccountId;
+use intear_events::events::newcontract::{
+    meme_cooking_meme::NewMemeCookingMemeEvent, meme_cooking_token::NewMemeCookingTokenEvent,
+    nep141::NewContractNep141Event,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::bridge;
+use crate::events::{
+    BridgedTokenCreatedEvent, BridgedTokenCreatedEventData, LaunchpadTokenCreatedEvent,
+    LaunchpadTokenCreatedEventData, MemeCookingCampaignOutcomeEventData,
+    MemeExpiredWithoutTokenEvent, MemeReachedHardCapEvent, MemeReachedSoftCapEvent,
+    NewContractNep141EventData, NewMemeCookingMemeEventData, NewMemeCookingTokenEventData,
+    TokenFirstPoolEvent, TokenFirstPoolEventData, TokenHoldersSnapshotEvent,
+    TokenHoldersSnapshotEventData, TokenInitialDistributionEvent,
+    TokenInitialDistributionEventData, TokenMetadataUpdatedEvent, TokenMetadataUpdatedEventData,
+    TokenSupplyChangedEvent, TokenSupplyChangedEventData,
+};
+use crate::holders;
+use crate::initial_distribution;
+use crate::launchpad;
+use crate::meme_cooking::{
+    MemeCookingCampaignOutcomeEvent, MemeCookingCreateMemeEvent, MemeCookingCreateTokenEvent,
+};
+use crate::metadata_updates;
+use crate::new_nep141::NewNep141Event;
+use crate::ref_finance;
+use crate::supply;
+use crate::{BlockContext, ContractEventHandler, EventContext};
+
+pub struct JsonlFileOptions {
+    /// The file is rotated when it would grow past this size. With gzip, the size is
+    /// counted before compression.
+    pub max_file_bytes: u64,
+    pub gzip: bool,
+}
+
+impl Default for JsonlFileOptions {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 100 * 1024 * 1024,
+            gzip: false,
+        }
+    }
+}
+
+/// One line of the file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonlEvent<C, T> {
+    pub event: String,
+    /// [`EventContext`], or [`BlockContext`] for events that are triggered by block time
+    pub context: C,
+    /// Same as the Redis payload
+    pub data: T,
+}
+
+/// Appends every event as a line of JSON to a file. Full files are renamed with a number
+/// before the extension (`events.jsonl` to `events.1.jsonl`, `events.2.jsonl`, and so on).
+/// A file left by a previous run is appended to, except with gzip, where it's rotated on
+/// start, since a gzip stream that was cut off by a killed process can't be continued.
+pub struct JsonlFileHandler {
+    writer: Arc<Mutex<JsonlWriter>>,
+    testnet: bool,
+}
+
+impl JsonlFileHandler {
+    /// With gzip, `.gz` is added to `path`
+    pub fn new(
+        path: impl Into<PathBuf>,
+        options: JsonlFileOptions,
+        testnet: bool,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            writer: Arc::new(Mutex::new(JsonlWriter::open(path.into(), options)?)),
+            testnet,
+        })
+    }
+
+    async fn write<C: Serialize>(&self, event_id: &str, context: C, data: impl Serialize) {
+        let mut line = serde_json::to_vec(&JsonlEvent {
+            event: event_id.to_string(),
+            context,
+            data,
+        })
+        .unwrap();
+        line.push(b'\n');
+        let writer = Arc::clone(&self.writer);
+        // File I/O blocks, so it's kept off the async runtime
+        tokio::task::spawn_blocking(move || writer.lock().unwrap().write_line(&line))
+            .await
+            .expect("JSONL writer panicked")
+            .unwrap_or_else(|err| panic!("Failed to write {event_id} event to JSONL file: {err}"));
+    }
+}
+
+enum Output {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+}
+
+struct JsonlWriter {
+    path: PathBuf,
+    options: JsonlFileOptions,
+    output: Output,
+    written: u64,
+}
+
+impl JsonlWriter {
+    fn open(path: PathBuf, options: JsonlFileOptions) -> io::Result<Self> {
+        let path = if options.gzip {
+            let mut path = path.into_os_string();
+            path.push(".gz");
+            PathBuf::from(path)
+        } else {
+            path
+        };
+        let (output, written) = if options.gzip {
+            if std::fs::metadata(&path).is_ok_and(|metadata| metadata.len() > 0) {
+                std::fs::rename(&path, next_rotated_path(&path)?)?;
+            }
+            (create_output(&path, true)?, 0)
+        } else {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .read(true)
+                .append(true)
+                .open(&path)?;
+            let mut written = file.metadata()?.len();
+            // The last line is incomplete if the previous run was killed while writing it
+            if written > 0 {
+                let mut last = [0];
+                file.seek(SeekFrom::End(-1))?;
+                file.read_exact(&mut last)?;
+                if last != *b"\n" {
+                    file.write_all(b"\n")?;
+                    written += 1;
+                }
+            }
+            (Output::Plain(file), written)
+        };
+        Ok(Self {
+            output,
+            path,
+            options,
+            written,
+        })
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.written > 0 && self.written + line.len() as u64 > self.options.max_file_bytes {
+            self.rotate()?;
+        }
+        match &mut self.output {
+            Output::Plain(file) => file.write_all(line)?,
+            Output::Gzip(encoder) => {
+                encoder.write_all(line)?;
+                // Keeps everything that was handled readable if the process is killed
+                encoder.flush()?;
+            }
+        }
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Output::Gzip(encoder) = &mut self.output {
+            encoder.try_finish()?;
+        }
+        std::fs::rename(&self.path, next_rotated_path(&self.path)?)?;
+        self.output = create_output(&self.path, self.options.gzip)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+fn create_output(path: &Path, gzip: bool) -> io::Result<Output> {
+    let file = File::create(path)?;
+    Ok(if gzip {
+        Output::Gzip(GzEncoder::new(file, Compression::default()))
+    } else {
+        Output::Plain(file)
+    })
+}
+
+/// `events.jsonl.gz` becomes `events.{n}.jsonl.gz`, with `n` after the last rotated file
+pub fn next_rotated_path(path: &Path) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid JSONL file name"))?;
+    let (stem, extension) = match name.split_once('.') {
+        Some((stem, extension)) => (stem, format!(".{extension}")),
+        None => (name, String::new()),
+    };
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut last = 0;
+    for entry in std::fs::read_dir(directory)? {
+        let entry_name = entry?.file_name();
+        let number = entry_name
+            .to_str()
+            .and_then(|entry_name| entry_name.strip_prefix(stem)?.strip_prefix('.'))
+            .and_then(|rest| rest.strip_suffix(extension.as_str()))
+            .and_then(|number| number.parse::<u64>().ok());
+        if let Some(number) = number {
+            last = last.max(number);
+        }
+    }
+    Ok(path.with_file_name(format!("{stem}.{}{extension}", last + 1)))
+}
+
+#[async_trait]
+impl ContractEventHandler for JsonlFileHandler {
+    async fn handle_new_nep141(&self, account_id: AccountId, context: EventContext) {
+        self.handle_new_nep141_with_metadata(NewNep141Event::new(account_id, None, None), context)
+            .await
+    }
+
+    async fn handle_new_nep141_with_metadata(&self, event: NewNep141Event, context: EventContext) {
+        self.write(
+            NewContractNep141Event::ID,
+            context.clone(),
+            NewContractNep141EventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_new_meme(
+        &self,
+        event: MemeCookingCreateMemeEvent,
+        context: EventContext,
+    ) {
+        self.write(
+            NewMemeCookingMemeEvent::ID,
+            context.clone(),
+            NewMemeCookingMemeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_new_token(
+        &self,
+        event: MemeCookingCreateTokenEvent,
+        context: EventContext,
+    ) {
+        self.write(
+            NewMemeCookingTokenEvent::ID,
+            context.clone(),
+            NewMemeCookingTokenEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_reached_soft_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.write(
+            MemeReachedSoftCapEvent::ID,
+            context.clone(),
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_reached_hard_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.write(
+            MemeReachedHardCapEvent::ID,
+            context.clone(),
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_expired_without_token(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.write(
+            MemeExpiredWithoutTokenEvent::ID,
+            context.clone(),
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_launchpad_token_created(
+        &self,
+        event: launchpad::LaunchpadTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.write(
+            LaunchpadTokenCreatedEvent::ID,
+            context.clone(),
+            LaunchpadTokenCreatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_bridged_token_created(
+        &self,
+        event: bridge::BridgedTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.write(
+            BridgedTokenCreatedEvent::ID,
+            context.clone(),
+            BridgedTokenCreatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_first_pool(
+        &self,
+        event: ref_finance::TokenFirstPoolEvent,
+        context: EventContext,
+    ) {
+        self.write(
+            TokenFirstPoolEvent::ID,
+            context.clone(),
+            TokenFirstPoolEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_initial_distribution(
+        &self,
+        event: initial_distribution::TokenInitialDistributionEvent,
+        context: EventContext,
+    ) {
+        self.write(
+            TokenInitialDistributionEvent::ID,
+            context.clone(),
+            TokenInitialDistributionEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_supply_changed(
+        &self,
+        event: supply::TokenSupplyChangedEvent,
+        context: BlockContext,
+    ) {
+        self.write(
+            TokenSupplyChangedEvent::ID,
+            context.clone(),
+            TokenSupplyChangedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_holders_snapshot(
+        &self,
+        event: holders::TokenHoldersSnapshotEvent,
+        context: BlockContext,
+    ) {
+        self.write(
+            TokenHoldersSnapshotEvent::ID,
+            context.clone(),
+            TokenHoldersSnapshotEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_metadata_updated(
+        &self,
+        event: metadata_updates::TokenMetadataUpdatedEvent,
+        context: BlockContext,
+    ) {
+        self.write(
+            TokenMetadataUpdatedEvent::ID,
+            context.clone(),
+            TokenMetadataUpdatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    fn is_testnet(&self) -> bool {
+        self.testnet
+    }
+}
@@ -0,0 +1,325 @@
+use std::{collections::HashMap, time::Duration};
+
+use async_trait::async_trait;
+use inindexer::near_indexer_primitives::types::AccountId;
+use intear_events::events::newcontract::{
+    meme_cooking_meme::NewMemeCookingMemeEvent, meme_cooking_token::NewMemeCookingTokenEvent,
+    nep141::NewContractNep141Event,
+};
+use rdkafka::{
+    message::{Header, OwnedHeaders},
+    producer::{FutureProducer, FutureRecord},
+    ClientConfig,
+};
+use serde::Serialize;
+
+use crate::bridge;
+use crate::events::{
+    BridgedTokenCreatedEvent, BridgedTokenCreatedEventData, LaunchpadTokenCreatedEvent,
+    LaunchpadTokenCreatedEventData, MemeCookingCampaignOutcomeEventData,
+    MemeExpiredWithoutTokenEvent, MemeReachedHardCapEvent, MemeReachedSoftCapEvent,
+    NewContractNep141EventData, NewMemeCookingMemeEventData, NewMemeCookingTokenEventData,
+    TokenFirstPoolEvent, TokenFirstPoolEventData, TokenHoldersSnapshotEvent,
+    TokenHoldersSnapshotEventData, TokenInitialDistributionEvent,
+    TokenInitialDistributionEventData, TokenMetadataUpdatedEvent, TokenMetadataUpdatedEventData,
+    TokenSupplyChangedEvent, TokenSupplyChangedEventData,
+};
+use crate::holders;
+use crate::initial_distribution;
+use crate::launchpad;
+use crate::meme_cooking::{
+    MemeCookingCampaignOutcomeEvent, MemeCookingCreateMemeEvent, MemeCookingCreateTokenEvent,
+};
+use crate::metadata_updates;
+use crate::new_nep141::NewNep141Event;
+use crate::redis_handler::stream_name;
+use crate::ref_finance;
+use crate::supply;
+use crate::{BlockContext, ContractEventHandler, EventContext};
+
+pub const BLOCK_HEIGHT_HEADER: &str = "block_height";
+pub const EVENT_HEADER: &str = "event";
+
+pub struct KafkaHandlerOptions {
+    /// Comma-separated `host:port` list
+    pub brokers: String,
+    /// Topics of event ids, events that are not listed here are written to a topic
+    /// with the same name as the Redis stream
+    pub topics: HashMap<String, String>,
+    pub message_timeout: Duration,
+}
+
+impl KafkaHandlerOptions {
+    pub fn new(brokers: impl Into<String>) -> Self {
+        Self {
+            brokers: brokers.into(),
+            topics: HashMap::new(),
+            message_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Parses `event_id=topic,event_id=topic`
+pub fn parse_kafka_topics(s: &str) -> Result<HashMap<String, String>, String> {
+    s.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((event_id, topic)) if !event_id.trim().is_empty() && !topic.trim().is_empty() => {
+                Ok((event_id.trim().to_string(), topic.trim().to_string()))
+            }
+            _ => Err(format!("Invalid topic mapping: {entry}")),
+        })
+        .collect()
+}
+
+/// Writes every event to a Kafka topic. Messages are keyed by the token's account id,
+/// or by the meme id for meme.cooking events about memes that don't have a token yet,
+/// so all events about one token land in the same partition.
+pub struct KafkaHandler {
+    producer: FutureProducer,
+    topics: HashMap<String, String>,
+    testnet: bool,
+}
+
+impl KafkaHandler {
+    pub fn new(options: KafkaHandlerOptions, testnet: bool) -> anyhow::Result<Self> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", &options.brokers)
+            // Retries after a lost acknowledgement don't write the same message twice
+            .set("enable.idempotence", "true")
+            .set("acks", "all")
+            .set(
+                "message.timeout.ms",
+                options.message_timeout.as_millis().to_string(),
+            )
+            .create()?;
+        Ok(Self {
+            producer,
+            topics: options.topics,
+            testnet,
+        })
+    }
+
+    pub fn topic(&self, event_id: &str) -> String {
+        self.topics
+            .get(event_id)
+            .cloned()
+            .unwrap_or_else(|| stream_name(event_id, self.testnet))
+    }
+
+    async fn emit(&self, event_id: &str, key: String, block_height: u64, data: impl Serialize) {
+        let topic = self.topic(event_id);
+        let payload = serde_json::to_vec(&data).unwrap();
+        let block_height = block_height.to_string();
+        let headers = OwnedHeaders::new()
+            .insert(Header {
+                key: BLOCK_HEIGHT_HEADER,
+                value: Some(&block_height),
+            })
+            .insert(Header {
+                key: EVENT_HEADER,
+                value: Some(event_id),
+            });
+        self.producer
+            .send(
+                FutureRecord::to(&topic)
+                    .key(&key)
+                    .payload(&payload)
+                    .headers(headers),
+                rdkafka::util::Timeout::Never,
+            )
+            .await
+            .map_err(|(err, _)| err)
+            .unwrap_or_else(|err| panic!("Failed to emit {event_id} event to Kafka: {err}"));
+    }
+}
+
+#[async_trait]
+impl ContractEventHandler for KafkaHandler {
+    async fn handle_new_nep141(&self, account_id: AccountId, context: EventContext) {
+        self.handle_new_nep141_with_metadata(NewNep141Event::new(account_id, None, None), context)
+            .await
+    }
+
+    async fn handle_new_nep141_with_metadata(&self, event: NewNep141Event, context: EventContext) {
+        self.emit(
+            NewContractNep141Event::ID,
+            event.account_id.to_string(),
+            context.block_height,
+            NewContractNep141EventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_new_meme(
+        &self,
+        event: MemeCookingCreateMemeEvent,
+        context: EventContext,
+    ) {
+        self.emit(
+            NewMemeCookingMemeEvent::ID,
+            event.meme_id.to_string(),
+            context.block_height,
+            NewMemeCookingMemeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_new_token(
+        &self,
+        event: MemeCookingCreateTokenEvent,
+        context: EventContext,
+    ) {
+        self.emit(
+            NewMemeCookingTokenEvent::ID,
+            event.token_id.to_string(),
+            context.block_height,
+            NewMemeCookingTokenEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_reached_soft_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.emit(
+            MemeReachedSoftCapEvent::ID,
+            event.meme_id.to_string(),
+            context.block_height,
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_reached_hard_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.emit(
+            MemeReachedHardCapEvent::ID,
+            event.meme_id.to_string(),
+            context.block_height,
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_expired_without_token(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.emit(
+            MemeExpiredWithoutTokenEvent::ID,
+            event.meme_id.to_string(),
+            context.block_height,
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_launchpad_token_created(
+        &self,
+        event: launchpad::LaunchpadTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.emit(
+            LaunchpadTokenCreatedEvent::ID,
+            event.token_id.to_string(),
+            context.block_height,
+            LaunchpadTokenCreatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_bridged_token_created(
+        &self,
+        event: bridge::BridgedTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.emit(
+            BridgedTokenCreatedEvent::ID,
+            event.token_id.to_string(),
+            context.block_height,
+            BridgedTokenCreatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_first_pool(
+        &self,
+        event: ref_finance::TokenFirstPoolEvent,
+        context: EventContext,
+    ) {
+        self.emit(
+            TokenFirstPoolEvent::ID,
+            event.token_id.to_string(),
+            context.block_height,
+            TokenFirstPoolEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_initial_distribution(
+        &self,
+        event: initial_distribution::TokenInitialDistributionEvent,
+        context: EventContext,
+    ) {
+        self.emit(
+            TokenInitialDistributionEvent::ID,
+            event.token_id.to_string(),
+            context.block_height,
+            TokenInitialDistributionEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_supply_changed(
+        &self,
+        event: supply::TokenSupplyChangedEvent,
+        context: BlockContext,
+    ) {
+        self.emit(
+            TokenSupplyChangedEvent::ID,
+            event.token_id.to_string(),
+            context.block_height,
+            TokenSupplyChangedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_holders_snapshot(
+        &self,
+        event: holders::TokenHoldersSnapshotEvent,
+        context: BlockContext,
+    ) {
+        self.emit(
+            TokenHoldersSnapshotEvent::ID,
+            event.token_id.to_string(),
+            context.block_height,
+            TokenHoldersSnapshotEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_metadata_updated(
+        &self,
+        event: metadata_updates::TokenMetadataUpdatedEvent,
+        context: BlockContext,
+    ) {
+        self.emit(
+            TokenMetadataUpdatedEvent::ID,
+            event.token_id.to_string(),
+            context.block_height,
+            TokenMetadataUpdatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    fn is_testnet(&self) -> bool {
+        self.testnet
+    }
+}
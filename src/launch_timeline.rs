@@ -0,0 +1,192 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use inindexer::{
+    near_indexer_primitives::types::AccountId, near_utils::EventLogData, IncompleteTransaction,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    json_file_storage::JsonFileStorage,
+    meme_cooking::{MemeCookingContract, MemeCookingCreateMemeEvent, MemeCookingCreateTokenEvent},
+    EventContext,
+};
+
+/// Links `create_meme`, `create_token` and the NEP-141 detection of the same
+/// meme.cooking launch, which otherwise arrive as unrelated events.
+pub struct LaunchTimelines {
+    storage: Arc<dyn LaunchTimelineStorage>,
+}
+
+impl LaunchTimelines {
+    pub fn new(storage: impl LaunchTimelineStorage + 'static) -> Self {
+        Self {
+            storage: Arc::new(storage),
+        }
+    }
+
+    /// Full launch timeline of a token, if it was launched on meme.cooking
+    pub async fn timeline(&self, token_id: &AccountId) -> Option<LaunchTimeline> {
+        self.storage.get_by_token(token_id).await
+    }
+
+    pub async fn record_meme(&self, event: &MemeCookingCreateMemeEvent, context: &EventContext) {
+        let mut timeline = self.get_or_create(event.meme_id).await;
+        timeline.owner = Some(event.owner.clone());
+        timeline.meme_created = Some(context.clone());
+        self.storage.save(timeline).await;
+    }
+
+    pub async fn record_token(&self, event: &MemeCookingCreateTokenEvent, context: &EventContext) {
+        let mut timeline = self.get_or_create(event.meme_id).await;
+        timeline.token_id = Some(event.token_id.clone());
+        timeline.pool_id = Some(event.pool_id);
+        timeline.token_created = Some(context.clone());
+        self.storage.save(timeline).await;
+    }
+
+    pub async fn record_nep141(
+        &self,
+        origin: &MemeCookingOrigin,
+        token_id: &AccountId,
+        context: &EventContext,
+    ) {
+        let mut timeline = self.get_or_create(origin.meme_id).await;
+        timeline.token_id = Some(token_id.clone());
+        timeline.nep141_detected = Some(context.clone());
+        self.storage.save(timeline).await;
+    }
+
+    /// Finds out whether a newly deployed token comes from meme.cooking. The
+    /// `create_token` event is logged a few blocks after the token contract is
+    /// deployed, so if it hasn't been seen yet, receipts of the same transaction
+    /// and the account id itself (`{symbol}-{meme_id}.{contract}`) are checked.
+    pub async fn meme_cooking_origin(
+        &self,
+        token_id: &AccountId,
+        tx: &IncompleteTransaction,
+        contracts: &[MemeCookingContract],
+    ) -> Option<MemeCookingOrigin> {
+        if let Some(timeline) = self.storage.get_by_token(token_id).await {
+            return Some(timeline.origin());
+        }
+
+        let create_token_event = tx
+            .receipts
+            .values()
+            .flatten()
+            .filter_map(|receipt| {
+                contracts
+                    .iter()
+                    .find(|contract| contract.account_id == receipt.receipt.receipt.receiver_id)
+                    .map(|contract| (receipt, contract))
+            })
+            .flat_map(|(receipt, contract)| {
+                receipt
+                    .receipt
+                    .execution_outcome
+                    .outcome
+                    .logs
+                    .iter()
+                    .map(move |log| (log, contract))
+            })
+            .filter_map(|(log, contract)| {
+                EventLogData::<MemeCookingCreateTokenEvent>::deserialize(log)
+                    .ok()
+                    .filter(|event| {
+                        event.standard == "meme-cooking"
+                            && event.event == "create_token"
+                            && contract.accepts_version(&event.version)
+                    })
+            })
+            .map(|event| event.data)
+            .find(|event| &event.token_id == token_id);
+        let meme_id = match &create_token_event {
+            Some(event) => event.meme_id,
+            None => contracts.iter().find_map(|contract| {
+                meme_id_from_token_account(token_id, contract.account_id.as_str())
+            })?,
+        };
+        let timeline = self.storage.get_by_meme(meme_id).await;
+        if create_token_event.is_none() && timeline.is_none() {
+            // The account id looks like a meme.cooking token, but nothing is known about this meme
+            return None;
+        }
+        Some(MemeCookingOrigin {
+            meme_id,
+            owner: timeline
+                .as_ref()
+                .and_then(|timeline| timeline.owner.clone()),
+            pool_id: create_token_event
+                .map(|event| event.pool_id)
+                .or(timeline.and_then(|timeline| timeline.pool_id)),
+        })
+    }
+
+    async fn get_or_create(&self, meme_id: u64) -> LaunchTimeline {
+        self.storage
+            .get_by_meme(meme_id)
+            .await
+            .unwrap_or(LaunchTimeline {
+                meme_id,
+                owner: None,
+                token_id: None,
+                pool_id: None,
+                meme_created: None,
+                token_created: None,
+                nep141_detected: None,
+            })
+    }
+}
+
+impl Default for LaunchTimelines {
+    fn default() -> Self {
+        Self::new(JsonFileStorage::<HashMap<u64, LaunchTimeline>>::default())
+    }
+}
+
+/// Parses `meme_id` out of `{symbol}-{meme_id}.{contract}`
+pub fn meme_id_from_token_account(token_id: &AccountId, contract: &str) -> Option<u64> {
+    let name = token_id
+        .as_str()
+        .strip_suffix(contract)?
+        .strip_suffix('.')?;
+    let (_symbol, meme_id) = name.rsplit_once('-')?;
+    meme_id.parse().ok()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LaunchTimeline {
+    pub meme_id: u64,
+    pub owner: Option<AccountId>,
+    pub token_id: Option<AccountId>,
+    pub pool_id: Option<u64>,
+    pub meme_created: Option<EventContext>,
+    pub token_created: Option<EventContext>,
+    pub nep141_detected: Option<EventContext>,
+}
+
+impl LaunchTimeline {
+    pub fn origin(&self) -> MemeCookingOrigin {
+        MemeCookingOrigin {
+            meme_id: self.meme_id,
+            owner: self.owner.clone(),
+            pool_id: self.pool_id,
+        }
+    }
+}
+
+/// Where a NEP-141 token came from, if it was launched on meme.cooking
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemeCookingOrigin {
+    pub meme_id: u64,
+    pub owner: Option<AccountId>,
+    pub pool_id: Option<u64>,
+}
+
+#[async_trait]
+pub trait LaunchTimelineStorage: Send + Sync {
+    async fn get_by_meme(&self, meme_id: u64) -> Option<LaunchTimeline>;
+    async fn get_by_token(&self, token_id: &AccountId) -> Option<LaunchTimeline>;
+    async fn save(&self, timeline: LaunchTimeline);
+}
@@ -0,0 +1,136 @@
+use std::{path::Path, sync::Arc};
+
+use inindexer::{
+    near_indexer_primitives::{
+        types::{AccountId, Balance},
+        StreamerMessage,
+    },
+    near_utils::EventLogData,
+    IncompleteTransaction, TransactionReceipt,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{ContractEventHandler, EventContext};
+
+/// Detects token launches on launchpads that don't need a hand-written parser
+/// like [`MemeCookingIndexer`](crate::meme_cooking::MemeCookingIndexer)
+#[derive(Default)]
+pub struct LaunchpadIndexer {
+    pub rules: Vec<LaunchpadRule>,
+}
+
+impl LaunchpadIndexer {
+    pub fn new(rules: Vec<LaunchpadRule>) -> Self {
+        Self { rules }
+    }
+
+    pub async fn detect_launchpads<T: ContractEventHandler>(
+        &mut self,
+        receipt: &TransactionReceipt,
+        tx: &IncompleteTransaction,
+        block: &StreamerMessage,
+        handler: Arc<T>,
+    ) {
+        for rule in self.rules.iter() {
+            if rule.contract_id != receipt.receipt.receipt.receiver_id {
+                continue;
+            }
+            for log in receipt.receipt.execution_outcome.outcome.logs.iter() {
+                for event in rule.parse_log(log) {
+                    let context = EventContext::new(receipt, tx, block);
+                    handler.handle_launchpad_token_created(event, context).await;
+                }
+            }
+        }
+    }
+}
+
+/// A launchpad contract that emits a NEP-297 event when a token is launched
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LaunchpadRule {
+    /// Name of the launchpad that is included in events
+    pub name: String,
+    pub contract_id: AccountId,
+    pub standard: String,
+    pub event: String,
+    pub fields: LaunchpadFieldMapping,
+}
+
+/// JSON pointers (RFC 6901) to the fields inside of the event's `data`, e.g. `/token_id`.
+/// If `data` is an array, the pointers are applied to each item.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LaunchpadFieldMapping {
+    pub token_id: String,
+    pub owner: Option<String>,
+    pub total_supply: Option<String>,
+}
+
+impl LaunchpadRule {
+    pub fn parse_log(&self, log: &str) -> Vec<LaunchpadTokenCreatedEvent> {
+        let Ok(event) = EventLogData::<Value>::deserialize(log) else {
+            return Vec::new();
+        };
+        if event.standard != self.standard || event.event != self.event {
+            return Vec::new();
+        }
+        let items = match event.data {
+            Value::Array(items) => items,
+            data => vec![data],
+        };
+        items
+            .iter()
+            .filter_map(|data| {
+                let Some(token_id) = data
+                    .pointer(&self.fields.token_id)
+                    .and_then(Value::as_str)
+                    .and_then(|token_id| token_id.parse().ok())
+                else {
+                    log::warn!(
+                        "Launchpad {} emitted {} without a valid token id at {}",
+                        self.name,
+                        self.event,
+                        self.fields.token_id
+                    );
+                    return None;
+                };
+                Some(LaunchpadTokenCreatedEvent {
+                    launchpad: self.name.clone(),
+                    contract_id: self.contract_id.clone(),
+                    token_id,
+                    owner: self
+                        .fields
+                        .owner
+                        .as_ref()
+                        .and_then(|pointer| data.pointer(pointer))
+                        .and_then(Value::as_str)
+                        .and_then(|owner| owner.parse().ok()),
+                    total_supply: self
+                        .fields
+                        .total_supply
+                        .as_ref()
+                        .and_then(|pointer| data.pointer(pointer))
+                        .and_then(|total_supply| match total_supply {
+                            Value::String(total_supply) => total_supply.parse().ok(),
+                            Value::Number(total_supply) => total_supply.as_u64().map(Balance::from),
+                            _ => None,
+                        }),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads a JSON array of [`LaunchpadRule`]s
+pub async fn load_launchpad_rules(path: impl AsRef<Path>) -> anyhow::Result<Vec<LaunchpadRule>> {
+    Ok(serde_json::from_slice(&tokio::fs::read(path).await?)?)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaunchpadTokenCreatedEvent {
+    pub launchpad: String,
+    pub contract_id: AccountId,
+    pub token_id: AccountId,
+    pub owner: Option<AccountId>,
+    pub total_supply: Option<Balance>,
+}
@@ -1,32 +1,110 @@
+#[cfg(feature = "api")]
+pub mod api;
+pub mod bridge;
+pub mod events;
+pub mod holders;
+pub mod initial_distribution;
+pub mod json_file_storage;
+pub mod jsonl_file_handler;
+#[cfg(feature = "kafka")]
+pub mod kafka_handler;
+pub mod launch_timeline;
+pub mod launchpad;
 pub mod meme_cooking;
+pub mod meme_cooking_reference;
+pub mod metadata_updates;
+pub mod metadata_validation;
+pub mod multi_handler;
+#[cfg(feature = "nats")]
+pub mod nats_handler;
 pub mod new_nep141;
+#[cfg(feature = "postgres")]
+pub mod postgres_handler;
+pub mod redis_consumer;
 pub mod redis_handler;
+pub mod ref_finance;
+pub mod risk;
+pub mod supply;
 #[cfg(test)]
 mod tests;
+pub mod tkn_factory;
 pub mod txt_file_storage;
+pub mod webhook_handler;
+#[cfg(feature = "websocket")]
+pub mod websocket_handler;
 
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use bridge::BridgeFactory;
+use bridge::BridgeIndexer;
+use bridge::BridgedTokenCreatedEvent;
+use holders::HoldersTracker;
+use holders::HoldersTrackerOptions;
+use holders::TokenHoldersSnapshotEvent;
 use inindexer::near_indexer_primitives::types::AccountId;
+use inindexer::near_indexer_primitives::types::Balance;
 use inindexer::near_indexer_primitives::types::BlockHeight;
+use inindexer::near_indexer_primitives::views::ActionView;
 use inindexer::near_indexer_primitives::views::ExecutionStatusView;
+use inindexer::near_indexer_primitives::views::ReceiptEnumView;
 use inindexer::near_indexer_primitives::CryptoHash;
 use inindexer::near_indexer_primitives::StreamerMessage;
+use inindexer::near_utils::dec_format;
 use inindexer::IncompleteTransaction;
 use inindexer::Indexer;
 use inindexer::TransactionReceipt;
+use initial_distribution::InitialDistributions;
+use initial_distribution::TokenInitialDistributionEvent;
+use json_file_storage::JsonFileStorage;
+use launch_timeline::LaunchTimelineStorage;
+use launch_timeline::LaunchTimelines;
+use launchpad::LaunchpadIndexer;
+use launchpad::LaunchpadRule;
+use launchpad::LaunchpadTokenCreatedEvent;
+use meme_cooking::MemeCookingCampaign;
+use meme_cooking::MemeCookingCampaignOutcomeEvent;
+use meme_cooking::MemeCookingCampaignStorage;
+use meme_cooking::MemeCookingContract;
 use meme_cooking::MemeCookingCreateMemeEvent;
+use meme_cooking::MemeCookingEventSchema;
 use meme_cooking::MemeCookingIndexer;
+use meme_cooking_reference::ReferenceFetcher;
+use metadata_updates::MetadataWatcher;
+use metadata_updates::MetadataWatcherOptions;
+use metadata_updates::TokenMetadataStorage;
+use metadata_updates::TokenMetadataUpdatedEvent;
 use near_jsonrpc_client::JsonRpcClient;
 use new_nep141::HandledTokensStorage;
 use new_nep141::Nep141Indexer;
+use new_nep141::NewNep141Event;
+use ref_finance::RefFinanceIndexer;
+use ref_finance::RefPoolStorage;
+use ref_finance::RefPools;
+use ref_finance::TokenFirstPoolEvent;
+use risk::RiskScorer;
+use risk::RiskScorerOptions;
+use serde::{Deserialize, Serialize};
+use supply::SupplyTracker;
+use supply::SupplyTrackerOptions;
+use supply::TokenSupplyChangedEvent;
+use supply::TokenSupplyStorage;
+use tkn_factory::TknFactoryIndexer;
 
 use crate::meme_cooking::MemeCookingCreateTokenEvent;
 
+/// New NEP-141 tokens and meme.cooking memes and tokens must be handled, other events are
+/// ignored unless a handler overrides their method.
 #[async_trait]
 pub trait ContractEventHandler: Send + Sync {
+    /// Called by [`handle_new_nep141_with_metadata`](Self::handle_new_nep141_with_metadata)
+    /// unless it's overridden, for handlers that only need the account id.
     async fn handle_new_nep141(&self, account_id: AccountId, context: EventContext);
+    async fn handle_new_nep141_with_metadata(&self, event: NewNep141Event, context: EventContext) {
+        self.handle_new_nep141(event.account_id, context).await
+    }
     async fn handle_meme_cooking_new_meme(
         &self,
         event: MemeCookingCreateMemeEvent,
@@ -37,13 +115,78 @@ pub trait ContractEventHandler: Send + Sync {
         event: MemeCookingCreateTokenEvent,
         context: EventContext,
     );
+    async fn handle_meme_cooking_reached_soft_cap(
+        &self,
+        _event: MemeCookingCampaignOutcomeEvent,
+        _context: BlockContext,
+    ) {
+    }
+    async fn handle_meme_cooking_reached_hard_cap(
+        &self,
+        _event: MemeCookingCampaignOutcomeEvent,
+        _context: BlockContext,
+    ) {
+    }
+    async fn handle_meme_cooking_expired_without_token(
+        &self,
+        _event: MemeCookingCampaignOutcomeEvent,
+        _context: BlockContext,
+    ) {
+    }
+    async fn handle_launchpad_token_created(
+        &self,
+        _event: LaunchpadTokenCreatedEvent,
+        _context: EventContext,
+    ) {
+    }
+    async fn handle_bridged_token_created(
+        &self,
+        _event: BridgedTokenCreatedEvent,
+        _context: EventContext,
+    ) {
+    }
+    async fn handle_token_first_pool(&self, _event: TokenFirstPoolEvent, _context: EventContext) {}
+    async fn handle_token_initial_distribution(
+        &self,
+        _event: TokenInitialDistributionEvent,
+        _context: EventContext,
+    ) {
+    }
+    async fn handle_token_supply_changed(
+        &self,
+        _event: TokenSupplyChangedEvent,
+        _context: BlockContext,
+    ) {
+    }
+    async fn handle_token_holders_snapshot(
+        &self,
+        _event: TokenHoldersSnapshotEvent,
+        _context: BlockContext,
+    ) {
+    }
+    async fn handle_token_metadata_updated(
+        &self,
+        _event: TokenMetadataUpdatedEvent,
+        _context: BlockContext,
+    ) {
+    }
     fn is_testnet(&self) -> bool;
 }
 
 pub struct NewTokenIndexer<T: ContractEventHandler> {
     pub handler: Arc<T>,
     pub nep141_indexer: Nep141Indexer,
+    pub tkn_factory_indexer: TknFactoryIndexer,
     pub meme_cooking_indexer: MemeCookingIndexer,
+    pub launches: Arc<LaunchTimelines>,
+    pub distributions: Arc<InitialDistributions>,
+    pub launchpad_indexer: LaunchpadIndexer,
+    pub bridge_indexer: BridgeIndexer,
+    pub ref_finance_indexer: RefFinanceIndexer,
+    pub supply_tracker: Option<SupplyTracker>,
+    pub holders_tracker: Option<HoldersTracker>,
+    pub metadata_watcher: Option<MetadataWatcher>,
+    pub status: Arc<IndexerStatus>,
 }
 
 impl<T: ContractEventHandler> NewTokenIndexer<T> {
@@ -52,12 +195,146 @@ impl<T: ContractEventHandler> NewTokenIndexer<T> {
         rpc_client: JsonRpcClient,
         handled_accounts: impl HandledTokensStorage + 'static,
     ) -> Self {
+        let meme_cooking_contracts = MemeCookingContract::defaults(handler.is_testnet());
+        let testnet = handler.is_testnet();
+        let bridge_factories = BridgeFactory::defaults(testnet);
+        let nep141_indexer = Nep141Indexer::new(rpc_client, handled_accounts);
         Self {
             handler: Arc::new(handler),
-            nep141_indexer: Nep141Indexer::new(rpc_client, handled_accounts),
-            meme_cooking_indexer: MemeCookingIndexer,
+            bridge_indexer: BridgeIndexer::new(nep141_indexer.storage(), bridge_factories),
+            ref_finance_indexer: RefFinanceIndexer::new(
+                nep141_indexer.storage(),
+                JsonFileStorage::<RefPools>::default(),
+                testnet,
+            ),
+            tkn_factory_indexer: TknFactoryIndexer::new(nep141_indexer.storage()),
+            distributions: nep141_indexer.distributions(),
+            nep141_indexer,
+            meme_cooking_indexer: MemeCookingIndexer::new(
+                JsonFileStorage::<HashMap<u64, MemeCookingCampaign>>::default(),
+                meme_cooking_contracts,
+            ),
+            launches: Arc::new(LaunchTimelines::default()),
+            launchpad_indexer: LaunchpadIndexer::default(),
+            supply_tracker: None,
+            holders_tracker: None,
+            metadata_watcher: None,
+            status: Arc::new(IndexerStatus::default()),
         }
     }
+
+    /// Persists meme.cooking campaign state, so that soft cap / hard cap / expiration
+    /// events are not lost when the indexer is restarted in the middle of a campaign.
+    pub fn with_meme_cooking_campaigns(
+        mut self,
+        campaigns: impl MemeCookingCampaignStorage + 'static,
+    ) -> Self {
+        self.meme_cooking_indexer = MemeCookingIndexer::new(
+            campaigns,
+            std::mem::take(&mut self.meme_cooking_indexer.contracts),
+        );
+        self
+    }
+
+    /// Overrides meme.cooking contracts, which default to the current mainnet or testnet factory
+    pub fn with_meme_cooking_contracts(mut self, contracts: Vec<MemeCookingContract>) -> Self {
+        self.meme_cooking_indexer.contracts = contracts;
+        self
+    }
+
+    /// Reads events of the listed meme.cooking contract versions with their own field mappings
+    pub fn with_meme_cooking_schemas(mut self, schemas: Vec<MemeCookingEventSchema>) -> Self {
+        self.meme_cooking_indexer.schemas = schemas;
+        self
+    }
+
+    /// Downloads and verifies `reference` documents of new memes
+    pub fn with_meme_cooking_references(mut self, reference_fetcher: ReferenceFetcher) -> Self {
+        self.meme_cooking_indexer.reference_fetcher = Some(reference_fetcher);
+        self
+    }
+
+    /// Launchpads that are detected by their NEP-297 events, in addition to meme.cooking
+    pub fn with_launchpad_rules(mut self, rules: Vec<LaunchpadRule>) -> Self {
+        self.launchpad_indexer = LaunchpadIndexer::new(rules);
+        self
+    }
+
+    /// Overrides bridge factories, which default to Rainbow and Omni bridge on mainnet
+    pub fn with_bridge_factories(mut self, factories: Vec<BridgeFactory>) -> Self {
+        self.bridge_indexer.factories = factories;
+        self
+    }
+
+    /// Persists Ref Finance pools that are waiting for liquidity and tokens that already have a pool
+    pub fn with_ref_pools(mut self, pools: impl RefPoolStorage + 'static) -> Self {
+        self.ref_finance_indexer = RefFinanceIndexer::new(
+            self.nep141_indexer.storage(),
+            pools,
+            self.handler.is_testnet(),
+        );
+        self
+    }
+
+    /// Tracks supply of known tokens from their mint and burn events
+    pub fn with_supply_tracking(
+        mut self,
+        storage: impl TokenSupplyStorage + 'static,
+        options: SupplyTrackerOptions,
+    ) -> Self {
+        self.supply_tracker = Some(SupplyTracker::new(
+            self.nep141_indexer.storage(),
+            storage,
+            self.nep141_indexer.rpc_client(),
+            options,
+        ));
+        self
+    }
+
+    /// Counts holders of tokens for some time after their first mint
+    pub async fn with_holders_tracking(mut self, options: HoldersTrackerOptions) -> Self {
+        self.holders_tracker = Some(HoldersTracker::new(
+            self.distributions.subscribe().await,
+            options,
+        ));
+        self
+    }
+
+    /// Adds a spam / scam risk score to NEP-141 events
+    pub fn with_risk_scoring(mut self, options: RiskScorerOptions) -> Self {
+        let risk_scorer = Arc::new(RiskScorer::new(self.nep141_indexer.rpc_client(), options));
+        self.nep141_indexer.risk_scorer = Some(Arc::clone(&risk_scorer));
+        self.tkn_factory_indexer.risk_scorer = Some(risk_scorer);
+        self
+    }
+
+    /// Re-checks metadata of known tokens and reports changes
+    pub fn with_metadata_updates(
+        mut self,
+        storage: impl TokenMetadataStorage + 'static,
+        options: MetadataWatcherOptions,
+    ) -> Self {
+        let metadata_watcher = MetadataWatcher::new(
+            self.nep141_indexer.storage(),
+            storage,
+            self.nep141_indexer.rpc_client(),
+            options,
+        );
+        self.nep141_indexer.metadata_storage = Some(metadata_watcher.storage());
+        self.tkn_factory_indexer.metadata_storage = Some(metadata_watcher.storage());
+        self.metadata_watcher = Some(metadata_watcher);
+        self
+    }
+
+    /// Persists links between `create_meme`, `create_token` and NEP-141 events of meme.cooking
+    /// launches, so that they can be queried with [`LaunchTimelines::timeline`]
+    pub fn with_launch_timelines(
+        mut self,
+        timelines: impl LaunchTimelineStorage + 'static,
+    ) -> Self {
+        self.launches = Arc::new(LaunchTimelines::new(timelines));
+        self
+    }
 }
 
 #[async_trait]
@@ -77,22 +354,202 @@ impl<T: ContractEventHandler + 'static> Indexer for NewTokenIndexer<T> {
             return Ok(());
         }
 
+        // Before NEP-141 detection, which marks the token as handled
+        self.bridge_indexer
+            .detect_bridged_tokens(receipt, tx, block, Arc::clone(&self.handler))
+            .await;
+
+        // Before NEP-141 detection, so that tkn.near tokens are marked as handled and don't need RPC
+        self.tkn_factory_indexer
+            .detect_tkn_factory(
+                receipt,
+                tx,
+                block,
+                Arc::clone(&self.handler),
+                &self.distributions,
+            )
+            .await;
+
         self.nep141_indexer
-            .detect_nep141(receipt, tx, block, Arc::clone(&self.handler))
+            .detect_nep141(
+                receipt,
+                tx,
+                block,
+                Arc::clone(&self.handler),
+                Arc::clone(&self.launches),
+                &self.meme_cooking_indexer.contracts,
+            )
             .await;
 
         self.meme_cooking_indexer
-            .detect_meme_cooking(receipt, tx, block, Arc::clone(&self.handler))
+            .detect_meme_cooking(
+                receipt,
+                tx,
+                block,
+                Arc::clone(&self.handler),
+                &self.launches,
+            )
+            .await;
+
+        self.launchpad_indexer
+            .detect_launchpads(receipt, tx, block, Arc::clone(&self.handler))
+            .await;
+
+        self.ref_finance_indexer
+            .detect_first_pools(receipt, tx, block, Arc::clone(&self.handler))
+            .await;
+
+        if let Some(holders_tracker) = &mut self.holders_tracker {
+            holders_tracker.track_holders(receipt);
+        }
+
+        if let Some(metadata_watcher) = &mut self.metadata_watcher {
+            metadata_watcher.watch_metadata(receipt).await;
+        }
+
+        // After NEP-141 detection, so that mints in the deployment receipt are attributed right away
+        self.distributions
+            .detect_mints(receipt, tx, block, &*self.handler)
+            .await;
+
+        if let Some(supply_tracker) = &mut self.supply_tracker {
+            supply_tracker.track_supply(receipt).await;
+        }
+
+        Ok(())
+    }
+
+    async fn process_block_end(&mut self, block: &StreamerMessage) -> Result<(), Self::Error> {
+        self.meme_cooking_indexer
+            .check_campaign_deadlines(block, Arc::clone(&self.handler))
+            .await;
+        self.distributions.prune(block.block.header.height).await;
+        if let Some(supply_tracker) = &mut self.supply_tracker {
+            supply_tracker
+                .process_block_end(block, Arc::clone(&self.handler))
+                .await;
+        }
+        if let Some(holders_tracker) = &mut self.holders_tracker {
+            holders_tracker
+                .process_block_end(block, Arc::clone(&self.handler))
+                .await;
+        }
+        if let Some(metadata_watcher) = &mut self.metadata_watcher {
+            metadata_watcher
+                .process_block_end(block, Arc::clone(&self.handler))
+                .await;
+        }
+        self.status.block_processed(block);
+
+        Ok(())
+    }
+
+    async fn finalize(&mut self) -> Result<(), Self::Error> {
+        self.meme_cooking_indexer
+            .finish_reference_downloads(Arc::clone(&self.handler))
             .await;
 
         Ok(())
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct EventContext {
     pub transaction_id: CryptoHash,
     pub receipt_id: CryptoHash,
     pub block_height: BlockHeight,
+    #[serde(deserialize_with = "deserialize_timestamp")]
     pub block_timestamp_nanosec: u128,
+    pub signer_id: AccountId,
+    pub predecessor_id: AccountId,
+    /// Sum of deposits attached to the actions of the receipt
+    #[serde(with = "dec_format")]
+    pub attached_deposit: Balance,
+    /// Set when the receiver is a sub-account created by its parent contract on behalf of the signer
+    pub factory_id: Option<AccountId>,
+}
+
+impl EventContext {
+    pub fn new(
+        receipt: &TransactionReceipt,
+        tx: &IncompleteTransaction,
+        block: &StreamerMessage,
+    ) -> Self {
+        let signer_id = tx.transaction.transaction.signer_id.clone();
+        let predecessor_id = receipt.receipt.receipt.predecessor_id.clone();
+        let attached_deposit = match &receipt.receipt.receipt.receipt {
+            ReceiptEnumView::Action { actions, .. } => actions
+                .iter()
+                .map(|action| match action {
+                    ActionView::FunctionCall { deposit, .. } | ActionView::Transfer { deposit } => {
+                        *deposit
+                    }
+                    _ => 0,
+                })
+                .fold(0, Balance::saturating_add),
+            _ => 0,
+        };
+        let factory_id = receipt
+            .receipt
+            .receipt
+            .receiver_id
+            .as_str()
+            .split_once('.')
+            .is_some_and(|(_, parent)| {
+                parent == predecessor_id.as_str() && predecessor_id != signer_id
+            })
+            .then(|| predecessor_id.clone());
+        Self {
+            transaction_id: tx.transaction.transaction.hash,
+            receipt_id: receipt.receipt.receipt.receipt_id,
+            block_height: block.block.header.height,
+            block_timestamp_nanosec: block.block.header.timestamp_nanosec as u128,
+            signer_id,
+            predecessor_id,
+            attached_deposit,
+            factory_id,
+        }
+    }
+}
+
+/// Progress of [`NewTokenIndexer`], updated after every block
+#[derive(Debug, Default)]
+pub struct IndexerStatus {
+    last_block_height: AtomicU64,
+    last_block_timestamp_nanosec: AtomicU64,
+}
+
+impl IndexerStatus {
+    fn block_processed(&self, block: &StreamerMessage) {
+        self.last_block_timestamp_nanosec
+            .store(block.block.header.timestamp_nanosec, Ordering::Relaxed);
+        self.last_block_height
+            .store(block.block.header.height, Ordering::Relaxed);
+    }
+
+    /// `None` until the first block is processed
+    pub fn last_block_height(&self) -> Option<BlockHeight> {
+        Some(self.last_block_height.load(Ordering::Relaxed)).filter(|height| *height != 0)
+    }
+
+    pub fn last_block_timestamp_nanosec(&self) -> Option<u64> {
+        Some(self.last_block_timestamp_nanosec.load(Ordering::Relaxed))
+            .filter(|timestamp| *timestamp != 0)
+    }
+}
+
+/// Context of events that are triggered by block time rather than by a specific receipt
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockContext {
+    pub block_height: BlockHeight,
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    pub block_timestamp_nanosec: u128,
+}
+
+/// Block timestamps fit in `u64`, and reading them as one also works in event payloads that
+/// flatten a context, where serde can't read a `u128`
+fn deserialize_timestamp<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u128, D::Error> {
+    u64::deserialize(deserializer).map(u128::from)
 }
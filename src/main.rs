@@ -1,11 +1,47 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use inindexer::near_indexer_primitives::types::AccountId;
 use inindexer::neardata_server::NeardataServerProvider;
 
 use inindexer::{
     run_indexer, AutoContinue, BlockIterator, IndexerOptions, PreprocessTransactionsSettings,
 };
 use near_jsonrpc_client::JsonRpcClient;
+#[cfg(feature = "api")]
+use new_token_indexer::api::api_router;
+#[cfg(feature = "kafka")]
+use new_token_indexer::kafka_handler::{parse_kafka_topics, KafkaHandler, KafkaHandlerOptions};
+#[cfg(feature = "nats")]
+use new_token_indexer::nats_handler::{NatsHandler, NatsHandlerOptions};
+#[cfg(feature = "postgres")]
+use new_token_indexer::postgres_handler::PostgresHandler;
+#[cfg(feature = "websocket")]
+use new_token_indexer::websocket_handler::{WebSocketHandler, WebSocketOptions};
 use new_token_indexer::{
-    redis_handler::PushToRedisStream, txt_file_storage::TxtFileStorage, NewTokenIndexer,
+    events::EVENT_IDS,
+    holders::HoldersTrackerOptions,
+    json_file_storage::{JsonFileStorage, JsonlWebhookStorage},
+    jsonl_file_handler::{JsonlFileHandler, JsonlFileOptions},
+    launch_timeline::LaunchTimeline,
+    launchpad::load_launchpad_rules,
+    meme_cooking::{load_meme_cooking_schemas, parse_meme_cooking_contracts, MemeCookingCampaign},
+    meme_cooking_reference::{ReferenceFetcher, DEFAULT_IPFS_GATEWAY},
+    metadata_updates::{MetadataWatcherOptions, StoredMetadata},
+    multi_handler::MultiHandler,
+    redis_handler::{
+        parse_redis_retention, parse_stream_retention, PushToRedisStream, RedisRetention,
+        StreamRetention,
+    },
+    ref_finance::RefPools,
+    risk::{load_allowlist, RiskScorerOptions},
+    supply::{SupplyTrackerOptions, TokenSupply},
+    txt_file_storage::TxtFileStorage,
+    webhook_handler::{load_webhook_endpoints, WebhookHandler, WebhookOptions},
+    ContractEventHandler, NewTokenIndexer,
 };
 use redis::aio::ConnectionManager;
 
@@ -21,17 +57,289 @@ async fn main() {
         .unwrap();
 
     let is_testnet = std::env::var("TESTNET").is_ok();
-    let client = redis::Client::open(
-        std::env::var("REDIS_URL").expect("No $REDIS_URL environment variable set"),
+    let args = Args::parse(std::env::args().skip(1));
+    let mut handler = MultiHandler::new(is_testnet);
+    if let Some(path) = args.jsonl {
+        let mut options = JsonlFileOptions {
+            gzip: args.gzip,
+            ..Default::default()
+        };
+        if let Some(max_file_bytes) = args.max_file_bytes {
+            options.max_file_bytes = max_file_bytes;
+        }
+        handler = add_sink(
+            handler,
+            "jsonl",
+            Arc::new(
+                JsonlFileHandler::new(path, options, is_testnet)
+                    .expect("Failed to open JSONL file"),
+            ),
+            sink_events("JSONL_EVENTS"),
+        );
+    }
+    if let Ok(path) = std::env::var("WEBHOOKS") {
+        let endpoints = load_webhook_endpoints(&path)
+            .await
+            .expect("Failed to load $WEBHOOKS");
+        handler = add_sink(
+            handler,
+            "webhooks",
+            Arc::new(
+                WebhookHandler::new(
+                    endpoints,
+                    JsonlWebhookStorage::new("undelivered_webhooks.jsonl")
+                        .await
+                        .expect("Failed to open undelivered_webhooks.jsonl"),
+                    WebhookOptions::default(),
+                    is_testnet,
+                )
+                .await,
+            ),
+            None,
+        );
+    }
+    #[cfg(feature = "kafka")]
+    if let Ok(brokers) = std::env::var("KAFKA_BROKERS") {
+        let mut options = KafkaHandlerOptions::new(brokers);
+        if let Ok(topics) = std::env::var("KAFKA_TOPICS") {
+            options.topics = parse_kafka_topics(&topics).expect("Invalid $KAFKA_TOPICS");
+        }
+        handler = add_sink(
+            handler,
+            "kafka",
+            Arc::new(
+                KafkaHandler::new(options, is_testnet).expect("Failed to create Kafka producer"),
+            ),
+            sink_events("KAFKA_EVENTS"),
+        );
+    }
+    #[cfg(feature = "nats")]
+    if let Ok(url) = std::env::var("NATS_URL") {
+        let client = async_nats::connect(url)
+            .await
+            .expect("Failed to connect to NATS");
+        let mut options = NatsHandlerOptions::default();
+        if let Ok(stream) = std::env::var("NATS_STREAM") {
+            options.stream = stream;
+        }
+        handler = add_sink(
+            handler,
+            "nats",
+            Arc::new(
+                NatsHandler::new(client, options, is_testnet)
+                    .await
+                    .expect("Failed to create NATS stream"),
+            ),
+            sink_events("NATS_EVENTS"),
+        );
+    }
+    #[cfg(feature = "postgres")]
+    if let Ok(url) = std::env::var("DATABASE_URL") {
+        let pool = sqlx::PgPool::connect(&url)
+            .await
+            .expect("Failed to connect to PostgreSQL");
+        handler = add_sink(
+            handler,
+            "postgres",
+            Arc::new(
+                PostgresHandler::new(pool, is_testnet)
+                    .await
+                    .expect("Failed to run PostgreSQL migrations"),
+            ),
+            sink_events("POSTGRES_EVENTS"),
+        );
+    }
+    #[cfg(feature = "websocket")]
+    if let Ok(addr) = std::env::var("WEBSOCKET_ADDR") {
+        let mut options = WebSocketOptions::default();
+        if let Ok(events) = std::env::var("WEBSOCKET_REPLAY_EVENTS") {
+            options.replay_events = events.parse().expect("Invalid $WEBSOCKET_REPLAY_EVENTS");
+        }
+        let websocket = WebSocketHandler::new(options, is_testnet);
+        let listener = tokio::net::TcpListener::bind(&addr)
+            .await
+            .expect("Failed to bind $WEBSOCKET_ADDR");
+        let router = websocket.router();
+        tokio::spawn(async move {
+            axum::serve(listener, router)
+                .await
+                .expect("WebSocket server failed");
+        });
+        handler = add_sink(
+            handler,
+            "websocket",
+            Arc::new(websocket),
+            sink_events("WEBSOCKET_EVENTS"),
+        );
+    }
+    // Redis is the default, other sinks are written to in addition to it if it's set
+    if handler.is_empty() || std::env::var("REDIS_URL").is_ok() {
+        let client = redis::Client::open(
+            std::env::var("REDIS_URL").expect("No $REDIS_URL environment variable set"),
+        )
+        .unwrap();
+        let connection = ConnectionManager::new(client).await.unwrap();
+        let mut retention = RedisRetention::new(match std::env::var("REDIS_DEFAULT_RETENTION") {
+            Ok(retention) => {
+                parse_stream_retention(&retention).expect("Invalid $REDIS_DEFAULT_RETENTION")
+            }
+            Err(_) => StreamRetention::MaxLen(1_000),
+        });
+        if let Ok(streams) = std::env::var("REDIS_RETENTION") {
+            retention.streams = parse_redis_retention(&streams).expect("Invalid $REDIS_RETENTION");
+            for event_id in retention.streams.keys() {
+                assert!(
+                    EVENT_IDS.contains(&event_id.as_str()),
+                    "Unknown event {event_id} in $REDIS_RETENTION"
+                );
+            }
+        }
+        retention.approximate = std::env::var("REDIS_APPROXIMATE_TRIMMING").is_ok();
+        handler = add_sink(
+            handler,
+            "redis",
+            Arc::new(PushToRedisStream::new(connection, retention, is_testnet).await),
+            sink_events("REDIS_EVENTS"),
+        );
+    }
+    run(handler, args.blocks).await;
+}
+
+/// Sinks listed in `OPTIONAL_SINKS` only log their failures, failures of the others stop indexing
+fn add_sink(
+    handler: MultiHandler,
+    name: &str,
+    sink: Arc<dyn ContractEventHandler>,
+    events: Option<HashSet<String>>,
+) -> MultiHandler {
+    let optional = std::env::var("OPTIONAL_SINKS")
+        .is_ok_and(|sinks| sinks.split(',').any(|optional| optional.trim() == name));
+    if optional {
+        handler.with_optional_handler(name, sink, events)
+    } else {
+        handler.with_handler(name, sink, events)
+    }
+}
+
+/// Comma-separated event ids that a sink is limited to
+fn sink_events(var: &str) -> Option<HashSet<String>> {
+    let events = std::env::var(var).ok()?;
+    Some(
+        events
+            .split(',')
+            .map(|event_id| event_id.trim().to_string())
+            .filter(|event_id| !event_id.is_empty())
+            .inspect(|event_id| {
+                assert!(
+                    EVENT_IDS.contains(&event_id.as_str()),
+                    "Unknown event {event_id} in ${var}"
+                )
+            })
+            .collect(),
     )
-    .unwrap();
-    let connection = ConnectionManager::new(client).await.unwrap();
+}
 
+async fn run(handler: impl ContractEventHandler + 'static, blocks: Vec<String>) {
+    let is_testnet = handler.is_testnet();
+    let tokens = Arc::new(TxtFileStorage::new("known_tokens.txt").await);
     let mut indexer = NewTokenIndexer::new(
-        PushToRedisStream::new(connection, 1_000, is_testnet).await,
+        handler,
         JsonRpcClient::connect(std::env::var("RPC_URL").unwrap_or(RPC_URL.to_string())),
-        TxtFileStorage::new("known_tokens.txt").await,
-    );
+        Arc::clone(&tokens),
+    )
+    .with_meme_cooking_campaigns(
+        JsonFileStorage::<HashMap<u64, MemeCookingCampaign>>::new("meme_cooking_campaigns.json")
+            .await,
+    )
+    .with_launch_timelines(
+        JsonFileStorage::<HashMap<u64, LaunchTimeline>>::new("meme_cooking_launches.json").await,
+    )
+    .with_ref_pools(JsonFileStorage::<RefPools>::new("ref_pools.json").await);
+    if let Ok(contracts) = std::env::var("MEME_COOKING_CONTRACTS") {
+        indexer = indexer.with_meme_cooking_contracts(
+            parse_meme_cooking_contracts(&contracts).expect("Invalid $MEME_COOKING_CONTRACTS"),
+        );
+    }
+    if let Ok(path) = std::env::var("MEME_COOKING_SCHEMAS") {
+        indexer = indexer.with_meme_cooking_schemas(
+            load_meme_cooking_schemas(&path)
+                .await
+                .expect("Failed to load $MEME_COOKING_SCHEMAS"),
+        );
+    }
+    if let Ok(gateway) = std::env::var("MEME_COOKING_REFERENCE_GATEWAY") {
+        indexer = indexer.with_meme_cooking_references(ReferenceFetcher::new(
+            if gateway.is_empty() {
+                DEFAULT_IPFS_GATEWAY.to_string()
+            } else {
+                gateway
+            },
+            Duration::from_secs(10),
+        ));
+    }
+    if std::env::var("SUPPLY_TRACKING").is_ok() {
+        indexer = indexer.with_supply_tracking(
+            JsonFileStorage::<HashMap<AccountId, TokenSupply>>::new("token_supply.json").await,
+            SupplyTrackerOptions::default(),
+        );
+    }
+    if std::env::var("HOLDERS_TRACKING").is_ok() {
+        let defaults = HoldersTrackerOptions::default();
+        indexer = indexer
+            .with_holders_tracking(HoldersTrackerOptions {
+                snapshot_interval_blocks: std::env::var("HOLDERS_SNAPSHOT_INTERVAL_BLOCKS")
+                    .map(|blocks| {
+                        blocks
+                            .parse()
+                            .expect("Invalid $HOLDERS_SNAPSHOT_INTERVAL_BLOCKS")
+                    })
+                    .unwrap_or(defaults.snapshot_interval_blocks),
+                lifetime_blocks: std::env::var("HOLDERS_TRACKING_BLOCKS")
+                    .map(|blocks| blocks.parse().expect("Invalid $HOLDERS_TRACKING_BLOCKS"))
+                    .unwrap_or(defaults.lifetime_blocks),
+            })
+            .await;
+    }
+    if std::env::var("METADATA_UPDATES").is_ok() {
+        indexer = indexer.with_metadata_updates(
+            JsonFileStorage::<HashMap<AccountId, StoredMetadata>>::new("token_metadata.json").await,
+            MetadataWatcherOptions::default(),
+        );
+    }
+    if std::env::var("RISK_SCORING").is_ok() {
+        let mut options = RiskScorerOptions::default();
+        if let Ok(path) = std::env::var("RISK_ALLOWLIST") {
+            options.allowlist = load_allowlist(&path)
+                .await
+                .expect("Failed to load $RISK_ALLOWLIST");
+        }
+        if let Ok(factories) = std::env::var("SPAM_FACTORIES") {
+            options.spam_factories = factories
+                .split(',')
+                .map(|factory| factory.trim().parse().expect("Invalid $SPAM_FACTORIES"))
+                .collect();
+        }
+        indexer = indexer.with_risk_scoring(options);
+    }
+    if let Ok(path) = std::env::var("LAUNCHPAD_RULES") {
+        indexer = indexer.with_launchpad_rules(
+            load_launchpad_rules(&path)
+                .await
+                .expect("Failed to load $LAUNCHPAD_RULES"),
+        );
+    }
+    #[cfg(feature = "api")]
+    if let Ok(addr) = std::env::var("API_ADDR") {
+        let listener = tokio::net::TcpListener::bind(&addr)
+            .await
+            .expect("Failed to bind $API_ADDR");
+        let router = api_router(tokens, indexer.status.clone(), is_testnet);
+        tokio::spawn(async move {
+            axum::serve(listener, router)
+                .await
+                .expect("API server failed");
+        });
+    }
 
     run_indexer(
         &mut indexer,
@@ -41,22 +349,20 @@ async fn main() {
             NeardataServerProvider::mainnet()
         },
         IndexerOptions {
-            range: if std::env::args().len() > 1 {
-                let msg =
-                    "Usage: `contract-indexer` or `contract-indexer [start-block] [end-block]`";
+            range: if !blocks.is_empty() {
                 BlockIterator::iterator(
-                    std::env::args()
-                        .nth(1)
-                        .expect(msg)
+                    blocks
+                        .first()
+                        .expect(USAGE)
                         .replace(['_', ',', ' ', '.'], "")
                         .parse()
-                        .expect(msg)
-                        ..=std::env::args()
-                            .nth(2)
-                            .expect(msg)
+                        .expect(USAGE)
+                        ..=blocks
+                            .get(1)
+                            .expect(USAGE)
                             .replace(['_', ',', ' ', '.'], "")
                             .parse()
-                            .expect(msg),
+                            .expect(USAGE),
                 )
             } else {
                 BlockIterator::AutoContinue(AutoContinue::default())
@@ -71,3 +377,48 @@ async fn main() {
     .await
     .expect("Indexer run failed");
 }
+
+const USAGE: &str = "Usage: `contract-indexer [--jsonl events.jsonl [--gzip] [--max-file-bytes N]] [start-block end-block]`";
+
+#[derive(Default)]
+struct Args {
+    jsonl: Option<String>,
+    gzip: bool,
+    max_file_bytes: Option<u64>,
+    /// Start and end of the range to index, if not empty
+    blocks: Vec<String>,
+}
+
+impl Args {
+    /// Options take their value after `=` or as the next argument
+    fn parse(args: impl IntoIterator<Item = String>) -> Self {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                parsed.blocks.push(arg);
+                continue;
+            }
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next_if(|next| !next.starts_with("--")))
+                    .filter(|value| !value.is_empty())
+                    .unwrap_or_else(|| panic!("No value for {name}. {USAGE}"))
+            };
+            match name {
+                "--jsonl" => parsed.jsonl = Some(value()),
+                "--gzip" => parsed.gzip = true,
+                "--max-file-bytes" => {
+                    parsed.max_file_bytes = Some(value().parse().expect("Invalid --max-file-bytes"))
+                }
+                _ => panic!("Unknown option {name}. {USAGE}"),
+            }
+        }
+        parsed
+    }
+}
@@ -1,8 +1,17 @@
 const MEME_COOKING_CONTRACT_TESTNET: &str = "factory.v10.meme-cooking.testnet";
 const MEME_COOKING_CONTRACT: &str = "meme-cooking.near";
+/// References of new memes that are downloaded at the same time, the indexer waits for a slot
+/// if there are more
+const MAX_REFERENCE_DOWNLOADS: usize = 16;
 
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
 
+use async_trait::async_trait;
 use inindexer::{
     near_indexer_primitives::{
         types::{AccountId, Balance},
@@ -11,59 +20,355 @@ use inindexer::{
     near_utils::{dec_format, EventLogData},
     IncompleteTransaction, TransactionReceipt,
 };
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{sync::Semaphore, task::JoinHandle};
 
-use crate::{ContractEventHandler, EventContext};
+use crate::{
+    launch_timeline::LaunchTimelines,
+    meme_cooking_reference::{MemeCookingReference, ReferenceFetcher},
+    BlockContext, ContractEventHandler, EventContext,
+};
 
-pub struct MemeCookingIndexer;
+pub struct MemeCookingIndexer {
+    campaigns: Arc<dyn MemeCookingCampaignStorage>,
+    pub contracts: Vec<MemeCookingContract>,
+    /// Mappings for versions of the contracts whose events differ from the structures in this module
+    pub schemas: Vec<MemeCookingEventSchema>,
+    /// If set, `reference` of new memes is downloaded and verified before the event is emitted
+    pub reference_fetcher: Option<ReferenceFetcher>,
+    /// New memes that wait for their `reference` to be downloaded, in the order they were created
+    reference_downloads: VecDeque<JoinHandle<(MemeCookingCreateMemeEvent, EventContext)>>,
+    reference_download_permits: Arc<Semaphore>,
+    /// Campaigns with deposits or withdrawals in the current block, saved at the end of the block
+    changed_campaigns: HashMap<u64, MemeCookingCampaign>,
+}
 
 impl MemeCookingIndexer {
-    pub async fn detect_meme_cooking<T: ContractEventHandler>(
+    pub fn new(
+        campaigns: impl MemeCookingCampaignStorage + 'static,
+        contracts: Vec<MemeCookingContract>,
+    ) -> Self {
+        Self {
+            campaigns: Arc::new(campaigns),
+            contracts,
+            schemas: Vec::new(),
+            reference_fetcher: None,
+            reference_downloads: VecDeque::new(),
+            reference_download_permits: Arc::new(Semaphore::new(MAX_REFERENCE_DOWNLOADS)),
+            changed_campaigns: HashMap::new(),
+        }
+    }
+
+    async fn get_campaign(&self, meme_id: u64) -> Option<MemeCookingCampaign> {
+        match self.changed_campaigns.get(&meme_id) {
+            Some(campaign) => Some(campaign.clone()),
+            None => self.campaigns.get_campaign(meme_id).await,
+        }
+    }
+
+    pub async fn detect_meme_cooking<T: ContractEventHandler + 'static>(
         &mut self,
         receipt: &TransactionReceipt,
         tx: &IncompleteTransaction,
         block: &StreamerMessage,
         handler: Arc<T>,
+        launches: &LaunchTimelines,
     ) {
-        let meme_cooking_contract = if handler.is_testnet() {
-            MEME_COOKING_CONTRACT_TESTNET
-        } else {
-            MEME_COOKING_CONTRACT
-        };
-        if receipt.receipt.receipt.receiver_id == meme_cooking_contract {
+        if let Some(contract) = self
+            .contracts
+            .iter()
+            .find(|contract| contract.account_id == receipt.receipt.receipt.receiver_id)
+            .cloned()
+        {
             for log in receipt.receipt.execution_outcome.outcome.logs.iter() {
-                if let Ok(event) = EventLogData::<MemeCookingCreateMemeEvent>::deserialize(log) {
-                    if event.standard == "meme-cooking" && event.event == "create_meme" {
-                        let context = EventContext {
-                            transaction_id: tx.transaction.transaction.hash,
-                            receipt_id: receipt.receipt.receipt.receipt_id,
-                            block_height: block.block.header.height,
-                            block_timestamp_nanosec: block.block.header.timestamp_nanosec as u128,
+                let Ok(mut event) = EventLogData::<serde_json::Value>::deserialize(log) else {
+                    continue;
+                };
+                if event.standard != "meme-cooking" {
+                    continue;
+                }
+                let schema = self
+                    .schemas
+                    .iter()
+                    .find(|schema| {
+                        schema.contract_id == contract.account_id && schema.version == event.version
+                    })
+                    .cloned();
+                if let Some(schema) = schema {
+                    schema.apply(&mut event);
+                } else if !contract.accepts_version(&event.version) {
+                    log::warn!(
+                        "Ignoring meme.cooking {} event with version {} from {}",
+                        event.event,
+                        event.version,
+                        contract.account_id
+                    );
+                    continue;
+                }
+                match event.event.as_str() {
+                    "create_meme" => {
+                        let Some(mut data) = event_data::<MemeCookingCreateMemeEvent>(event) else {
+                            continue;
                         };
-                        handler
-                            .handle_meme_cooking_new_meme(event.data, context)
+                        let context = EventContext::new(receipt, tx, block);
+                        self.campaigns
+                            .save_campaign(MemeCookingCampaign::new(&data))
                             .await;
+                        launches.record_meme(&data, &context).await;
+                        match &self.reference_fetcher {
+                            Some(reference_fetcher) => {
+                                // Downloading can take up to the timeout, so the meme is
+                                // emitted at the end of a later block instead of holding up this one
+                                let reference_fetcher = reference_fetcher.clone();
+                                let permit = Arc::clone(&self.reference_download_permits)
+                                    .acquire_owned()
+                                    .await
+                                    .expect("Semaphore is never closed");
+                                self.reference_downloads.push_back(tokio::spawn(async move {
+                                    data.reference_document = Some(
+                                        reference_fetcher
+                                            .fetch(&data.reference, &data.reference_hash)
+                                            .await,
+                                    );
+                                    drop(permit);
+                                    (data, context)
+                                }));
+                            }
+                            None => handler.handle_meme_cooking_new_meme(data, context).await,
+                        }
                     }
-                }
-                if let Ok(event) = EventLogData::<MemeCookingCreateTokenEvent>::deserialize(log) {
-                    if event.standard == "meme-cooking" && event.event == "create_token" {
-                        let context = EventContext {
-                            transaction_id: tx.transaction.transaction.hash,
-                            receipt_id: receipt.receipt.receipt.receipt_id,
-                            block_height: block.block.header.height,
-                            block_timestamp_nanosec: block.block.header.timestamp_nanosec as u128,
+                    "create_token" => {
+                        let Some(data) = event_data::<MemeCookingCreateTokenEvent>(event) else {
+                            continue;
                         };
-                        handler
-                            .handle_meme_cooking_new_token(event.data, context)
-                            .await;
+                        let context = EventContext::new(receipt, tx, block);
+                        // The token of a meme is never emitted before the meme itself
+                        self.finish_reference_downloads(Arc::clone(&handler)).await;
+                        launches.record_token(&data, &context).await;
+                        handler.handle_meme_cooking_new_token(data, context).await;
                     }
+                    "deposit" => {
+                        let Some(data) = event_data::<MemeCookingDepositEvent>(event) else {
+                            continue;
+                        };
+                        if let Some(mut campaign) = self.get_campaign(data.meme_id).await {
+                            campaign.deposit(data.account_id, data.amount);
+                            self.changed_campaigns.insert(campaign.meme_id, campaign);
+                        }
+                    }
+                    "withdraw" => {
+                        let Some(data) = event_data::<MemeCookingWithdrawEvent>(event) else {
+                            continue;
+                        };
+                        if let Some(mut campaign) = self.get_campaign(data.meme_id).await {
+                            campaign.withdraw(&data.account_id, data.amount);
+                            self.changed_campaigns.insert(campaign.meme_id, campaign);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub async fn check_campaign_deadlines<T: ContractEventHandler>(
+        &mut self,
+        block: &StreamerMessage,
+        handler: Arc<T>,
+    ) {
+        while self
+            .reference_downloads
+            .front()
+            .is_some_and(|download| download.is_finished())
+        {
+            self.emit_next_meme(Arc::clone(&handler)).await;
+        }
+        if !self.changed_campaigns.is_empty() {
+            let changed = std::mem::take(&mut self.changed_campaigns);
+            self.campaigns
+                .save_campaigns(changed.into_values().collect())
+                .await;
+        }
+        let block_timestamp_ms = block.block.header.timestamp_nanosec / 1_000_000;
+        for campaign in self.campaigns.campaigns_ended_by(block_timestamp_ms).await {
+            let context = BlockContext {
+                block_height: block.block.header.height,
+                block_timestamp_nanosec: block.block.header.timestamp_nanosec as u128,
+            };
+            let outcome = campaign.outcome();
+            let event = MemeCookingCampaignOutcomeEvent {
+                meme_id: campaign.meme_id,
+                end_timestamp_ms: campaign.end_timestamp_ms,
+                soft_cap: campaign.soft_cap,
+                hard_cap: campaign.hard_cap,
+                total_deposited: campaign.total_deposited,
+                participants: campaign.participants(),
+            };
+            match outcome {
+                MemeCookingCampaignOutcome::ReachedHardCap => {
+                    handler
+                        .handle_meme_cooking_reached_hard_cap(event, context)
+                        .await
+                }
+                MemeCookingCampaignOutcome::ReachedSoftCap => {
+                    handler
+                        .handle_meme_cooking_reached_soft_cap(event, context)
+                        .await
+                }
+                MemeCookingCampaignOutcome::ExpiredWithoutToken => {
+                    handler
+                        .handle_meme_cooking_expired_without_token(event, context)
+                        .await
                 }
             }
+            self.campaigns.remove_campaign(campaign.meme_id).await;
+        }
+    }
+
+    /// Waits for all references that are still downloading and emits their memes, so none are
+    /// lost when the indexer stops
+    pub async fn finish_reference_downloads<T: ContractEventHandler>(&mut self, handler: Arc<T>) {
+        while !self.reference_downloads.is_empty() {
+            self.emit_next_meme(Arc::clone(&handler)).await;
+        }
+    }
+
+    async fn emit_next_meme<T: ContractEventHandler>(&mut self, handler: Arc<T>) {
+        let Some(download) = self.reference_downloads.pop_front() else {
+            return;
+        };
+        match download.await {
+            Ok((data, context)) => handler.handle_meme_cooking_new_meme(data, context).await,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(err) => log::error!("Reference download was cancelled: {err}"),
         }
     }
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+/// `data` of an event with an accepted version that doesn't match the structures in this
+/// module means that the contract changed its events without changing the version, or that
+/// its [`MemeCookingEventSchema`] is wrong
+fn event_data<T: DeserializeOwned>(event: EventLogData<serde_json::Value>) -> Option<T> {
+    match serde_json::from_value(event.data) {
+        Ok(data) => Some(data),
+        Err(err) => {
+            log::warn!(
+                "Invalid meme.cooking {} event with version {}: {err}",
+                event.event,
+                event.version
+            );
+            None
+        }
+    }
+}
+
+/// A meme.cooking contract to watch. When meme.cooking deploys a new version of
+/// the factory, it can be added here alongside the old one, which keeps emitting
+/// events for memes that were created before the migration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemeCookingContract {
+    pub account_id: AccountId,
+    /// Version filter: NEP-297 `version`s of events that match the structures in this
+    /// module. Events with other versions are logged and ignored, unless there's a
+    /// [`MemeCookingEventSchema`] for them. If empty, all versions are accepted.
+    pub event_versions: Vec<String>,
+}
+
+impl MemeCookingContract {
+    pub fn defaults(testnet: bool) -> Vec<Self> {
+        vec![Self {
+            account_id: if testnet {
+                MEME_COOKING_CONTRACT_TESTNET
+            } else {
+                MEME_COOKING_CONTRACT
+            }
+            .parse()
+            .unwrap(),
+            event_versions: Vec::new(),
+        }]
+    }
+
+    pub fn accepts_version(&self, version: &str) -> bool {
+        self.event_versions.is_empty() || self.event_versions.iter().any(|v| v == version)
+    }
+}
+
+/// `account_id` or `account_id@version1|version2`
+impl FromStr for MemeCookingContract {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (account_id, versions) = match s.trim().split_once('@') {
+            Some((account_id, versions)) => (account_id, versions.split('|').collect()),
+            None => (s.trim(), Vec::new()),
+        };
+        Ok(Self {
+            account_id: account_id
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid meme.cooking contract {account_id}: {e}"))?,
+            event_versions: versions
+                .into_iter()
+                .map(|version| version.trim().to_string())
+                .collect(),
+        })
+    }
+}
+
+/// Parses a comma-separated list of [`MemeCookingContract`]s
+pub fn parse_meme_cooking_contracts(s: &str) -> anyhow::Result<Vec<MemeCookingContract>> {
+    s.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(MemeCookingContract::from_str)
+        .collect()
+}
+
+/// Maps events of one version of a contract to the structures in this module, so that
+/// a migration that renames events or their fields doesn't need a new release
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MemeCookingEventSchema {
+    pub contract_id: AccountId,
+    pub version: String,
+    /// Event names of this version to the names in this module, e.g.
+    /// `{"meme_created": "create_meme"}`. Events that aren't listed keep their name.
+    #[serde(default)]
+    pub events: HashMap<String, String>,
+    /// Event names (after renaming) to fields of the structures in this module, mapped to
+    /// JSON pointers (RFC 6901) into `data`, e.g. `{"create_meme": {"owner": "/creator"}}`.
+    /// Fields that aren't listed are read from `data` as they are.
+    #[serde(default)]
+    pub fields: HashMap<String, HashMap<String, String>>,
+}
+
+impl MemeCookingEventSchema {
+    pub fn apply(&self, event: &mut EventLogData<serde_json::Value>) {
+        if let Some(name) = self.events.get(&event.event) {
+            event.event = name.clone();
+        }
+        let Some(fields) = self.fields.get(&event.event) else {
+            return;
+        };
+        let mut data = match &event.data {
+            serde_json::Value::Object(data) => data.clone(),
+            _ => serde_json::Map::new(),
+        };
+        for (field, pointer) in fields {
+            match event.data.pointer(pointer) {
+                Some(value) => data.insert(field.clone(), value.clone()),
+                None => data.remove(field),
+            };
+        }
+        event.data = serde_json::Value::Object(data);
+    }
+}
+
+/// Reads a JSON array of [`MemeCookingEventSchema`]s
+pub async fn load_meme_cooking_schemas(
+    path: impl AsRef<Path>,
+) -> anyhow::Result<Vec<MemeCookingEventSchema>> {
+    Ok(serde_json::from_slice(&tokio::fs::read(path).await?)?)
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct MemeCookingCreateMemeEvent {
     pub meme_id: u64,
     pub owner: AccountId,
@@ -79,11 +384,14 @@ pub struct MemeCookingCreateMemeEvent {
     pub deposit_token_id: AccountId,
     #[serde(with = "dec_format")]
     pub soft_cap: Balance,
-    #[serde(with = "dec_format")]
+    #[serde(default, with = "dec_format")]
     pub hard_cap: Option<Balance>,
+    /// Contents of `reference`, if [`MemeCookingIndexer::reference_fetcher`] is set
+    #[serde(skip)]
+    pub reference_document: Option<MemeCookingReference>,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct MemeCookingCreateTokenEvent {
     pub meme_id: u64,
     pub token_id: AccountId,
@@ -91,3 +399,107 @@ pub struct MemeCookingCreateTokenEvent {
     pub total_supply: Balance,
     pub pool_id: u64,
 }
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct MemeCookingDepositEvent {
+    pub meme_id: u64,
+    pub account_id: AccountId,
+    #[serde(with = "dec_format")]
+    pub amount: Balance,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct MemeCookingWithdrawEvent {
+    pub meme_id: u64,
+    pub account_id: AccountId,
+    #[serde(with = "dec_format")]
+    pub amount: Balance,
+}
+
+/// Deposits of a meme that hasn't reached its `end_timestamp_ms` yet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemeCookingCampaign {
+    pub meme_id: u64,
+    pub end_timestamp_ms: u64,
+    pub soft_cap: Balance,
+    pub hard_cap: Option<Balance>,
+    pub total_deposited: Balance,
+    pub deposits: HashMap<AccountId, Balance>,
+}
+
+impl MemeCookingCampaign {
+    pub fn new(event: &MemeCookingCreateMemeEvent) -> Self {
+        Self {
+            meme_id: event.meme_id,
+            end_timestamp_ms: event.end_timestamp_ms,
+            soft_cap: event.soft_cap,
+            hard_cap: event.hard_cap,
+            total_deposited: 0,
+            deposits: HashMap::new(),
+        }
+    }
+
+    pub fn deposit(&mut self, account_id: AccountId, amount: Balance) {
+        self.total_deposited = self.total_deposited.saturating_add(amount);
+        let deposited = self.deposits.entry(account_id).or_default();
+        *deposited = deposited.saturating_add(amount);
+    }
+
+    pub fn withdraw(&mut self, account_id: &AccountId, amount: Balance) {
+        if let Some(deposited) = self.deposits.get_mut(account_id) {
+            let amount = amount.min(*deposited);
+            *deposited -= amount;
+            self.total_deposited -= amount;
+            if *deposited == 0 {
+                self.deposits.remove(account_id);
+            }
+        }
+    }
+
+    pub fn participants(&self) -> usize {
+        self.deposits.len()
+    }
+
+    pub fn outcome(&self) -> MemeCookingCampaignOutcome {
+        if self
+            .hard_cap
+            .is_some_and(|hard_cap| self.total_deposited >= hard_cap)
+        {
+            MemeCookingCampaignOutcome::ReachedHardCap
+        } else if self.total_deposited >= self.soft_cap {
+            MemeCookingCampaignOutcome::ReachedSoftCap
+        } else {
+            MemeCookingCampaignOutcome::ExpiredWithoutToken
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemeCookingCampaignOutcome {
+    ReachedHardCap,
+    ReachedSoftCap,
+    ExpiredWithoutToken,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemeCookingCampaignOutcomeEvent {
+    pub meme_id: u64,
+    pub end_timestamp_ms: u64,
+    pub soft_cap: Balance,
+    pub hard_cap: Option<Balance>,
+    pub total_deposited: Balance,
+    pub participants: usize,
+}
+
+#[async_trait]
+pub trait MemeCookingCampaignStorage: Send + Sync {
+    async fn get_campaign(&self, meme_id: u64) -> Option<MemeCookingCampaign>;
+    async fn save_campaign(&self, campaign: MemeCookingCampaign);
+    async fn save_campaigns(&self, campaigns: Vec<MemeCookingCampaign>) {
+        for campaign in campaigns {
+            self.save_campaign(campaign).await;
+        }
+    }
+    async fn remove_campaign(&self, meme_id: u64);
+    async fn campaigns_ended_by(&self, timestamp_ms: u64) -> Vec<MemeCookingCampaign>;
+}
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const DEFAULT_IPFS_GATEWAY: &str = "https://ipfs.io/ipfs";
+/// Reference documents are small JSON objects, downloads are stopped after this many bytes
+pub const MAX_REFERENCE_BYTES: usize = 256 * 1024;
+
+/// Downloads `reference` documents of new memes and checks them against `reference_hash`.
+/// Only IPFS references are downloaded, and only through the configured gateway, since
+/// `reference` is set by whoever creates the meme and can point anywhere.
+#[derive(Clone)]
+pub struct ReferenceFetcher {
+    client: reqwest::Client,
+    ipfs_gateway: String,
+}
+
+impl ReferenceFetcher {
+    pub fn new(ipfs_gateway: impl Into<String>, timeout: Duration) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .expect("Failed to create HTTP client"),
+            ipfs_gateway: ipfs_gateway.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Turns an IPFS CID (with or without `ipfs://`) into a gateway URL. HTTP(S) URLs are returned as is.
+    pub fn resolve(&self, reference: &str) -> String {
+        if reference.starts_with("https://") || reference.starts_with("http://") {
+            reference.to_string()
+        } else {
+            let cid = reference.strip_prefix("ipfs://").unwrap_or(reference);
+            format!("{}/{cid}", self.ipfs_gateway)
+        }
+    }
+
+    /// The CID and the path after it, from a CID (with or without `ipfs://`) or a URL of
+    /// any path gateway (`https://gateway/ipfs/{cid}`)
+    pub fn ipfs_path(reference: &str) -> Option<&str> {
+        let path = if reference.starts_with("https://") || reference.starts_with("http://") {
+            reference.split_once("/ipfs/")?.1
+        } else {
+            reference.strip_prefix("ipfs://").unwrap_or(reference)
+        };
+        let valid = !path.is_empty()
+            && path.split('/').all(|segment| {
+                !segment.is_empty()
+                    && segment != "."
+                    && segment != ".."
+                    && segment
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+            });
+        valid.then_some(path)
+    }
+
+    pub async fn fetch(&self, reference: &str, reference_hash: &str) -> MemeCookingReference {
+        let Some(ipfs_path) = Self::ipfs_path(reference) else {
+            return MemeCookingReference::with_status(ReferenceStatus::NotIpfs);
+        };
+        let url = format!("{}/{ipfs_path}", self.ipfs_gateway);
+        let contents = match self.download(&url).await {
+            Ok(contents) => contents,
+            Err(err) => {
+                log::warn!("Failed to fetch meme.cooking reference {reference}: {err}");
+                return MemeCookingReference::with_status(ReferenceStatus::FetchFailed);
+            }
+        };
+        if BASE64.encode(Sha256::digest(&contents)) != reference_hash {
+            return MemeCookingReference::with_status(ReferenceStatus::HashMismatch);
+        }
+        let Ok(document) = serde_json::from_slice::<ReferenceDocument>(&contents) else {
+            return MemeCookingReference::with_status(ReferenceStatus::InvalidDocument);
+        };
+        MemeCookingReference {
+            status: ReferenceStatus::Verified,
+            description: document.description,
+            image: document.image.map(|image| self.resolve(&image)),
+            twitter: document.twitter,
+            telegram: document.telegram,
+            website: document.website,
+        }
+    }
+
+    async fn download(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let mut response = self.client.get(url).send().await?.error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_REFERENCE_BYTES as u64)
+        {
+            anyhow::bail!("Larger than {MAX_REFERENCE_BYTES} bytes");
+        }
+        let mut contents = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if contents.len() + chunk.len() > MAX_REFERENCE_BYTES {
+                anyhow::bail!("Larger than {MAX_REFERENCE_BYTES} bytes");
+            }
+            contents.extend_from_slice(&chunk);
+        }
+        Ok(contents)
+    }
+}
+
+/// What meme.cooking frontend uploads as `reference`
+#[derive(Debug, Deserialize)]
+struct ReferenceDocument {
+    description: Option<String>,
+    image: Option<String>,
+    #[serde(alias = "twitterLink")]
+    twitter: Option<String>,
+    #[serde(alias = "telegramLink")]
+    telegram: Option<String>,
+    website: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemeCookingReference {
+    pub status: ReferenceStatus,
+    pub description: Option<String>,
+    /// URL of the image, with IPFS CIDs resolved through the gateway
+    pub image: Option<String>,
+    pub twitter: Option<String>,
+    pub telegram: Option<String>,
+    pub website: Option<String>,
+}
+
+impl MemeCookingReference {
+    fn with_status(status: ReferenceStatus) -> Self {
+        Self {
+            status,
+            description: None,
+            image: None,
+            twitter: None,
+            telegram: None,
+            website: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceStatus {
+    Verified,
+    /// The document was downloaded, but its sha256 is different from `reference_hash`
+    HashMismatch,
+    /// The hash matches, but the document is not a JSON object
+    InvalidDocument,
+    /// Failed to download, or the document is larger than [`MAX_REFERENCE_BYTES`]
+    FetchFailed,
+    /// `reference` is not an IPFS CID or a gateway URL, so it's not downloaded
+    NotIpfs,
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use inindexer::{
+    near_indexer_primitives::{
+        types::{AccountId, BlockHeight},
+        views::{ActionView, ReceiptEnumView},
+        StreamerMessage,
+    },
+    TransactionReceipt,
+};
+use near_jsonrpc_client::JsonRpcClient;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    metadata_validation::validate_metadata,
+    new_nep141::{ft_metadata, FtMetadata, HandledTokensStorage},
+    BlockContext, ContractEventHandler,
+};
+
+/// Methods of common token contracts that change metadata
+pub const METADATA_METHODS: &[&str] = &[
+    "set_metadata",
+    "update_metadata",
+    "set_ft_metadata",
+    "ft_set_metadata",
+    "set_icon",
+    "update_icon",
+    "set_reference",
+    "set_name",
+    "set_symbol",
+];
+
+pub struct MetadataWatcherOptions {
+    /// Active tokens are re-checked if they weren't checked for this many blocks. Tokens
+    /// that had no receipts for this many blocks are no longer active.
+    pub recheck_interval_blocks: BlockHeight,
+    /// Limit of periodic checks per block
+    pub periodic_checks_per_block: usize,
+    /// Checks triggered by calls and upgrades are delayed until this many blocks
+    /// have passed since the previous check of the token
+    pub min_check_interval_blocks: BlockHeight,
+}
+
+impl Default for MetadataWatcherOptions {
+    fn default() -> Self {
+        Self {
+            recheck_interval_blocks: 100_000,
+            periodic_checks_per_block: 1,
+            min_check_interval_blocks: 10,
+        }
+    }
+}
+
+/// Re-fetches `ft_metadata` of known tokens when they call one of [`METADATA_METHODS`]
+/// or get upgraded, and periodically for tokens that are used.
+pub struct MetadataWatcher {
+    tokens: Arc<dyn HandledTokensStorage>,
+    storage: Arc<dyn TokenMetadataStorage>,
+    rpc_client: JsonRpcClient,
+    options: MetadataWatcherOptions,
+    triggered: HashSet<AccountId>,
+    active: HashMap<AccountId, ActiveToken>,
+}
+
+struct ActiveToken {
+    last_receipt_block: BlockHeight,
+    last_checked_block: Option<BlockHeight>,
+}
+
+impl MetadataWatcher {
+    pub fn new(
+        tokens: Arc<dyn HandledTokensStorage>,
+        storage: impl TokenMetadataStorage + 'static,
+        rpc_client: JsonRpcClient,
+        options: MetadataWatcherOptions,
+    ) -> Self {
+        Self {
+            tokens,
+            storage: Arc::new(storage),
+            rpc_client,
+            options,
+            triggered: HashSet::new(),
+            active: HashMap::new(),
+        }
+    }
+
+    pub fn storage(&self) -> Arc<dyn TokenMetadataStorage> {
+        Arc::clone(&self.storage)
+    }
+
+    pub async fn watch_metadata(&mut self, receipt: &TransactionReceipt) {
+        let token_id = &receipt.receipt.receipt.receiver_id;
+        if let Some(active) = self.active.get_mut(token_id) {
+            active.last_receipt_block = receipt.block_height;
+        } else {
+            if !self.tokens.is_already_indexed(token_id).await {
+                return;
+            }
+            self.active.insert(
+                token_id.clone(),
+                ActiveToken {
+                    last_receipt_block: receipt.block_height,
+                    last_checked_block: None,
+                },
+            );
+        }
+        let ReceiptEnumView::Action { actions, .. } = &receipt.receipt.receipt.receipt else {
+            return;
+        };
+        if actions.iter().any(|action| match action {
+            ActionView::DeployContract { .. } => true,
+            ActionView::FunctionCall { method_name, .. } => {
+                METADATA_METHODS.contains(&method_name.as_str())
+            }
+            _ => false,
+        }) {
+            self.triggered.insert(token_id.clone());
+        }
+    }
+
+    pub async fn process_block_end<T: ContractEventHandler>(
+        &mut self,
+        block: &StreamerMessage,
+        handler: Arc<T>,
+    ) {
+        let context = BlockContext {
+            block_height: block.block.header.height,
+            block_timestamp_nanosec: block.block.header.timestamp_nanosec as u128,
+        };
+        let options = &self.options;
+        self.active.retain(|_, active| {
+            active.last_receipt_block + options.recheck_interval_blocks > context.block_height
+        });
+        let checked_since = |active: Option<&ActiveToken>, interval: BlockHeight| {
+            active
+                .and_then(|active| active.last_checked_block)
+                .is_some_and(|last_checked| last_checked + interval > context.block_height)
+        };
+        // Triggered tokens that were checked recently stay triggered until the interval passes
+        let (throttled, to_check): (HashSet<_>, HashSet<_>) = std::mem::take(&mut self.triggered)
+            .into_iter()
+            .partition(|token_id| {
+                checked_since(self.active.get(token_id), options.min_check_interval_blocks)
+            });
+        self.triggered = throttled;
+        let mut to_check = to_check.into_iter().collect::<Vec<_>>();
+        to_check.extend(
+            self.active
+                .iter()
+                .filter(|(token_id, active)| {
+                    !to_check.contains(token_id)
+                        && !self.triggered.contains(*token_id)
+                        && !checked_since(Some(active), options.recheck_interval_blocks)
+                })
+                .map(|(token_id, _)| token_id.clone())
+                .take(options.periodic_checks_per_block)
+                .collect::<Vec<_>>(),
+        );
+        for token_id in to_check {
+            if let Some(active) = self.active.get_mut(&token_id) {
+                active.last_checked_block = Some(context.block_height);
+            }
+            self.check(token_id, &context, &*handler).await;
+        }
+        self.storage.flush().await;
+    }
+
+    async fn check<T: ContractEventHandler>(
+        &self,
+        token_id: AccountId,
+        context: &BlockContext,
+        handler: &T,
+    ) {
+        let Ok(Some(mut metadata)) =
+            ft_metadata(&token_id, context.block_height, &self.rpc_client).await
+        else {
+            return;
+        };
+        validate_metadata(&mut metadata);
+        let current = StoredMetadata::new(metadata.clone());
+        let previous = self.storage.get_metadata(&token_id).await;
+        if previous.as_ref().map(|previous| &previous.hash) == Some(&current.hash) {
+            return;
+        }
+        self.storage
+            .save_metadata(token_id.clone(), current.clone())
+            .await;
+        // The first check only remembers the metadata
+        let Some(previous) = previous else {
+            return;
+        };
+        log::info!("Metadata of {token_id} was updated");
+        handler
+            .handle_token_metadata_updated(
+                TokenMetadataUpdatedEvent {
+                    token_id,
+                    changes: previous.diff(&current),
+                    previous_hash: previous.hash,
+                    hash: current.hash,
+                    metadata,
+                },
+                context.clone(),
+            )
+            .await;
+    }
+}
+
+/// Last known metadata of a token. The icon is replaced with its hash to keep the storage small.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredMetadata {
+    /// Base64-encoded sha256 of the metadata JSON, including the full icon
+    pub hash: String,
+    pub metadata: FtMetadata,
+}
+
+impl StoredMetadata {
+    pub fn new(mut metadata: FtMetadata) -> Self {
+        let hash = hash(&serde_json::to_vec(&metadata).unwrap());
+        metadata.icon = metadata.icon.map(|icon| hash_icon(&icon));
+        Self { hash, metadata }
+    }
+
+    /// Fields that are different in `other`. Icons are compared by their hashes.
+    pub fn diff(&self, other: &StoredMetadata) -> Vec<MetadataChange> {
+        let (Value::Object(old), Value::Object(new)) = (
+            serde_json::to_value(&self.metadata).unwrap(),
+            serde_json::to_value(&other.metadata).unwrap(),
+        ) else {
+            unreachable!("FtMetadata is serialized as an object");
+        };
+        let mut changes = new
+            .into_iter()
+            .filter_map(|(field, new)| {
+                let old = old.get(&field).cloned().unwrap_or(Value::Null);
+                (old != new).then_some(MetadataChange { field, old, new })
+            })
+            .collect::<Vec<_>>();
+        changes.sort_by(|a, b| a.field.cmp(&b.field));
+        changes
+    }
+}
+
+fn hash(data: &[u8]) -> String {
+    BASE64.encode(Sha256::digest(data))
+}
+
+fn hash_icon(icon: &str) -> String {
+    format!("sha256:{}", hash(icon.as_bytes()))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetadataChange {
+    pub field: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenMetadataUpdatedEvent {
+    pub token_id: AccountId,
+    /// Changed fields. For `icon`, old and new values are `sha256:{base64 hash}`.
+    pub changes: Vec<MetadataChange>,
+    pub previous_hash: String,
+    pub hash: String,
+    /// New metadata, with the full icon
+    pub metadata: FtMetadata,
+}
+
+/// Remembers metadata of a new token, so that its first update is reported
+/// instead of only being saved by the first check
+pub async fn seed_metadata(
+    storage: &dyn TokenMetadataStorage,
+    token_id: &AccountId,
+    metadata: &FtMetadata,
+) {
+    if storage.get_metadata(token_id).await.is_none() {
+        storage
+            .save_metadata(token_id.clone(), StoredMetadata::new(metadata.clone()))
+            .await;
+    }
+}
+
+#[async_trait]
+pub trait TokenMetadataStorage: Send + Sync {
+    async fn get_metadata(&self, token_id: &AccountId) -> Option<StoredMetadata>;
+    async fn save_metadata(&self, token_id: AccountId, metadata: StoredMetadata);
+    /// Called at the end of every block, for storages that persist saved metadata in batches
+    async fn flush(&self) {}
+}
@@ -0,0 +1,411 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use quick_xml::{
+    events::{BytesEnd, BytesStart, Event},
+    Reader, Writer,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::new_nep141::FtMetadata;
+
+/// Icons larger than this are removed, since they're embedded in every response with metadata
+pub const MAX_ICON_BYTES: usize = 100 * 1024;
+
+const ALLOWED_ICON_TYPES: &[&str] = &[
+    "image/svg+xml",
+    "image/png",
+    "image/jpeg",
+    "image/webp",
+    "image/gif",
+    "image/avif",
+];
+
+/// Checks the NEP-148 icon of a token. Icons that can't be displayed safely are removed,
+/// and SVGs are reduced to an allowlist of elements and attributes and re-encoded as base64.
+pub fn validate_metadata(metadata: &mut FtMetadata) -> Vec<MetadataIssue> {
+    let Some(icon) = &metadata.icon else {
+        return vec![MetadataIssue::MissingIcon];
+    };
+    match validate_icon(icon) {
+        Ok((icon, issues)) => {
+            metadata.icon = Some(icon);
+            issues
+        }
+        Err(issue) => {
+            metadata.icon = None;
+            vec![issue]
+        }
+    }
+}
+
+fn validate_icon(icon: &str) -> Result<(String, Vec<MetadataIssue>), MetadataIssue> {
+    let Some((header, data)) = icon
+        .trim()
+        .strip_prefix("data:")
+        .and_then(|icon| icon.split_once(','))
+    else {
+        return Err(MetadataIssue::IconNotDataUrl);
+    };
+    let mut parameters = header.split(';');
+    let mime_type = parameters.next().unwrap_or_default().to_ascii_lowercase();
+    let is_base64 = parameters.any(|parameter| parameter.eq_ignore_ascii_case("base64"));
+    if !ALLOWED_ICON_TYPES.contains(&mime_type.as_str()) {
+        return Err(MetadataIssue::UnsupportedIconType);
+    }
+    let bytes = if is_base64 {
+        // Some icons contain line breaks or spaces
+        let data = data
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect::<String>();
+        BASE64
+            .decode(data)
+            .map_err(|_| MetadataIssue::InvalidIconEncoding)?
+    } else {
+        percent_decode(data).ok_or(MetadataIssue::InvalidIconEncoding)?
+    };
+    if bytes.len() > MAX_ICON_BYTES {
+        return Err(MetadataIssue::IconTooLarge);
+    }
+
+    let mut issues = Vec::new();
+    if mime_type == "image/svg+xml" {
+        let svg = String::from_utf8(bytes).map_err(|_| MetadataIssue::InvalidIconEncoding)?;
+        let (sanitized, removed) = sanitize_svg(&svg).ok_or(MetadataIssue::InvalidSvg)?;
+        if removed {
+            issues.push(MetadataIssue::IconScriptRemoved);
+        }
+        return Ok((
+            format!("data:{mime_type};base64,{}", BASE64.encode(sanitized)),
+            issues,
+        ));
+    }
+    if !matches_magic_bytes(&mime_type, &bytes) {
+        issues.push(MetadataIssue::IconTypeMismatch);
+    }
+    Ok((
+        format!("data:{mime_type};base64,{}", BASE64.encode(bytes)),
+        issues,
+    ))
+}
+
+/// SVG elements that are kept. Anything else, like `<script>` or `<foreignObject>`, is removed with its children.
+const SVG_ELEMENTS: &[&str] = &[
+    "svg",
+    "g",
+    "defs",
+    "symbol",
+    "use",
+    "title",
+    "desc",
+    "a",
+    "style",
+    "path",
+    "rect",
+    "circle",
+    "ellipse",
+    "line",
+    "polyline",
+    "polygon",
+    "text",
+    "tspan",
+    "image",
+    "linearGradient",
+    "radialGradient",
+    "stop",
+    "clipPath",
+    "mask",
+    "pattern",
+    "filter",
+    "feBlend",
+    "feColorMatrix",
+    "feComposite",
+    "feDropShadow",
+    "feFlood",
+    "feGaussianBlur",
+    "feMerge",
+    "feMergeNode",
+    "feOffset",
+];
+
+/// SVG attributes that are kept, event handlers and everything else is removed
+const SVG_ATTRIBUTES: &[&str] = &[
+    "xmlns",
+    "xmlns:xlink",
+    "version",
+    "id",
+    "class",
+    "style",
+    "viewBox",
+    "preserveAspectRatio",
+    "width",
+    "height",
+    "x",
+    "y",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "cx",
+    "cy",
+    "r",
+    "rx",
+    "ry",
+    "fx",
+    "fy",
+    "d",
+    "points",
+    "transform",
+    "opacity",
+    "visibility",
+    "display",
+    "fill",
+    "fill-opacity",
+    "fill-rule",
+    "stroke",
+    "stroke-width",
+    "stroke-opacity",
+    "stroke-linecap",
+    "stroke-linejoin",
+    "stroke-miterlimit",
+    "stroke-dasharray",
+    "stroke-dashoffset",
+    "clip-path",
+    "clip-rule",
+    "clipPathUnits",
+    "mask",
+    "maskUnits",
+    "maskContentUnits",
+    "filter",
+    "filterUnits",
+    "primitiveUnits",
+    "gradientUnits",
+    "gradientTransform",
+    "spreadMethod",
+    "offset",
+    "stop-color",
+    "stop-opacity",
+    "patternUnits",
+    "patternContentUnits",
+    "patternTransform",
+    "in",
+    "in2",
+    "result",
+    "mode",
+    "type",
+    "values",
+    "operator",
+    "k1",
+    "k2",
+    "k3",
+    "k4",
+    "dx",
+    "dy",
+    "stdDeviation",
+    "flood-color",
+    "flood-opacity",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-weight",
+    "letter-spacing",
+    "text-anchor",
+    "dominant-baseline",
+    "href",
+    "xlink:href",
+];
+
+/// Parses the SVG and keeps only [`SVG_ELEMENTS`] and [`SVG_ATTRIBUTES`]. Links can only point to
+/// elements of the same SVG or to embedded images, and `javascript:` URLs are removed even when
+/// they're escaped. Returns the SVG and whether anything was removed, or `None` if the icon isn't
+/// a well-formed SVG document.
+pub fn sanitize_svg(svg: &str) -> Option<(String, bool)> {
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Vec::new());
+    // Names of the kept elements that are open
+    let mut open = Vec::new();
+    // Depth inside an element that is removed
+    let mut removed_depth = 0;
+    let mut removed = false;
+    let mut has_root = false;
+    loop {
+        match reader.read_event().ok()? {
+            Event::Eof => break,
+            Event::Start(element) => {
+                if removed_depth > 0 {
+                    removed_depth += 1;
+                    continue;
+                }
+                if open.is_empty() {
+                    if element.name().as_ref() != b"svg" {
+                        return None;
+                    }
+                    has_root = true;
+                }
+                match sanitize_element(&element) {
+                    Some((sanitized, removed_attributes)) => {
+                        removed |= removed_attributes;
+                        open.push(String::from_utf8_lossy(element.name().as_ref()).into_owned());
+                        writer.write_event(Event::Start(sanitized)).ok()?;
+                    }
+                    None => {
+                        removed = true;
+                        removed_depth = 1;
+                    }
+                }
+            }
+            Event::Empty(element) => {
+                if removed_depth > 0 {
+                    continue;
+                }
+                if open.is_empty() {
+                    if element.name().as_ref() != b"svg" {
+                        return None;
+                    }
+                    has_root = true;
+                }
+                match sanitize_element(&element) {
+                    Some((sanitized, removed_attributes)) => {
+                        removed |= removed_attributes;
+                        writer.write_event(Event::Empty(sanitized)).ok()?;
+                    }
+                    None => removed = true,
+                }
+            }
+            Event::End(_) => {
+                if removed_depth > 0 {
+                    removed_depth -= 1;
+                    continue;
+                }
+                let name = open.pop()?;
+                writer.write_event(Event::End(BytesEnd::new(name))).ok()?;
+            }
+            Event::Text(text) if removed_depth == 0 => {
+                // CSS of <style> elements can contain URLs too
+                if open.last().is_some_and(|name| name == "style")
+                    && !is_safe_value(&text.unescape().ok()?)
+                {
+                    removed = true;
+                } else {
+                    writer.write_event(Event::Text(text)).ok()?;
+                }
+            }
+            Event::CData(data) if removed_depth == 0 => {
+                if open.last().is_some_and(|name| name == "style")
+                    && !is_safe_value(&String::from_utf8_lossy(&data))
+                {
+                    removed = true;
+                } else {
+                    writer.write_event(Event::CData(data)).ok()?;
+                }
+            }
+            Event::Decl(declaration) => {
+                writer.write_event(Event::Decl(declaration)).ok()?;
+            }
+            Event::Text(_) | Event::CData(_) | Event::Comment(_) => {}
+            // Processing instructions can load stylesheets, and DTDs can define entities
+            Event::PI(_) | Event::DocType(_) => removed = true,
+        }
+    }
+    if !has_root || !open.is_empty() || removed_depth > 0 {
+        return None;
+    }
+    Some((String::from_utf8(writer.into_inner()).ok()?, removed))
+}
+
+/// Returns the element with only allowed attributes and whether any attributes were
+/// removed, or `None` if the element isn't allowed
+fn sanitize_element(element: &BytesStart) -> Option<(BytesStart<'static>, bool)> {
+    let name = std::str::from_utf8(element.name().as_ref())
+        .ok()?
+        .to_string();
+    if !SVG_ELEMENTS.contains(&name.as_str()) {
+        return None;
+    }
+    let mut sanitized = BytesStart::new(name);
+    let mut removed = false;
+    for attribute in element.attributes() {
+        let attribute = attribute.ok().and_then(|attribute| {
+            let key = std::str::from_utf8(attribute.key.as_ref())
+                .ok()?
+                .to_string();
+            let value = attribute.unescape_value().ok()?.into_owned();
+            Some((key, value))
+        });
+        match attribute {
+            Some((key, value)) if is_allowed_attribute(&key, &value) => {
+                sanitized.push_attribute((key.as_str(), value.as_str()));
+            }
+            _ => removed = true,
+        }
+    }
+    Some((sanitized, removed))
+}
+
+fn is_allowed_attribute(key: &str, value: &str) -> bool {
+    if !SVG_ATTRIBUTES.contains(&key) || !is_safe_value(value) {
+        return false;
+    }
+    if key == "href" || key == "xlink:href" {
+        let value = value.trim();
+        return value.starts_with('#') || value.starts_with("data:image/");
+    }
+    true
+}
+
+/// Checks unescaped attribute values and CSS for script URLs. Browsers ignore whitespace
+/// and control characters inside the scheme, so they're ignored here as well.
+fn is_safe_value(value: &str) -> bool {
+    let value = value
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    !["javascript:", "vbscript:", "expression(", "@import"]
+        .iter()
+        .any(|pattern| value.contains(pattern))
+}
+
+fn matches_magic_bytes(mime_type: &str, bytes: &[u8]) -> bool {
+    match mime_type {
+        "image/png" => bytes.starts_with(b"\x89PNG\r\n\x1a\n"),
+        "image/jpeg" => bytes.starts_with(b"\xff\xd8\xff"),
+        "image/gif" => bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a"),
+        "image/webp" => bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP",
+        "image/avif" => bytes.len() >= 12 && &bytes[4..12] == b"ftypavif",
+        _ => true,
+    }
+}
+
+fn percent_decode(data: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut input = data.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            let hex = [input.next()?, input.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    Some(bytes)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataIssue {
+    MissingIcon,
+    /// The icon is a link instead of a data URL, so it was removed
+    IconNotDataUrl,
+    /// The icon is not an image that browsers can display, so it was removed
+    UnsupportedIconType,
+    /// The icon couldn't be decoded, so it was removed
+    InvalidIconEncoding,
+    /// The icon is larger than [`MAX_ICON_BYTES`], so it was removed
+    IconTooLarge,
+    /// The icon's contents don't match its MIME type
+    IconTypeMismatch,
+    /// The SVG icon isn't well-formed XML, so it was removed
+    InvalidSvg,
+    /// Scripts or other elements and attributes that are not allowed were removed from the SVG icon
+    IconScriptRemoved,
+}
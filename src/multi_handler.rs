@@ -0,0 +1,376 @@
+use std::{collections::HashSet, future::Future, sync::Arc};
+
+use async_trait::async_trait;
+use inindexer::near_indexer_primitives::types::AccountId;
+use intear_events::events::newcontract::{
+    meme_cooking_meme::NewMemeCookingMemeEvent, meme_cooking_token::NewMemeCookingTokenEvent,
+    nep141::NewContractNep141Event,
+};
+
+use crate::bridge;
+use crate::events::{
+    BridgedTokenCreatedEvent, LaunchpadTokenCreatedEvent, MemeExpiredWithoutTokenEvent,
+    MemeReachedHardCapEvent, MemeReachedSoftCapEvent, TokenFirstPoolEvent,
+    TokenHoldersSnapshotEvent, TokenInitialDistributionEvent, TokenMetadataUpdatedEvent,
+    TokenSupplyChangedEvent,
+};
+use crate::holders;
+use crate::initial_distribution;
+use crate::launchpad;
+use crate::meme_cooking::{
+    MemeCookingCampaignOutcomeEvent, MemeCookingCreateMemeEvent, MemeCookingCreateTokenEvent,
+};
+use crate::metadata_updates;
+use crate::new_nep141::NewNep141Event;
+use crate::ref_finance;
+use crate::supply;
+use crate::{BlockContext, ContractEventHandler, EventContext};
+
+struct Sink {
+    name: String,
+    handler: Arc<dyn ContractEventHandler>,
+    /// Event ids, or all events if `None`
+    events: Option<HashSet<String>>,
+    /// Failures stop indexing instead of being logged
+    required: bool,
+}
+
+/// Sends each event to several handlers at once. Every handler runs in its own task, so if
+/// one of them panics (handlers panic when they fail to write an event), the others still
+/// get the event. A panic of a required handler is then raised again, which stops indexing,
+/// and a panic of an optional handler is only logged, so that event is missing from it.
+///
+/// The next event is only sent once all handlers are done with the current one, so the
+/// slowest handler sets the pace for the others.
+pub struct MultiHandler {
+    sinks: Vec<Sink>,
+    testnet: bool,
+}
+
+impl MultiHandler {
+    pub fn new(testnet: bool) -> Self {
+        Self {
+            sinks: Vec::new(),
+            testnet,
+        }
+    }
+
+    /// `events` are ids like `newcontract_nep141`, `None` sends all events to this handler.
+    /// `name` is only used in logs.
+    pub fn with_handler(
+        self,
+        name: impl Into<String>,
+        handler: Arc<dyn ContractEventHandler>,
+        events: Option<HashSet<String>>,
+    ) -> Self {
+        self.with_sink(name.into(), handler, events, true)
+    }
+
+    /// Like [`with_handler`](Self::with_handler), but failures of this handler are only logged
+    pub fn with_optional_handler(
+        self,
+        name: impl Into<String>,
+        handler: Arc<dyn ContractEventHandler>,
+        events: Option<HashSet<String>>,
+    ) -> Self {
+        self.with_sink(name.into(), handler, events, false)
+    }
+
+    fn with_sink(
+        mut self,
+        name: String,
+        handler: Arc<dyn ContractEventHandler>,
+        events: Option<HashSet<String>>,
+        required: bool,
+    ) -> Self {
+        self.sinks.push(Sink {
+            name,
+            handler,
+            events,
+            required,
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    async fn dispatch<E, C, F, Fut>(&self, event_id: &str, event: E, context: C, handle: F)
+    where
+        E: Clone + Send + 'static,
+        C: Clone + Send + 'static,
+        F: Fn(Arc<dyn ContractEventHandler>, E, C) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let sinks = self
+            .sinks
+            .iter()
+            .filter(|sink| {
+                sink.events
+                    .as_ref()
+                    .is_none_or(|events| events.contains(event_id))
+            })
+            .collect::<Vec<_>>();
+        // Nothing to isolate, so a failure is raised right away
+        if let [sink] = sinks.as_slice() {
+            if sink.required {
+                handle(Arc::clone(&sink.handler), event, context).await;
+                return;
+            }
+        }
+        let tasks = sinks
+            .into_iter()
+            .map(|sink| {
+                let task = tokio::spawn(handle(
+                    Arc::clone(&sink.handler),
+                    event.clone(),
+                    context.clone(),
+                ));
+                (sink, task)
+            })
+            .collect::<Vec<_>>();
+        let mut panic = None;
+        for (sink, task) in tasks {
+            let Err(err) = task.await else {
+                continue;
+            };
+            log::error!(
+                "Sink {} failed to handle {event_id} event: {err}",
+                sink.name
+            );
+            if sink.required && err.is_panic() && panic.is_none() {
+                panic = Some(err.into_panic());
+            }
+        }
+        if let Some(panic) = panic {
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
+
+#[async_trait]
+impl ContractEventHandler for MultiHandler {
+    async fn handle_new_nep141(&self, account_id: AccountId, context: EventContext) {
+        self.handle_new_nep141_with_metadata(NewNep141Event::new(account_id, None, None), context)
+            .await
+    }
+
+    async fn handle_new_nep141_with_metadata(&self, event: NewNep141Event, context: EventContext) {
+        self.dispatch(
+            NewContractNep141Event::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler
+                    .handle_new_nep141_with_metadata(event, context)
+                    .await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_new_meme(
+        &self,
+        event: MemeCookingCreateMemeEvent,
+        context: EventContext,
+    ) {
+        self.dispatch(
+            NewMemeCookingMemeEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler.handle_meme_cooking_new_meme(event, context).await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_new_token(
+        &self,
+        event: MemeCookingCreateTokenEvent,
+        context: EventContext,
+    ) {
+        self.dispatch(
+            NewMemeCookingTokenEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler.handle_meme_cooking_new_token(event, context).await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_reached_soft_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.dispatch(
+            MemeReachedSoftCapEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler
+                    .handle_meme_cooking_reached_soft_cap(event, context)
+                    .await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_reached_hard_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.dispatch(
+            MemeReachedHardCapEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler
+                    .handle_meme_cooking_reached_hard_cap(event, context)
+                    .await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_expired_without_token(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.dispatch(
+            MemeExpiredWithoutTokenEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler
+                    .handle_meme_cooking_expired_without_token(event, context)
+                    .await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_launchpad_token_created(
+        &self,
+        event: launchpad::LaunchpadTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.dispatch(
+            LaunchpadTokenCreatedEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler.handle_launchpad_token_created(event, context).await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_bridged_token_created(
+        &self,
+        event: bridge::BridgedTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.dispatch(
+            BridgedTokenCreatedEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler.handle_bridged_token_created(event, context).await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_token_first_pool(
+        &self,
+        event: ref_finance::TokenFirstPoolEvent,
+        context: EventContext,
+    ) {
+        self.dispatch(
+            TokenFirstPoolEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler.handle_token_first_pool(event, context).await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_token_initial_distribution(
+        &self,
+        event: initial_distribution::TokenInitialDistributionEvent,
+        context: EventContext,
+    ) {
+        self.dispatch(
+            TokenInitialDistributionEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler
+                    .handle_token_initial_distribution(event, context)
+                    .await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_token_supply_changed(
+        &self,
+        event: supply::TokenSupplyChangedEvent,
+        context: BlockContext,
+    ) {
+        self.dispatch(
+            TokenSupplyChangedEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler.handle_token_supply_changed(event, context).await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_token_holders_snapshot(
+        &self,
+        event: holders::TokenHoldersSnapshotEvent,
+        context: BlockContext,
+    ) {
+        self.dispatch(
+            TokenHoldersSnapshotEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler.handle_token_holders_snapshot(event, context).await
+            },
+        )
+        .await;
+    }
+
+    async fn handle_token_metadata_updated(
+        &self,
+        event: metadata_updates::TokenMetadataUpdatedEvent,
+        context: BlockContext,
+    ) {
+        self.dispatch(
+            TokenMetadataUpdatedEvent::ID,
+            event,
+            context,
+            |handler, event, context| async move {
+                handler.handle_token_metadata_updated(event, context).await
+            },
+        )
+        .await;
+    }
+
+    fn is_testnet(&self) -> bool {
+        self.testnet
+    }
+}
@@ -0,0 +1,325 @@
+use std::time::Duration;
+
+use async_nats::jetstream::{self, context::Publish, stream};
+use async_trait::async_trait;
+use inindexer::near_indexer_primitives::types::AccountId;
+use intear_events::events::newcontract::{
+    meme_cooking_meme::NewMemeCookingMemeEvent, meme_cooking_token::NewMemeCookingTokenEvent,
+    nep141::NewContractNep141Event,
+};
+use serde::Serialize;
+
+use crate::bridge;
+use crate::events::{
+    BridgedTokenCreatedEvent, BridgedTokenCreatedEventData, LaunchpadTokenCreatedEvent,
+    LaunchpadTokenCreatedEventData, MemeCookingCampaignOutcomeEventData,
+    MemeExpiredWithoutTokenEvent, MemeReachedHardCapEvent, MemeReachedSoftCapEvent,
+    NewContractNep141EventData, NewMemeCookingMemeEventData, NewMemeCookingTokenEventData,
+    TokenFirstPoolEvent, TokenFirstPoolEventData, TokenHoldersSnapshotEvent,
+    TokenHoldersSnapshotEventData, TokenInitialDistributionEvent,
+    TokenInitialDistributionEventData, TokenMetadataUpdatedEvent, TokenMetadataUpdatedEventData,
+    TokenSupplyChangedEvent, TokenSupplyChangedEventData, EVENT_IDS,
+};
+use crate::holders;
+use crate::initial_distribution;
+use crate::launchpad;
+use crate::meme_cooking::{
+    MemeCookingCampaignOutcomeEvent, MemeCookingCreateMemeEvent, MemeCookingCreateTokenEvent,
+};
+use crate::metadata_updates;
+use crate::new_nep141::NewNep141Event;
+use crate::ref_finance;
+use crate::supply;
+use crate::{BlockContext, ContractEventHandler, EventContext};
+
+pub struct NatsHandlerOptions {
+    /// JetStream stream that captures all subjects, created if it doesn't exist.
+    /// `_TESTNET` is appended on testnet, so both networks can share a server.
+    pub stream: String,
+    /// Messages with the same id within this window are only stored once
+    pub duplicate_window: Duration,
+}
+
+impl Default for NatsHandlerOptions {
+    fn default() -> Self {
+        Self {
+            stream: "NEW_TOKENS".to_string(),
+            duplicate_window: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+/// `newcontract_nep141` is published to `newcontract.nep141`, `token_supply_changed` to
+/// `token.supply_changed`. Testnet subjects start with `testnet.`
+pub fn nats_subject(event_id: &str, testnet: bool) -> String {
+    let subject = event_id.replacen('_', ".", 1);
+    if testnet {
+        format!("testnet.{subject}")
+    } else {
+        subject
+    }
+}
+
+/// Stream names can't overlap in subjects, so testnet gets its own stream
+pub fn nats_stream_name(stream: &str, testnet: bool) -> String {
+    if testnet {
+        format!("{stream}_TESTNET")
+    } else {
+        stream.to_string()
+    }
+}
+
+/// Publishes the same payloads as [`PushToRedisStream`](crate::redis_handler::PushToRedisStream)
+/// to NATS JetStream. Each message has a `Nats-Msg-Id` built from the receipt id (or block
+/// height, for events that are triggered by block time), so replaying a block range
+/// within the duplicate window doesn't deliver the same event twice.
+pub struct NatsHandler {
+    jetstream: jetstream::Context,
+    testnet: bool,
+}
+
+impl NatsHandler {
+    pub async fn new(
+        client: async_nats::Client,
+        options: NatsHandlerOptions,
+        testnet: bool,
+    ) -> anyhow::Result<Self> {
+        let jetstream = jetstream::new(client);
+        jetstream
+            .get_or_create_stream(stream::Config {
+                name: nats_stream_name(&options.stream, testnet),
+                subjects: EVENT_IDS
+                    .iter()
+                    .map(|event_id| nats_subject(event_id, testnet))
+                    .collect(),
+                duplicate_window: options.duplicate_window,
+                ..Default::default()
+            })
+            .await?;
+        Ok(Self { jetstream, testnet })
+    }
+
+    async fn publish(&self, event_id: &str, key: String, origin: String, data: impl Serialize) {
+        let subject = nats_subject(event_id, self.testnet);
+        let publish = Publish::build()
+            .payload(serde_json::to_vec(&data).unwrap().into())
+            .message_id(nats_message_id(event_id, &origin, &key));
+        self.jetstream
+            .send_publish(subject, publish)
+            .await
+            .expect("Failed to publish NATS message")
+            .await
+            .unwrap_or_else(|err| panic!("{event_id} event was not stored by JetStream: {err}"));
+    }
+}
+
+/// Same event about the same token from the same receipt or block
+pub fn nats_message_id(event_id: &str, origin: &str, key: &str) -> String {
+    format!("{event_id}:{origin}:{key}")
+}
+
+trait DeduplicationOrigin {
+    fn deduplication_origin(&self) -> String;
+}
+
+impl DeduplicationOrigin for EventContext {
+    fn deduplication_origin(&self) -> String {
+        self.receipt_id.to_string()
+    }
+}
+
+impl DeduplicationOrigin for BlockContext {
+    fn deduplication_origin(&self) -> String {
+        self.block_height.to_string()
+    }
+}
+
+#[async_trait]
+impl ContractEventHandler for NatsHandler {
+    async fn handle_new_nep141(&self, account_id: AccountId, context: EventContext) {
+        self.handle_new_nep141_with_metadata(NewNep141Event::new(account_id, None, None), context)
+            .await
+    }
+
+    async fn handle_new_nep141_with_metadata(&self, event: NewNep141Event, context: EventContext) {
+        self.publish(
+            NewContractNep141Event::ID,
+            event.account_id.to_string(),
+            context.deduplication_origin(),
+            NewContractNep141EventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_new_meme(
+        &self,
+        event: MemeCookingCreateMemeEvent,
+        context: EventContext,
+    ) {
+        self.publish(
+            NewMemeCookingMemeEvent::ID,
+            event.meme_id.to_string(),
+            context.deduplication_origin(),
+            NewMemeCookingMemeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_new_token(
+        &self,
+        event: MemeCookingCreateTokenEvent,
+        context: EventContext,
+    ) {
+        self.publish(
+            NewMemeCookingTokenEvent::ID,
+            event.token_id.to_string(),
+            context.deduplication_origin(),
+            NewMemeCookingTokenEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_reached_soft_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.publish(
+            MemeReachedSoftCapEvent::ID,
+            event.meme_id.to_string(),
+            context.deduplication_origin(),
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_reached_hard_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.publish(
+            MemeReachedHardCapEvent::ID,
+            event.meme_id.to_string(),
+            context.deduplication_origin(),
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_expired_without_token(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.publish(
+            MemeExpiredWithoutTokenEvent::ID,
+            event.meme_id.to_string(),
+            context.deduplication_origin(),
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_launchpad_token_created(
+        &self,
+        event: launchpad::LaunchpadTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.publish(
+            LaunchpadTokenCreatedEvent::ID,
+            event.token_id.to_string(),
+            context.deduplication_origin(),
+            LaunchpadTokenCreatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_bridged_token_created(
+        &self,
+        event: bridge::BridgedTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.publish(
+            BridgedTokenCreatedEvent::ID,
+            event.token_id.to_string(),
+            context.deduplication_origin(),
+            BridgedTokenCreatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_first_pool(
+        &self,
+        event: ref_finance::TokenFirstPoolEvent,
+        context: EventContext,
+    ) {
+        self.publish(
+            TokenFirstPoolEvent::ID,
+            event.token_id.to_string(),
+            context.deduplication_origin(),
+            TokenFirstPoolEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_initial_distribution(
+        &self,
+        event: initial_distribution::TokenInitialDistributionEvent,
+        context: EventContext,
+    ) {
+        self.publish(
+            TokenInitialDistributionEvent::ID,
+            event.token_id.to_string(),
+            context.deduplication_origin(),
+            TokenInitialDistributionEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_supply_changed(
+        &self,
+        event: supply::TokenSupplyChangedEvent,
+        context: BlockContext,
+    ) {
+        self.publish(
+            TokenSupplyChangedEvent::ID,
+            event.token_id.to_string(),
+            context.deduplication_origin(),
+            TokenSupplyChangedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_holders_snapshot(
+        &self,
+        event: holders::TokenHoldersSnapshotEvent,
+        context: BlockContext,
+    ) {
+        self.publish(
+            TokenHoldersSnapshotEvent::ID,
+            event.token_id.to_string(),
+            context.deduplication_origin(),
+            TokenHoldersSnapshotEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_metadata_updated(
+        &self,
+        event: metadata_updates::TokenMetadataUpdatedEvent,
+        context: BlockContext,
+    ) {
+        self.publish(
+            TokenMetadataUpdatedEvent::ID,
+            event.token_id.to_string(),
+            context.deduplication_origin(),
+            TokenMetadataUpdatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    fn is_testnet(&self) -> bool {
+        self.testnet
+    }
+}
@@ -7,19 +7,35 @@ use std::{
 use async_trait::async_trait;
 use inindexer::{
     near_indexer_primitives::{
-        types::{AccountId, BlockHeight, BlockId, BlockReference},
+        types::{AccountId, Balance, BlockHeight, BlockId, BlockReference},
         views::{ActionView, QueryRequest, ReceiptEnumView},
         StreamerMessage,
     },
-    near_utils::{EventLogData, FtBurnLog, FtMintLog, FtTransferLog},
+    near_utils::{dec_format, EventLogData, FtBurnLog, FtMintLog, FtTransferLog},
     IncompleteTransaction, TransactionReceipt,
 };
-use near_jsonrpc_client::{methods, JsonRpcClient};
+use near_jsonrpc_client::{
+    methods::{self, query::QueryResponseKind},
+    JsonRpcClient,
+};
+use serde::{Deserialize, Serialize};
 
-use crate::{ContractEventHandler, EventContext};
+use crate::{
+    initial_distribution::InitialDistributions,
+    launch_timeline::{LaunchTimelines, MemeCookingOrigin},
+    meme_cooking::MemeCookingContract,
+    metadata_updates::{seed_metadata, TokenMetadataStorage},
+    metadata_validation::{validate_metadata, MetadataIssue},
+    risk::{RiskAssessment, RiskScorer},
+    ContractEventHandler, EventContext,
+};
 
 pub struct Nep141Indexer {
     storage: Arc<dyn HandledTokensStorage>,
+    distributions: Arc<InitialDistributions>,
+    pub risk_scorer: Option<Arc<RiskScorer>>,
+    /// Metadata of new tokens is saved here, so that updates are compared with it
+    pub metadata_storage: Option<Arc<dyn TokenMetadataStorage>>,
     rpc_client: JsonRpcClient,
     last_checked_event: HashMap<AccountId, Instant>,
 }
@@ -29,16 +45,33 @@ impl Nep141Indexer {
         Self {
             rpc_client,
             storage: Arc::new(storage),
+            distributions: Arc::new(InitialDistributions::default()),
+            risk_scorer: None,
+            metadata_storage: None,
             last_checked_event: HashMap::new(),
         }
     }
 
+    pub fn storage(&self) -> Arc<dyn HandledTokensStorage> {
+        Arc::clone(&self.storage)
+    }
+
+    pub fn rpc_client(&self) -> JsonRpcClient {
+        self.rpc_client.clone()
+    }
+
+    pub fn distributions(&self) -> Arc<InitialDistributions> {
+        Arc::clone(&self.distributions)
+    }
+
     pub async fn detect_nep141<T: ContractEventHandler + 'static>(
         &mut self,
         receipt: &TransactionReceipt,
         tx: &IncompleteTransaction,
         block: &StreamerMessage,
         handler: Arc<T>,
+        launches: Arc<LaunchTimelines>,
+        meme_cooking_contracts: &[MemeCookingContract],
     ) {
         if let ReceiptEnumView::Action { actions, .. } = &receipt.receipt.receipt.receipt {
             for action in actions.iter() {
@@ -50,28 +83,73 @@ impl Nep141Indexer {
                     {
                         let storage = Arc::clone(&self.storage);
                         let handler = Arc::clone(&handler);
+                        let launches = Arc::clone(&launches);
+                        let distributions = Arc::clone(&self.distributions);
+                        let risk_scorer = self.risk_scorer.clone();
+                        let metadata_storage = self.metadata_storage.clone();
                         let rpc_client = self.rpc_client.clone();
-                        let context = EventContext {
-                            transaction_id: tx.transaction.transaction.hash,
-                            receipt_id: receipt.receipt.receipt.receipt_id,
-                            block_height: block.block.header.height,
-                            block_timestamp_nanosec: block.block.header.timestamp_nanosec as u128,
-                        };
+                        let context = EventContext::new(receipt, tx, block);
+                        let deployer = tx.transaction.transaction.signer_id.clone();
                         let token_id = receipt.receipt.receipt.receiver_id.clone();
-                        if is_nep141(&token_id, context.block_height, &rpc_client).await {
+                        let meme_cooking = launches
+                            .meme_cooking_origin(&token_id, tx, meme_cooking_contracts)
+                            .await;
+                        if let Ok(metadata) =
+                            ft_metadata(&token_id, context.block_height, &rpc_client).await
+                        {
                             log::info!("Found NEP141: {token_id}");
-                            storage.mark_handled(token_id.clone()).await;
-                            handler.handle_new_nep141(token_id.clone(), context).await;
+                            let event =
+                                NewNep141Event::new(token_id.clone(), meme_cooking, metadata);
+                            storage
+                                .mark_handled_token(KnownToken::new(&event, &context))
+                                .await;
+                            let block_height = context.block_height;
+                            emit_nep141(
+                                &*handler,
+                                &launches,
+                                risk_scorer.as_deref(),
+                                metadata_storage.as_deref(),
+                                event,
+                                &deployer,
+                                context,
+                            )
+                            .await;
+                            distributions
+                                .expect_mint(&token_id, block_height, &*handler)
+                                .await;
                         } else {
                             tokio::spawn(async move {
                                 // Give RPC some time to catch up
                                 tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-                                if !storage.is_already_indexed(&token_id).await
-                                    && is_nep141(&token_id, context.block_height, &rpc_client).await
+                                if storage.is_already_indexed(&token_id).await {
+                                    return;
+                                }
+                                if let Ok(metadata) =
+                                    ft_metadata(&token_id, context.block_height, &rpc_client).await
                                 {
                                     log::info!("Found NEP141 with delay: {token_id}");
-                                    storage.mark_handled(token_id.clone()).await;
-                                    handler.handle_new_nep141(token_id.clone(), context).await;
+                                    let event = NewNep141Event::new(
+                                        token_id.clone(),
+                                        meme_cooking,
+                                        metadata,
+                                    );
+                                    storage
+                                        .mark_handled_token(KnownToken::new(&event, &context))
+                                        .await;
+                                    let block_height = context.block_height;
+                                    emit_nep141(
+                                        &*handler,
+                                        &launches,
+                                        risk_scorer.as_deref(),
+                                        metadata_storage.as_deref(),
+                                        event,
+                                        &deployer,
+                                        context,
+                                    )
+                                    .await;
+                                    distributions
+                                        .expect_mint(&token_id, block_height, &*handler)
+                                        .await;
                                 }
                             });
                         }
@@ -95,51 +173,83 @@ impl Nep141Indexer {
             if EventLogData::<FtTransferLog>::deserialize(log).is_ok()
                 || EventLogData::<FtBurnLog>::deserialize(log).is_ok()
                 || EventLogData::<FtMintLog>::deserialize(log).is_ok()
-                || receipt
-                    .receipt
-                    .receipt
-                    .receiver_id
-                    .as_str()
-                    .ends_with(".tkn.near")
             {
                 self.last_checked_event
                     .insert(receipt.receipt.receipt.receiver_id.clone(), Instant::now());
 
-                if !self
+                if self
                     .storage
                     .is_already_indexed(&receipt.receipt.receipt.receiver_id)
                     .await
-                    && is_nep141(
-                        &receipt.receipt.receipt.receiver_id,
-                        block.block.header.height,
-                        &self.rpc_client,
-                    )
-                    .await
                 {
-                    self.storage
-                        .mark_handled(receipt.receipt.receipt.receiver_id.clone())
-                        .await;
-                    let context = EventContext {
-                        transaction_id: tx.transaction.transaction.hash,
-                        receipt_id: receipt.receipt.receipt.receipt_id,
-                        block_height: block.block.header.height,
-                        block_timestamp_nanosec: block.block.header.timestamp_nanosec as u128,
-                    };
-                    handler
-                        .handle_new_nep141(receipt.receipt.receipt.receiver_id.clone(), context)
-                        .await;
+                    continue;
                 }
+                let Ok(metadata) = ft_metadata(
+                    &receipt.receipt.receipt.receiver_id,
+                    block.block.header.height,
+                    &self.rpc_client,
+                )
+                .await
+                else {
+                    continue;
+                };
+                let context = EventContext::new(receipt, tx, block);
+                let token_id = receipt.receipt.receipt.receiver_id.clone();
+                let meme_cooking = launches
+                    .meme_cooking_origin(&token_id, tx, meme_cooking_contracts)
+                    .await;
+                let event = NewNep141Event::new(token_id, meme_cooking, metadata);
+                self.storage
+                    .mark_handled_token(KnownToken::new(&event, &context))
+                    .await;
+                emit_nep141(
+                    &*handler,
+                    &launches,
+                    self.risk_scorer.as_deref(),
+                    self.metadata_storage.as_deref(),
+                    event,
+                    &tx.transaction.transaction.signer_id,
+                    context,
+                )
+                .await;
             }
         }
     }
 }
 
-async fn is_nep141(
+async fn emit_nep141<T: ContractEventHandler>(
+    handler: &T,
+    launches: &LaunchTimelines,
+    risk_scorer: Option<&RiskScorer>,
+    metadata_storage: Option<&dyn TokenMetadataStorage>,
+    mut event: NewNep141Event,
+    deployer: &AccountId,
+    context: EventContext,
+) {
+    if let Some(origin) = &event.meme_cooking {
+        launches
+            .record_nep141(origin, &event.account_id, &context)
+            .await;
+    }
+    if let (Some(storage), Some(metadata)) = (metadata_storage, &event.metadata) {
+        seed_metadata(storage, &event.account_id, metadata).await;
+    }
+    if let Some(risk_scorer) = risk_scorer {
+        event.risk = Some(risk_scorer.assess(&event, deployer, &context).await);
+    }
+    handler
+        .handle_new_nep141_with_metadata(event, context)
+        .await;
+}
+
+/// Fails if the contract doesn't implement `ft_metadata`. If it does, but the
+/// result is not valid NEP-148 metadata, the token is still considered NEP-141.
+pub(crate) async fn ft_metadata(
     account_id: &AccountId,
     block_height: BlockHeight,
     rpc_client: &JsonRpcClient,
-) -> bool {
-    let metadata = rpc_client
+) -> anyhow::Result<Option<FtMetadata>> {
+    let response = rpc_client
         .call(methods::query::RpcQueryRequest {
             block_reference: BlockReference::BlockId(BlockId::Height(block_height)),
             request: QueryRequest::CallFunction {
@@ -148,12 +258,121 @@ async fn is_nep141(
                 args: serde_json::to_vec(&serde_json::json!({})).unwrap().into(),
             },
         })
-        .await;
-    metadata.is_ok()
+        .await?;
+    Ok(match response.kind {
+        QueryResponseKind::CallResult(result) => serde_json::from_slice(&result.result).ok(),
+        _ => None,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewNep141Event {
+    pub account_id: AccountId,
+    pub meme_cooking: Option<MemeCookingOrigin>,
+    pub metadata: Option<FtMetadata>,
+    /// Only known for tokens created by known factories
+    pub owner_id: Option<AccountId>,
+    /// Only known for tokens created by known factories
+    pub total_supply: Option<Balance>,
+    pub risk: Option<RiskAssessment>,
+    /// Problems with `metadata` that were found (and fixed, if possible) when the event was created
+    pub metadata_issues: Vec<MetadataIssue>,
+}
+
+impl NewNep141Event {
+    /// Validates and normalizes the metadata
+    pub fn new(
+        account_id: AccountId,
+        meme_cooking: Option<MemeCookingOrigin>,
+        mut metadata: Option<FtMetadata>,
+    ) -> Self {
+        let metadata_issues = metadata.as_mut().map(validate_metadata).unwrap_or_default();
+        Self {
+            account_id,
+            meme_cooking,
+            metadata,
+            owner_id: None,
+            total_supply: None,
+            risk: None,
+            metadata_issues,
+        }
+    }
+}
+
+/// NEP-148 `ft_metadata`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FtMetadata {
+    pub spec: String,
+    pub name: String,
+    pub symbol: String,
+    pub icon: Option<String>,
+    pub reference: Option<String>,
+    pub reference_hash: Option<String>,
+    pub decimals: u8,
+}
+
+/// A token in [`HandledTokensStorage`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KnownToken {
+    pub account_id: AccountId,
+    /// `None` for tokens that were saved before first-seen blocks were recorded
+    pub first_seen_block_height: Option<BlockHeight>,
+    #[serde(with = "dec_format")]
+    pub first_seen_timestamp_nanosec: Option<u128>,
+    pub symbol: Option<String>,
+}
+
+impl KnownToken {
+    pub fn new(event: &NewNep141Event, context: &EventContext) -> Self {
+        Self {
+            account_id: event.account_id.clone(),
+            first_seen_block_height: Some(context.block_height),
+            first_seen_timestamp_nanosec: Some(context.block_timestamp_nanosec),
+            symbol: event
+                .metadata
+                .as_ref()
+                .map(|metadata| metadata.symbol.clone()),
+        }
+    }
 }
 
 #[async_trait]
 pub trait HandledTokensStorage: Send + Sync {
     async fn is_already_indexed(&self, account_id: &AccountId) -> bool;
     async fn mark_handled(&self, account_id: AccountId);
+    /// Also gets the first-seen block and symbol, for storages that keep them
+    async fn mark_handled_token(&self, token: KnownToken) {
+        self.mark_handled(token.account_id).await
+    }
+}
+
+#[async_trait]
+impl<S: HandledTokensStorage + ?Sized> HandledTokensStorage for Arc<S> {
+    async fn is_already_indexed(&self, account_id: &AccountId) -> bool {
+        (**self).is_already_indexed(account_id).await
+    }
+
+    async fn mark_handled(&self, account_id: AccountId) {
+        (**self).mark_handled(account_id).await
+    }
+
+    async fn mark_handled_token(&self, token: KnownToken) {
+        (**self).mark_handled_token(token).await
+    }
+}
+
+/// Storage that can be queried by the HTTP API
+#[async_trait]
+pub trait TokenRegistry: HandledTokensStorage {
+    async fn get_token(&self, account_id: &AccountId) -> Option<KnownToken>;
+    /// Sorted by first-seen block, tokens without one are not included
+    async fn tokens_first_seen_between(
+        &self,
+        from: BlockHeight,
+        to: BlockHeight,
+        limit: usize,
+    ) -> Vec<KnownToken>;
+    /// Case-insensitive, exact matches first, then by first-seen block
+    async fn search_by_symbol(&self, query: &str, limit: usize) -> Vec<KnownToken>;
+    async fn token_count(&self) -> usize;
 }
@@ -0,0 +1,336 @@
+use async_trait::async_trait;
+use inindexer::near_indexer_primitives::types::AccountId;
+use sqlx::{migrate::Migrator, PgPool};
+
+use crate::events::{
+    MemeCookingCampaignOutcomeEventData, NewContractNep141EventData, NewMemeCookingMemeEventData,
+    NewMemeCookingTokenEventData, TokenMetadataUpdatedEventData,
+};
+use crate::meme_cooking::{
+    MemeCookingCampaignOutcomeEvent, MemeCookingCreateMemeEvent, MemeCookingCreateTokenEvent,
+};
+use crate::metadata_updates;
+use crate::new_nep141::NewNep141Event;
+use crate::{BlockContext, ContractEventHandler, EventContext};
+
+/// Migrations in `migrations/`, embedded in the binary
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Upserts tokens and meme.cooking memes into the tables created by [`MIGRATOR`]:
+/// `tokens` by account id, `meme_cooking_memes` and `meme_cooking_tokens` by meme id.
+/// A row is only overwritten by a different receipt from the same or a later block, so
+/// replaying a block range doesn't change anything. Metadata updates and campaign outcomes
+/// update the existing rows, other events are not stored.
+pub struct PostgresHandler {
+    pool: PgPool,
+    testnet: bool,
+}
+
+impl PostgresHandler {
+    /// Runs the migrations that weren't applied yet
+    pub async fn new(pool: PgPool, testnet: bool) -> Result<Self, sqlx::migrate::MigrateError> {
+        MIGRATOR.run(&pool).await?;
+        Ok(Self { pool, testnet })
+    }
+
+    async fn set_campaign_outcome(
+        &self,
+        outcome: &str,
+        event: MemeCookingCampaignOutcomeEventData,
+    ) {
+        sqlx::query(
+            "UPDATE meme_cooking_memes
+            SET outcome = $2, total_deposited = $3::numeric, participants = $4
+            WHERE meme_id = $1",
+        )
+        .bind(event.meme_id as i64)
+        .bind(outcome)
+        .bind(event.total_deposited.to_string())
+        .bind(event.participants as i32)
+        .execute(&self.pool)
+        .await
+        .expect("Failed to update meme cooking campaign outcome");
+    }
+}
+
+#[async_trait]
+impl ContractEventHandler for PostgresHandler {
+    async fn handle_new_nep141(&self, account_id: AccountId, context: EventContext) {
+        self.handle_new_nep141_with_metadata(NewNep141Event::new(account_id, None, None), context)
+            .await
+    }
+
+    async fn handle_new_nep141_with_metadata(&self, event: NewNep141Event, context: EventContext) {
+        let data = NewContractNep141EventData::new(event, context);
+        let metadata = data.metadata.as_ref();
+        sqlx::query(
+            "INSERT INTO tokens (
+                account_id, name, symbol, decimals, icon, reference, reference_hash, spec,
+                owner_id, total_supply, meme_id, risk, metadata_issues,
+                transaction_id, receipt_id, block_height, block_timestamp_nanosec,
+                signer_id, predecessor_id, attached_deposit, factory_id
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10::numeric, $11, $12::jsonb, $13::jsonb,
+                $14, $15, $16, $17::numeric, $18, $19, $20::numeric, $21
+            )
+            ON CONFLICT (account_id) DO UPDATE SET
+                name = EXCLUDED.name,
+                symbol = EXCLUDED.symbol,
+                decimals = EXCLUDED.decimals,
+                icon = EXCLUDED.icon,
+                reference = EXCLUDED.reference,
+                reference_hash = EXCLUDED.reference_hash,
+                spec = EXCLUDED.spec,
+                owner_id = EXCLUDED.owner_id,
+                total_supply = EXCLUDED.total_supply,
+                meme_id = EXCLUDED.meme_id,
+                risk = EXCLUDED.risk,
+                metadata_issues = EXCLUDED.metadata_issues,
+                transaction_id = EXCLUDED.transaction_id,
+                receipt_id = EXCLUDED.receipt_id,
+                block_height = EXCLUDED.block_height,
+                block_timestamp_nanosec = EXCLUDED.block_timestamp_nanosec,
+                signer_id = EXCLUDED.signer_id,
+                predecessor_id = EXCLUDED.predecessor_id,
+                attached_deposit = EXCLUDED.attached_deposit,
+                factory_id = EXCLUDED.factory_id
+            WHERE tokens.receipt_id <> EXCLUDED.receipt_id
+                AND tokens.block_height <= EXCLUDED.block_height",
+        )
+        .bind(data.account_id.to_string())
+        .bind(metadata.map(|metadata| metadata.name.clone()))
+        .bind(metadata.map(|metadata| metadata.symbol.clone()))
+        .bind(metadata.map(|metadata| metadata.decimals as i16))
+        .bind(metadata.and_then(|metadata| metadata.icon.clone()))
+        .bind(metadata.and_then(|metadata| metadata.reference.clone()))
+        .bind(metadata.and_then(|metadata| metadata.reference_hash.clone()))
+        .bind(metadata.map(|metadata| metadata.spec.clone()))
+        .bind(data.owner_id.as_ref().map(|owner_id| owner_id.to_string()))
+        .bind(
+            data.total_supply
+                .map(|total_supply| total_supply.to_string()),
+        )
+        .bind(
+            data.meme_cooking
+                .as_ref()
+                .map(|origin| origin.meme_id as i64),
+        )
+        .bind(
+            data.risk
+                .as_ref()
+                .map(|risk| serde_json::to_string(risk).unwrap()),
+        )
+        .bind(serde_json::to_string(&data.metadata_issues).unwrap())
+        .bind(data.context.transaction_id.to_string())
+        .bind(data.context.receipt_id.to_string())
+        .bind(data.context.block_height as i64)
+        .bind(data.context.block_timestamp_nanosec.to_string())
+        .bind(data.context.signer_id.to_string())
+        .bind(data.context.predecessor_id.to_string())
+        .bind(data.context.attached_deposit.to_string())
+        .bind(
+            data.context
+                .factory_id
+                .as_ref()
+                .map(|factory_id| factory_id.to_string()),
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to upsert token");
+    }
+
+    async fn handle_meme_cooking_new_meme(
+        &self,
+        event: MemeCookingCreateMemeEvent,
+        context: EventContext,
+    ) {
+        let data = NewMemeCookingMemeEventData::new(event, context);
+        sqlx::query(
+            "INSERT INTO meme_cooking_memes (
+                meme_id, owner, end_timestamp_ms, name, symbol, decimals, total_supply,
+                reference, reference_hash, reference_document, deposit_token_id, soft_cap, hard_cap,
+                transaction_id, receipt_id, block_height, block_timestamp_nanosec,
+                signer_id, predecessor_id, attached_deposit, factory_id
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, $6, $7::numeric, $8, $9, $10::jsonb, $11, $12::numeric,
+                $13::numeric, $14, $15, $16, $17::numeric, $18, $19, $20::numeric, $21
+            )
+            ON CONFLICT (meme_id) DO UPDATE SET
+                owner = EXCLUDED.owner,
+                end_timestamp_ms = EXCLUDED.end_timestamp_ms,
+                name = EXCLUDED.name,
+                symbol = EXCLUDED.symbol,
+                decimals = EXCLUDED.decimals,
+                total_supply = EXCLUDED.total_supply,
+                reference = EXCLUDED.reference,
+                reference_hash = EXCLUDED.reference_hash,
+                reference_document = EXCLUDED.reference_document,
+                deposit_token_id = EXCLUDED.deposit_token_id,
+                soft_cap = EXCLUDED.soft_cap,
+                hard_cap = EXCLUDED.hard_cap,
+                transaction_id = EXCLUDED.transaction_id,
+                receipt_id = EXCLUDED.receipt_id,
+                block_height = EXCLUDED.block_height,
+                block_timestamp_nanosec = EXCLUDED.block_timestamp_nanosec,
+                signer_id = EXCLUDED.signer_id,
+                predecessor_id = EXCLUDED.predecessor_id,
+                attached_deposit = EXCLUDED.attached_deposit,
+                factory_id = EXCLUDED.factory_id
+            WHERE meme_cooking_memes.receipt_id <> EXCLUDED.receipt_id
+                AND meme_cooking_memes.block_height <= EXCLUDED.block_height",
+        )
+        .bind(data.meme_id as i64)
+        .bind(data.owner.to_string())
+        .bind(data.end_timestamp_ms as i64)
+        .bind(data.name)
+        .bind(data.symbol)
+        .bind(data.decimals as i32)
+        .bind(data.total_supply.to_string())
+        .bind(data.reference)
+        .bind(data.reference_hash)
+        .bind(
+            data.reference_document
+                .as_ref()
+                .map(|document| serde_json::to_string(document).unwrap()),
+        )
+        .bind(data.deposit_token_id.to_string())
+        .bind(data.soft_cap.to_string())
+        .bind(data.hard_cap.map(|hard_cap| hard_cap.to_string()))
+        .bind(data.context.transaction_id.to_string())
+        .bind(data.context.receipt_id.to_string())
+        .bind(data.context.block_height as i64)
+        .bind(data.context.block_timestamp_nanosec.to_string())
+        .bind(data.context.signer_id.to_string())
+        .bind(data.context.predecessor_id.to_string())
+        .bind(data.context.attached_deposit.to_string())
+        .bind(
+            data.context
+                .factory_id
+                .as_ref()
+                .map(|factory_id| factory_id.to_string()),
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to upsert meme cooking meme");
+    }
+
+    async fn handle_meme_cooking_new_token(
+        &self,
+        event: MemeCookingCreateTokenEvent,
+        context: EventContext,
+    ) {
+        let data = NewMemeCookingTokenEventData::new(event, context);
+        sqlx::query(
+            "INSERT INTO meme_cooking_tokens (
+                meme_id, token_id, total_supply, pool_id,
+                transaction_id, receipt_id, block_height, block_timestamp_nanosec,
+                signer_id, predecessor_id, attached_deposit, factory_id
+            )
+            VALUES ($1, $2, $3::numeric, $4, $5, $6, $7, $8::numeric, $9, $10, $11::numeric, $12)
+            ON CONFLICT (meme_id) DO UPDATE SET
+                token_id = EXCLUDED.token_id,
+                total_supply = EXCLUDED.total_supply,
+                pool_id = EXCLUDED.pool_id,
+                transaction_id = EXCLUDED.transaction_id,
+                receipt_id = EXCLUDED.receipt_id,
+                block_height = EXCLUDED.block_height,
+                block_timestamp_nanosec = EXCLUDED.block_timestamp_nanosec,
+                signer_id = EXCLUDED.signer_id,
+                predecessor_id = EXCLUDED.predecessor_id,
+                attached_deposit = EXCLUDED.attached_deposit,
+                factory_id = EXCLUDED.factory_id
+            WHERE meme_cooking_tokens.receipt_id <> EXCLUDED.receipt_id
+                AND meme_cooking_tokens.block_height <= EXCLUDED.block_height",
+        )
+        .bind(data.meme_id as i64)
+        .bind(data.token_id.to_string())
+        .bind(data.total_supply.to_string())
+        .bind(data.pool_id as i64)
+        .bind(data.context.transaction_id.to_string())
+        .bind(data.context.receipt_id.to_string())
+        .bind(data.context.block_height as i64)
+        .bind(data.context.block_timestamp_nanosec.to_string())
+        .bind(data.context.signer_id.to_string())
+        .bind(data.context.predecessor_id.to_string())
+        .bind(data.context.attached_deposit.to_string())
+        .bind(
+            data.context
+                .factory_id
+                .as_ref()
+                .map(|factory_id| factory_id.to_string()),
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to upsert meme cooking token");
+    }
+
+    async fn handle_meme_cooking_reached_soft_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.set_campaign_outcome(
+            "reached_soft_cap",
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_reached_hard_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.set_campaign_outcome(
+            "reached_hard_cap",
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_expired_without_token(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.set_campaign_outcome(
+            "expired_without_token",
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_metadata_updated(
+        &self,
+        event: metadata_updates::TokenMetadataUpdatedEvent,
+        context: BlockContext,
+    ) {
+        let data = TokenMetadataUpdatedEventData::new(event, context);
+        sqlx::query(
+            "UPDATE tokens
+            SET name = $2, symbol = $3, decimals = $4, icon = $5, reference = $6,
+                reference_hash = $7, spec = $8, metadata_block_height = $9
+            WHERE account_id = $1
+                AND block_height <= $9
+                AND (metadata_block_height IS NULL OR metadata_block_height < $9)",
+        )
+        .bind(data.token_id.to_string())
+        .bind(data.metadata.name)
+        .bind(data.metadata.symbol)
+        .bind(data.metadata.decimals as i16)
+        .bind(data.metadata.icon)
+        .bind(data.metadata.reference)
+        .bind(data.metadata.reference_hash)
+        .bind(data.metadata.spec)
+        .bind(data.context.block_height as i64)
+        .execute(&self.pool)
+        .await
+        .expect("Failed to update token metadata");
+    }
+
+    fn is_testnet(&self) -> bool {
+        self.testnet
+    }
+}
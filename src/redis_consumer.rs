@@ -0,0 +1,241 @@
+use std::{
+    future::Future,
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+use inindexer::near_indexer_primitives::types::BlockHeight;
+use redis::{
+    aio::ConnectionManager,
+    streams::{StreamId, StreamRangeReply, StreamReadOptions, StreamReadReply},
+    AsyncCommands, RedisResult, Value,
+};
+use serde::de::DeserializeOwned;
+
+use crate::redis_handler::stream_name;
+
+/// Field of stream entries that has the event serialized as JSON
+pub const EVENT_FIELD: &str = "event";
+
+pub struct RedisConsumerOptions {
+    pub group: String,
+    /// Unique within the group, like the hostname of the service
+    pub consumer: String,
+    /// Max number of events returned by one `next_batch` call
+    pub batch_size: usize,
+    /// How long `next_batch` waits for new events before returning an empty batch
+    pub block_timeout: Duration,
+    /// Events that were read but not acknowledged for this long (by any consumer of the group,
+    /// for example one that crashed) are delivered again
+    pub min_idle_time: Duration,
+    /// Where a new group starts reading: `0` for all events that are still in the stream,
+    /// `$` for only the events added after the group is created
+    pub start_id: String,
+}
+
+impl RedisConsumerOptions {
+    pub fn new(group: impl Into<String>, consumer: impl Into<String>) -> Self {
+        Self {
+            group: group.into(),
+            consumer: consumer.into(),
+            batch_size: 100,
+            block_timeout: Duration::from_secs(5),
+            min_idle_time: Duration::from_secs(60),
+            start_id: "0".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsumedEvent<T> {
+    /// Stream entry id, pass it to `ack` once the event is handled
+    pub id: String,
+    /// Entry ids start with the block height of the event
+    pub block_height: BlockHeight,
+    pub data: T,
+}
+
+/// Reads events written by [`PushToRedisStream`](crate::redis_handler::PushToRedisStream)
+/// as a member of a consumer group, so that several instances of a service share the
+/// events of a stream. `T` is the event data, like
+/// [`NewContractNep141EventData`](crate::events::NewContractNep141EventData) for
+/// `newcontract_nep141`.
+///
+/// Events are delivered at least once: an event that is not acknowledged is delivered
+/// again, to this consumer after a restart, or to any consumer of the group once it's
+/// idle for `min_idle_time`. Entries that can't be deserialized into `T` are logged and
+/// acknowledged, since reading them again won't help.
+pub struct RedisEventConsumer<T> {
+    connection: ConnectionManager,
+    stream: String,
+    options: RedisConsumerOptions,
+    /// Events that were delivered to this consumer before a restart are read first, once,
+    /// starting after this id. `None` once they were all returned.
+    own_pending_cursor: Option<String>,
+    autoclaim_cursor: String,
+    next_autoclaim: Instant,
+    _data: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> RedisEventConsumer<T> {
+    /// `event_id` is the stream name without `_testnet`, like `newcontract_nep141`.
+    /// Creates the stream and the group if they don't exist.
+    pub async fn new(
+        mut connection: ConnectionManager,
+        event_id: &str,
+        testnet: bool,
+        options: RedisConsumerOptions,
+    ) -> RedisResult<Self> {
+        let stream = stream_name(event_id, testnet);
+        let created: RedisResult<()> = connection
+            .xgroup_create_mkstream(&stream, &options.group, &options.start_id)
+            .await;
+        // BUSYGROUP: the group already exists
+        if let Err(err) = created {
+            if err.code() != Some("BUSYGROUP") {
+                return Err(err);
+            }
+        }
+        Ok(Self {
+            connection,
+            stream,
+            options,
+            own_pending_cursor: Some("0".to_string()),
+            autoclaim_cursor: "0-0".to_string(),
+            next_autoclaim: Instant::now(),
+            _data: PhantomData,
+        })
+    }
+
+    /// Returns events that this consumer should handle: its own pending events after a
+    /// restart, then events reclaimed from idle consumers, then new events. Waits up to
+    /// `block_timeout` if there's nothing to handle, and returns an empty batch after that.
+    pub async fn next_batch(&mut self) -> RedisResult<Vec<ConsumedEvent<T>>> {
+        if let Some(cursor) = self.own_pending_cursor.take() {
+            let entries = self.read_group(&cursor, None).await?;
+            if let Some(last) = entries.last() {
+                // Events that fail again stay pending and are reclaimed once they're idle
+                self.own_pending_cursor = Some(last.id.clone());
+                return self.deserialize(entries).await;
+            }
+        }
+        if Instant::now() >= self.next_autoclaim {
+            let entries = self.autoclaim().await?;
+            if !entries.is_empty() {
+                return self.deserialize(entries).await;
+            }
+        }
+        let entries = self
+            .read_group(">", Some(self.options.block_timeout))
+            .await?;
+        self.deserialize(entries).await
+    }
+
+    pub async fn ack(&mut self, ids: &[String]) -> RedisResult<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.connection
+            .xack(&self.stream, &self.options.group, ids)
+            .await
+    }
+
+    /// Handles events one by one and acknowledges the ones that `handle` returned `Ok` for.
+    /// Failed events are logged and stay pending, so they are delivered again once they're
+    /// idle for `min_idle_time`, or after a restart. Runs until Redis returns an error.
+    pub async fn consume<F, Fut>(&mut self, mut handle: F) -> RedisResult<()>
+    where
+        F: FnMut(ConsumedEvent<T>) -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        loop {
+            for event in self.next_batch().await? {
+                let id = event.id.clone();
+                match handle(event).await {
+                    Ok(()) => self.ack(&[id]).await?,
+                    Err(err) => log::warn!("Failed to handle {} event {id}: {err:?}", self.stream),
+                }
+            }
+        }
+    }
+
+    async fn read_group(
+        &mut self,
+        id: &str,
+        block_timeout: Option<Duration>,
+    ) -> RedisResult<Vec<StreamId>> {
+        let mut options = StreamReadOptions::default()
+            .group(&self.options.group, &self.options.consumer)
+            .count(self.options.batch_size);
+        if let Some(block_timeout) = block_timeout {
+            options = options.block(block_timeout.as_millis() as usize);
+        }
+        let reply: StreamReadReply = self
+            .connection
+            .xread_options(&[&self.stream], &[id], &options)
+            .await?;
+        Ok(reply.keys.into_iter().flat_map(|key| key.ids).collect())
+    }
+
+    /// One step of `XAUTOCLAIM` (Redis 6.2+). Once it went through all pending entries,
+    /// waits for `min_idle_time` before starting again.
+    async fn autoclaim(&mut self) -> RedisResult<Vec<StreamId>> {
+        let reply: Vec<Value> = redis::cmd("XAUTOCLAIM")
+            .arg(&self.stream)
+            .arg(&self.options.group)
+            .arg(&self.options.consumer)
+            .arg(self.options.min_idle_time.as_millis() as u64)
+            .arg(&self.autoclaim_cursor)
+            .arg("COUNT")
+            .arg(self.options.batch_size)
+            .query_async(&mut self.connection)
+            .await?;
+        let (Some(cursor), Some(entries)) = (reply.first(), reply.get(1)) else {
+            return Err((redis::ErrorKind::TypeError, "Unexpected XAUTOCLAIM reply").into());
+        };
+        self.autoclaim_cursor = redis::from_redis_value(cursor)?;
+        if self.autoclaim_cursor == "0-0" {
+            self.next_autoclaim = Instant::now() + self.options.min_idle_time;
+        }
+        // Entries that were trimmed from the stream are not returned, and XAUTOCLAIM
+        // removes them from the pending list
+        let entries: StreamRangeReply = redis::from_redis_value(entries)?;
+        Ok(entries.ids)
+    }
+
+    async fn deserialize(&mut self, entries: Vec<StreamId>) -> RedisResult<Vec<ConsumedEvent<T>>> {
+        let mut events = Vec::with_capacity(entries.len());
+        let mut invalid = Vec::new();
+        for entry in entries {
+            let data = entry
+                .get::<String>(EVENT_FIELD)
+                .ok_or_else(|| format!("no `{EVENT_FIELD}` field"))
+                .and_then(|json| serde_json::from_str(&json).map_err(|err| err.to_string()));
+            let block_height = entry
+                .id
+                .split_once('-')
+                .and_then(|(block_height, _)| block_height.parse().ok());
+            match (data, block_height) {
+                (Ok(data), Some(block_height)) => events.push(ConsumedEvent {
+                    id: entry.id,
+                    block_height,
+                    data,
+                }),
+                (Err(err), _) => {
+                    log::error!("Skipping invalid {} event {}: {err}", self.stream, entry.id);
+                    invalid.push(entry.id);
+                }
+                (_, None) => {
+                    log::error!(
+                        "Skipping {} event with invalid id {}",
+                        self.stream,
+                        entry.id
+                    );
+                    invalid.push(entry.id);
+                }
+            }
+        }
+        self.ack(&invalid).await?;
+        Ok(events)
+    }
+}
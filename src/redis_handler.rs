@@ -1,93 +1,207 @@
-use std::sync::{
-    atomic::{AtomicU64, Ordering},
-    Arc,
-};
+use std::collections::HashMap;
+use std::sync::Mutex;
 
 use async_trait::async_trait;
-use inevents_redis::RedisEventStream;
-use inindexer::near_indexer_primitives::types::AccountId;
-use intear_events::events::newcontract::meme_cooking_token::{
-    NewMemeCookingTokenEvent, NewMemeCookingTokenEventData,
-};
+use inindexer::near_indexer_primitives::types::{AccountId, BlockHeight};
+use intear_events::events::newcontract::meme_cooking_token::NewMemeCookingTokenEvent;
 use intear_events::events::newcontract::{
-    meme_cooking_meme::{NewMemeCookingMemeEvent, NewMemeCookingMemeEventData},
-    nep141::{NewContractNep141Event, NewContractNep141EventData},
+    meme_cooking_meme::NewMemeCookingMemeEvent, nep141::NewContractNep141Event,
+};
+use redis::{aio::ConnectionManager, RedisResult};
+use serde::Serialize;
+
+use crate::bridge;
+use crate::events::{
+    BridgedTokenCreatedEvent, BridgedTokenCreatedEventData, LaunchpadTokenCreatedEvent,
+    LaunchpadTokenCreatedEventData, MemeCookingCampaignOutcomeEventData,
+    MemeExpiredWithoutTokenEvent, MemeReachedHardCapEvent, MemeReachedSoftCapEvent,
+    NewContractNep141EventData, NewMemeCookingMemeEventData, NewMemeCookingTokenEventData,
+    TokenFirstPoolEvent, TokenFirstPoolEventData, TokenHoldersSnapshotEvent,
+    TokenHoldersSnapshotEventData, TokenInitialDistributionEvent,
+    TokenInitialDistributionEventData, TokenMetadataUpdatedEvent, TokenMetadataUpdatedEventData,
+    TokenSupplyChangedEvent, TokenSupplyChangedEventData,
+};
+use crate::holders;
+use crate::initial_distribution;
+use crate::launchpad;
+use crate::meme_cooking::{MemeCookingCampaignOutcomeEvent, MemeCookingCreateTokenEvent};
+use crate::metadata_updates;
+use crate::new_nep141::NewNep141Event;
+use crate::redis_consumer::EVENT_FIELD;
+use crate::ref_finance;
+use crate::supply;
+use crate::{
+    meme_cooking::MemeCookingCreateMemeEvent, BlockContext, ContractEventHandler, EventContext,
 };
-use redis::aio::ConnectionManager;
 
-use crate::meme_cooking::MemeCookingCreateTokenEvent;
-use crate::{meme_cooking::MemeCookingCreateMemeEvent, ContractEventHandler, EventContext};
+/// How many events are kept in a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamRetention {
+    /// The last N events
+    MaxLen(usize),
+    /// Events of the last N blocks
+    MaxBlocks(BlockHeight),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedisRetention {
+    /// Retention of streams that are not in `streams`
+    pub default: StreamRetention,
+    /// Retention of event ids like `newcontract_nep141`
+    pub streams: HashMap<String, StreamRetention>,
+    /// Trim with `~`: Redis only removes whole nodes of the stream, so a stream can be a bit
+    /// longer than its limit, but trimming is much cheaper
+    pub approximate: bool,
+}
+
+impl RedisRetention {
+    pub fn new(default: StreamRetention) -> Self {
+        Self {
+            default,
+            streams: HashMap::new(),
+            approximate: false,
+        }
+    }
+
+    pub fn get(&self, event_id: &str) -> StreamRetention {
+        self.streams.get(event_id).copied().unwrap_or(self.default)
+    }
+}
+
+/// Parses `maxlen:N` or `blocks:N`
+pub fn parse_stream_retention(s: &str) -> Result<StreamRetention, String> {
+    let (kind, value) = s
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid retention: {s}"))?;
+    let value = value
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid retention: {s}"))?;
+    match kind.trim() {
+        "maxlen" => Ok(StreamRetention::MaxLen(value as usize)),
+        "blocks" => Ok(StreamRetention::MaxBlocks(value)),
+        _ => Err(format!("Invalid retention: {s}")),
+    }
+}
+
+/// Parses `event_id=maxlen:N,event_id=blocks:N`
+pub fn parse_redis_retention(s: &str) -> Result<HashMap<String, StreamRetention>, String> {
+    s.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((event_id, retention)) if !event_id.trim().is_empty() => Ok((
+                event_id.trim().to_string(),
+                parse_stream_retention(retention)?,
+            )),
+            _ => Err(format!("Invalid stream retention: {entry}")),
+        })
+        .collect()
+}
 
 pub struct PushToRedisStream {
-    nep141_stream: RedisEventStream<NewContractNep141EventData>,
-    meme_cooking_meme_stream: RedisEventStream<NewMemeCookingMemeEventData>,
-    meme_cooking_token_stream: RedisEventStream<NewMemeCookingTokenEventData>,
-    max_stream_size: usize,
+    connection: ConnectionManager,
+    retention: RedisRetention,
     testnet: bool,
-    // We sometimes give RPC 5 seconds to catch up, but if another token is created in the meantime, we don't
-    // want to have "The ID specified in XADD is equal or smaller than the target stream top item" error
-    latest_nep141_block: Arc<AtomicU64>,
+    // Events can be emitted after newer ones of the same stream (RPC is given 5 seconds to catch up,
+    // first mints wait for their token to be detected, meme references are downloaded in the background),
+    // but we don't want to have "The ID specified in XADD is equal or smaller than the target stream top item"
+    // error, so ids of such events use the latest block of their stream
+    latest_blocks: Mutex<HashMap<String, BlockHeight>>,
 }
 
 impl PushToRedisStream {
-    pub async fn new(connection: ConnectionManager, max_stream_size: usize, testnet: bool) -> Self {
+    pub async fn new(
+        connection: ConnectionManager,
+        retention: RedisRetention,
+        testnet: bool,
+    ) -> Self {
         Self {
-            nep141_stream: RedisEventStream::new(
-                connection.clone(),
-                if testnet {
-                    format!("{}_testnet", NewContractNep141Event::ID)
-                } else {
-                    NewContractNep141Event::ID.to_string()
-                },
-            ),
-            meme_cooking_meme_stream: RedisEventStream::new(
-                connection.clone(),
-                if testnet {
-                    format!("{}_testnet", NewMemeCookingMemeEvent::ID)
-                } else {
-                    NewMemeCookingMemeEvent::ID.to_string()
-                },
-            ),
-            meme_cooking_token_stream: RedisEventStream::new(
-                connection.clone(),
-                if testnet {
-                    format!("{}_testnet", NewMemeCookingTokenEvent::ID)
-                } else {
-                    NewMemeCookingTokenEvent::ID.to_string()
-                },
-            ),
-            max_stream_size,
+            connection,
+            retention,
             testnet,
-            latest_nep141_block: Arc::new(AtomicU64::new(0)),
+            latest_blocks: Mutex::new(HashMap::new()),
         }
     }
+
+    /// Adds the event and trims the stream with a single `XADD`
+    async fn emit<E: Serialize>(
+        &self,
+        event_id: &str,
+        id_block_height: BlockHeight,
+        data: E,
+    ) -> RedisResult<()> {
+        let id_block_height = {
+            let mut latest_blocks = self.latest_blocks.lock().unwrap();
+            let latest = latest_blocks.entry(event_id.to_string()).or_default();
+            *latest = id_block_height.max(*latest);
+            *latest
+        };
+        let json = serde_json::to_string(&data).map_err(|err| {
+            redis::RedisError::from((
+                redis::ErrorKind::TypeError,
+                "Failed to serialize event",
+                err.to_string(),
+            ))
+        })?;
+        let mut xadd = redis::cmd("XADD");
+        xadd.arg(stream_name(event_id, self.testnet));
+        let (strategy, threshold) = match self.retention.get(event_id) {
+            StreamRetention::MaxLen(max_len) => ("MAXLEN", max_len as u64),
+            // Entry ids start with the block height
+            StreamRetention::MaxBlocks(blocks) => ("MINID", id_block_height.saturating_sub(blocks)),
+        };
+        xadd.arg(strategy);
+        if self.retention.approximate {
+            xadd.arg("~");
+        }
+        xadd.arg(threshold)
+            .arg(format!("{id_block_height}-*"))
+            .arg(EVENT_FIELD)
+            .arg(json);
+        let _id: String = xadd.query_async(&mut self.connection.clone()).await?;
+        Ok(())
+    }
+
+    async fn emit_campaign_outcome(
+        &self,
+        event_id: &str,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.emit(
+            event_id,
+            context.block_height,
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await
+        .expect("Failed to emit meme cooking campaign outcome event");
+    }
+}
+
+pub(crate) fn stream_name(id: &str, testnet: bool) -> String {
+    if testnet {
+        format!("{id}_testnet")
+    } else {
+        id.to_string()
+    }
 }
 
 #[async_trait]
 impl ContractEventHandler for PushToRedisStream {
     async fn handle_new_nep141(&self, account_id: AccountId, context: EventContext) {
-        let latest_handled = self.latest_nep141_block.load(Ordering::Relaxed);
-        self.nep141_stream
-            .emit_event(
-                if context.block_height >= latest_handled {
-                    self.latest_nep141_block
-                        .store(context.block_height, Ordering::Relaxed);
-                    context.block_height
-                } else {
-                    latest_handled
-                },
-                NewContractNep141EventData {
-                    account_id,
-
-                    transaction_id: context.transaction_id,
-                    receipt_id: context.receipt_id,
-                    block_height: context.block_height,
-                    block_timestamp_nanosec: context.block_timestamp_nanosec,
-                },
-                self.max_stream_size,
-            )
+        self.handle_new_nep141_with_metadata(NewNep141Event::new(account_id, None, None), context)
             .await
-            .expect("Failed to emit nep141 creation event");
+    }
+
+    async fn handle_new_nep141_with_metadata(&self, event: NewNep141Event, context: EventContext) {
+        self.emit(
+            NewContractNep141Event::ID,
+            context.block_height,
+            NewContractNep141EventData::new(event, context),
+        )
+        .await
+        .expect("Failed to emit nep141 creation event");
     }
 
     async fn handle_meme_cooking_new_meme(
@@ -95,32 +209,13 @@ impl ContractEventHandler for PushToRedisStream {
         event: MemeCookingCreateMemeEvent,
         context: EventContext,
     ) {
-        self.meme_cooking_meme_stream
-            .emit_event(
-                context.block_height,
-                NewMemeCookingMemeEventData {
-                    transaction_id: context.transaction_id,
-                    receipt_id: context.receipt_id,
-                    block_height: context.block_height,
-                    block_timestamp_nanosec: context.block_timestamp_nanosec,
-
-                    meme_id: event.meme_id,
-                    owner: event.owner,
-                    end_timestamp_ms: event.end_timestamp_ms,
-                    name: event.name,
-                    symbol: event.symbol,
-                    decimals: event.decimals,
-                    total_supply: event.total_supply,
-                    reference: event.reference,
-                    reference_hash: event.reference_hash,
-                    deposit_token_id: event.deposit_token_id,
-                    soft_cap: event.soft_cap,
-                    hard_cap: event.hard_cap,
-                },
-                self.max_stream_size,
-            )
-            .await
-            .expect("Failed to emit meme cooking event");
+        self.emit(
+            NewMemeCookingMemeEvent::ID,
+            context.block_height,
+            NewMemeCookingMemeEventData::new(event, context),
+        )
+        .await
+        .expect("Failed to emit meme cooking event");
     }
 
     async fn handle_meme_cooking_new_token(
@@ -128,24 +223,138 @@ impl ContractEventHandler for PushToRedisStream {
         event: MemeCookingCreateTokenEvent,
         context: EventContext,
     ) {
-        self.meme_cooking_token_stream
-            .emit_event(
-                context.block_height,
-                NewMemeCookingTokenEventData {
-                    transaction_id: context.transaction_id,
-                    receipt_id: context.receipt_id,
-                    block_height: context.block_height,
-                    block_timestamp_nanosec: context.block_timestamp_nanosec,
-
-                    meme_id: event.meme_id,
-                    token_id: event.token_id,
-                    total_supply: event.total_supply,
-                    pool_id: event.pool_id,
-                },
-                self.max_stream_size,
-            )
-            .await
-            .expect("Failed to emit meme cooking event");
+        self.emit(
+            NewMemeCookingTokenEvent::ID,
+            context.block_height,
+            NewMemeCookingTokenEventData::new(event, context),
+        )
+        .await
+        .expect("Failed to emit meme cooking event");
+    }
+
+    async fn handle_meme_cooking_reached_soft_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.emit_campaign_outcome(MemeReachedSoftCapEvent::ID, event, context)
+            .await;
+    }
+
+    async fn handle_meme_cooking_reached_hard_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.emit_campaign_outcome(MemeReachedHardCapEvent::ID, event, context)
+            .await;
+    }
+
+    async fn handle_meme_cooking_expired_without_token(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.emit_campaign_outcome(MemeExpiredWithoutTokenEvent::ID, event, context)
+            .await;
+    }
+
+    async fn handle_launchpad_token_created(
+        &self,
+        event: launchpad::LaunchpadTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.emit(
+            LaunchpadTokenCreatedEvent::ID,
+            context.block_height,
+            LaunchpadTokenCreatedEventData::new(event, context),
+        )
+        .await
+        .expect("Failed to emit launchpad token event");
+    }
+
+    async fn handle_bridged_token_created(
+        &self,
+        event: bridge::BridgedTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.emit(
+            BridgedTokenCreatedEvent::ID,
+            context.block_height,
+            BridgedTokenCreatedEventData::new(event, context),
+        )
+        .await
+        .expect("Failed to emit bridged token event");
+    }
+
+    async fn handle_token_first_pool(
+        &self,
+        event: ref_finance::TokenFirstPoolEvent,
+        context: EventContext,
+    ) {
+        self.emit(
+            TokenFirstPoolEvent::ID,
+            context.block_height,
+            TokenFirstPoolEventData::new(event, context),
+        )
+        .await
+        .expect("Failed to emit first pool event");
+    }
+
+    async fn handle_token_initial_distribution(
+        &self,
+        event: initial_distribution::TokenInitialDistributionEvent,
+        context: EventContext,
+    ) {
+        self.emit(
+            TokenInitialDistributionEvent::ID,
+            context.block_height,
+            TokenInitialDistributionEventData::new(event, context),
+        )
+        .await
+        .expect("Failed to emit initial distribution event");
+    }
+
+    async fn handle_token_supply_changed(
+        &self,
+        event: supply::TokenSupplyChangedEvent,
+        context: BlockContext,
+    ) {
+        self.emit(
+            TokenSupplyChangedEvent::ID,
+            context.block_height,
+            TokenSupplyChangedEventData::new(event, context),
+        )
+        .await
+        .expect("Failed to emit supply event");
+    }
+
+    async fn handle_token_holders_snapshot(
+        &self,
+        event: holders::TokenHoldersSnapshotEvent,
+        context: BlockContext,
+    ) {
+        self.emit(
+            TokenHoldersSnapshotEvent::ID,
+            context.block_height,
+            TokenHoldersSnapshotEventData::new(event, context),
+        )
+        .await
+        .expect("Failed to emit holders event");
+    }
+
+    async fn handle_token_metadata_updated(
+        &self,
+        event: metadata_updates::TokenMetadataUpdatedEvent,
+        context: BlockContext,
+    ) {
+        self.emit(
+            TokenMetadataUpdatedEvent::ID,
+            context.block_height,
+            TokenMetadataUpdatedEventData::new(event, context),
+        )
+        .await
+        .expect("Failed to emit metadata update event");
     }
 
     fn is_testnet(&self) -> bool {
@@ -0,0 +1,256 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use inindexer::{
+    near_indexer_primitives::{
+        types::{AccountId, Balance, BlockHeight},
+        views::{ActionView, ExecutionStatusView, ReceiptEnumView},
+        StreamerMessage,
+    },
+    near_utils::dec_format,
+    IncompleteTransaction, TransactionReceipt,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{new_nep141::HandledTokensStorage, ContractEventHandler, EventContext};
+
+/// Pending pools that didn't get liquidity in this many blocks (~10 days) are forgotten
+pub const DEFAULT_PENDING_POOL_MAX_AGE_BLOCKS: BlockHeight = 864_000;
+
+/// Detects the first Ref Finance pool of each indexed token. A pool is only considered
+/// once liquidity is added to it, since an empty pool doesn't make the token tradable.
+pub struct RefFinanceIndexer {
+    tokens: Arc<dyn HandledTokensStorage>,
+    pools: Arc<dyn RefPoolStorage>,
+    pub contract_id: AccountId,
+    pub pending_pool_max_age_blocks: BlockHeight,
+}
+
+impl RefFinanceIndexer {
+    pub fn new(
+        tokens: Arc<dyn HandledTokensStorage>,
+        pools: impl RefPoolStorage + 'static,
+        testnet: bool,
+    ) -> Self {
+        Self {
+            tokens,
+            pools: Arc::new(pools),
+            contract_id: if testnet {
+                "ref-finance-101.testnet".parse().unwrap()
+            } else {
+                "v2.ref-finance.near".parse().unwrap()
+            },
+            pending_pool_max_age_blocks: DEFAULT_PENDING_POOL_MAX_AGE_BLOCKS,
+        }
+    }
+
+    pub async fn detect_first_pools<T: ContractEventHandler>(
+        &mut self,
+        receipt: &TransactionReceipt,
+        tx: &IncompleteTransaction,
+        block: &StreamerMessage,
+        handler: Arc<T>,
+    ) {
+        if receipt.receipt.receipt.receiver_id != self.contract_id {
+            return;
+        }
+        let ReceiptEnumView::Action { actions, .. } = &receipt.receipt.receipt.receipt else {
+            return;
+        };
+        for (i, action) in actions.iter().enumerate() {
+            let ActionView::FunctionCall {
+                method_name, args, ..
+            } = action
+            else {
+                continue;
+            };
+            let kind = match method_name.as_str() {
+                "add_simple_pool" => RefPoolKind::Simple,
+                "add_stable_swap_pool" => RefPoolKind::StableSwap,
+                "add_liquidity" | "add_stable_liquidity" => {
+                    let Ok(args) = serde_json::from_slice::<AddLiquidityArgs>(args) else {
+                        log::warn!("Failed to parse Ref Finance {method_name} arguments");
+                        continue;
+                    };
+                    let context = EventContext::new(receipt, tx, block);
+                    self.add_liquidity(args, context, &*handler).await;
+                    continue;
+                }
+                _ => continue,
+            };
+
+            // Pool id is only known from the return value, which belongs to the last action
+            if i != actions.len() - 1 {
+                log::warn!("Ref Finance {method_name} is not the last action of the receipt");
+                continue;
+            }
+            let ExecutionStatusView::SuccessValue(value) =
+                &receipt.receipt.execution_outcome.outcome.status
+            else {
+                continue;
+            };
+            let (Ok(pool_id), Ok(args)) = (
+                serde_json::from_slice::<u64>(value),
+                serde_json::from_slice::<AddPoolArgs>(args),
+            ) else {
+                log::warn!("Failed to parse Ref Finance {method_name} call");
+                continue;
+            };
+            let mut has_new_tokens = false;
+            for token_id in args.tokens.iter() {
+                if self.tokens.is_already_indexed(token_id).await
+                    && !self.pools.has_pool(token_id).await
+                {
+                    has_new_tokens = true;
+                }
+            }
+            if has_new_tokens {
+                let block_height = block.block.header.height;
+                self.pools
+                    .remove_pending_pools_before(
+                        block_height.saturating_sub(self.pending_pool_max_age_blocks),
+                    )
+                    .await;
+                self.pools
+                    .save_pending_pool(RefPool {
+                        pool_id,
+                        kind,
+                        tokens: args.tokens,
+                        created_at_block: block_height,
+                    })
+                    .await;
+            }
+        }
+    }
+
+    async fn add_liquidity<T: ContractEventHandler>(
+        &self,
+        args: AddLiquidityArgs,
+        context: EventContext,
+        handler: &T,
+    ) {
+        let Some(pool) = self.pools.get_pending_pool(args.pool_id).await else {
+            return;
+        };
+        let Some(initial_liquidity) = pool.liquidity(&args.amounts) else {
+            log::warn!(
+                "Ref Finance pool {} got liquidity for a different number of tokens",
+                pool.pool_id
+            );
+            return;
+        };
+        for token_id in pool.tokens.iter() {
+            if !self.tokens.is_already_indexed(token_id).await
+                || self.pools.has_pool(token_id).await
+            {
+                continue;
+            }
+            self.pools.mark_has_pool(token_id.clone()).await;
+            log::info!("Found first pool of {token_id}: {}", pool.pool_id);
+            handler
+                .handle_token_first_pool(
+                    TokenFirstPoolEvent {
+                        token_id: token_id.clone(),
+                        pool_id: pool.pool_id,
+                        pool_kind: pool.kind,
+                        counter_tokens: pool
+                            .tokens
+                            .iter()
+                            .filter(|other| *other != token_id)
+                            .cloned()
+                            .collect(),
+                        initial_liquidity: initial_liquidity.clone(),
+                    },
+                    context.clone(),
+                )
+                .await;
+        }
+        self.pools.remove_pending_pool(pool.pool_id).await;
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AddPoolArgs {
+    tokens: Vec<AccountId>,
+}
+
+/// Arguments of both `add_liquidity` and `add_stable_liquidity`. For the first
+/// deposit to an empty pool, all of `amounts` end up in the pool.
+#[derive(Debug, Deserialize)]
+pub struct AddLiquidityArgs {
+    pub pool_id: u64,
+    pub amounts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefPoolKind {
+    Simple,
+    StableSwap,
+}
+
+/// A pool that includes a token without a pool yet, but doesn't have liquidity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RefPool {
+    pub pool_id: u64,
+    pub kind: RefPoolKind,
+    pub tokens: Vec<AccountId>,
+    /// Missing from files of older versions, which makes those pools expire first
+    #[serde(default)]
+    pub created_at_block: BlockHeight,
+}
+
+impl RefPool {
+    /// Matches `amounts` from `add_liquidity` with the pool's tokens
+    pub fn liquidity(&self, amounts: &[String]) -> Option<Vec<PoolTokenAmount>> {
+        if amounts.len() != self.tokens.len() {
+            return None;
+        }
+        self.tokens
+            .iter()
+            .zip(amounts)
+            .map(|(token_id, amount)| {
+                Some(PoolTokenAmount {
+                    token_id: token_id.clone(),
+                    amount: amount.parse().ok()?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolTokenAmount {
+    pub token_id: AccountId,
+    #[serde(with = "dec_format")]
+    pub amount: Balance,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RefPools {
+    pub pending: HashMap<u64, RefPool>,
+    pub tokens_with_pool: HashSet<AccountId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenFirstPoolEvent {
+    pub token_id: AccountId,
+    pub pool_id: u64,
+    pub pool_kind: RefPoolKind,
+    pub counter_tokens: Vec<AccountId>,
+    pub initial_liquidity: Vec<PoolTokenAmount>,
+}
+
+#[async_trait]
+pub trait RefPoolStorage: Send + Sync {
+    async fn has_pool(&self, token_id: &AccountId) -> bool;
+    async fn mark_has_pool(&self, token_id: AccountId);
+    async fn save_pending_pool(&self, pool: RefPool);
+    async fn get_pending_pool(&self, pool_id: u64) -> Option<RefPool>;
+    async fn remove_pending_pool(&self, pool_id: u64);
+    /// Forgets pools that were created before `block_height` and never got liquidity
+    async fn remove_pending_pools_before(&self, block_height: BlockHeight);
+}
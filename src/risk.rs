@@ -0,0 +1,283 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use inindexer::near_indexer_primitives::{
+    types::{AccountId, Balance, BlockHeight, BlockId, BlockReference},
+    views::QueryRequest,
+};
+use near_jsonrpc_client::{
+    methods::{self, query::RpcQueryError},
+    JsonRpcClient,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{new_nep141::NewNep141Event, supply::ft_total_supply, EventContext};
+
+/// A token with more than this many whole tokens is considered to have a huge supply
+const HUGE_SUPPLY_TOKENS: Balance = 1_000_000_000_000_000;
+
+pub struct RiskScorerOptions {
+    /// Symbols (normalized with [`normalize_symbol`]) of well-known tokens and the only accounts
+    /// that are allowed to use them
+    pub allowlist: HashMap<String, HashSet<AccountId>>,
+    /// Factories that are known to deploy spam tokens
+    pub spam_factories: HashSet<AccountId>,
+    /// Deployers that didn't exist this many blocks before the deployment are considered new
+    pub new_account_blocks: BlockHeight,
+}
+
+impl Default for RiskScorerOptions {
+    fn default() -> Self {
+        let allowlist = [
+            (
+                "USDC",
+                &[
+                    "17208628f84f5d6ad33f0da3bbbeb27ffcb398eac501a31bd6ad2011e36133a1",
+                    "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48.factory.bridge.near",
+                ][..],
+            ),
+            (
+                "USDT",
+                &[
+                    "usdt.tether-token.near",
+                    "dac17f958d2ee523a2206206994597c13d831ec7.factory.bridge.near",
+                ][..],
+            ),
+            ("WNEAR", &["wrap.near"][..]),
+            ("NEAR", &["wrap.near"][..]),
+            ("REF", &["token.v2.ref-finance.near"][..]),
+        ]
+        .into_iter()
+        .map(|(symbol, accounts)| {
+            (
+                symbol.to_string(),
+                accounts
+                    .iter()
+                    .map(|account_id| account_id.parse().unwrap())
+                    .collect(),
+            )
+        })
+        .collect();
+        Self {
+            allowlist,
+            spam_factories: HashSet::new(),
+            new_account_blocks: 86_400,
+        }
+    }
+}
+
+/// Scores how likely a new token is to be spam or a scam
+pub struct RiskScorer {
+    rpc_client: JsonRpcClient,
+    options: RiskScorerOptions,
+}
+
+impl RiskScorer {
+    pub fn new(rpc_client: JsonRpcClient, options: RiskScorerOptions) -> Self {
+        Self {
+            rpc_client,
+            options,
+        }
+    }
+
+    pub async fn assess(
+        &self,
+        event: &NewNep141Event,
+        deployer: &AccountId,
+        context: &EventContext,
+    ) -> RiskAssessment {
+        let total_supply = match event.total_supply {
+            Some(total_supply) => Some(total_supply),
+            None => {
+                ft_total_supply(&event.account_id, context.block_height, &self.rpc_client).await
+            }
+        };
+        let mut reasons = self.check(event, context, total_supply);
+        if self.is_new_account(deployer, context.block_height).await {
+            reasons.push(RiskReason::NewDeployer);
+        }
+        RiskAssessment::new(reasons)
+    }
+
+    /// Whether the account didn't exist `new_account_blocks` before the block. Asking the RPC
+    /// instead of watching account creations also works for accounts created before a restart.
+    async fn is_new_account(&self, account_id: &AccountId, block_height: BlockHeight) -> bool {
+        let Some(block_height) = block_height.checked_sub(self.options.new_account_blocks) else {
+            return false;
+        };
+        let result = self
+            .rpc_client
+            .call(methods::query::RpcQueryRequest {
+                block_reference: BlockReference::BlockId(BlockId::Height(block_height)),
+                request: QueryRequest::ViewAccount {
+                    account_id: account_id.clone(),
+                },
+            })
+            .await;
+        match result {
+            Ok(_) => false,
+            Err(err) => {
+                if let Some(RpcQueryError::UnknownAccount { .. }) = err.handler_error() {
+                    true
+                } else {
+                    log::warn!("Failed to check when {account_id} was created: {err}");
+                    false
+                }
+            }
+        }
+    }
+
+    /// Checks that don't need the RPC
+    pub fn check(
+        &self,
+        event: &NewNep141Event,
+        context: &EventContext,
+        total_supply: Option<Balance>,
+    ) -> Vec<RiskReason> {
+        let mut reasons = Vec::new();
+        if let Some(metadata) = &event.metadata {
+            if let Some(allowed) = self
+                .options
+                .allowlist
+                .get(&normalize_symbol(&metadata.symbol))
+            {
+                if !allowed.contains(&event.account_id) {
+                    reasons.push(RiskReason::SymbolCollision);
+                }
+            }
+            if metadata
+                .symbol
+                .chars()
+                .chain(metadata.name.chars())
+                .any(is_lookalike)
+            {
+                reasons.push(RiskReason::LookalikeUnicode);
+            }
+            if metadata.decimals == 0 {
+                reasons.push(RiskReason::ZeroDecimals);
+            }
+            if let Some(total_supply) = total_supply {
+                let whole_tokens = 10u128
+                    .checked_pow(metadata.decimals.into())
+                    .map_or(0, |one| total_supply / one);
+                if whole_tokens > HUGE_SUPPLY_TOKENS {
+                    reasons.push(RiskReason::HugeSupply);
+                }
+            }
+        }
+        if context
+            .factory_id
+            .iter()
+            .chain([&context.predecessor_id])
+            .any(|account_id| self.options.spam_factories.contains(account_id))
+        {
+            reasons.push(RiskReason::SpamFactory);
+        }
+        reasons
+    }
+}
+
+/// Reads a JSON object of symbols to arrays of account ids that are allowed to use them.
+/// Symbols are normalized the same way as the symbols of new tokens.
+pub async fn load_allowlist(
+    path: impl AsRef<Path>,
+) -> anyhow::Result<HashMap<String, HashSet<AccountId>>> {
+    let allowlist: HashMap<String, HashSet<AccountId>> =
+        serde_json::from_slice(&tokio::fs::read(path).await?)?;
+    let mut normalized = HashMap::<String, HashSet<AccountId>>::new();
+    for (symbol, accounts) in allowlist {
+        normalized
+            .entry(normalize_symbol(&symbol))
+            .or_default()
+            .extend(accounts);
+    }
+    Ok(normalized)
+}
+
+/// Uppercase ASCII version of a symbol, with lookalike letters replaced
+/// by the ones they look like and invisible characters removed
+pub fn normalize_symbol(symbol: &str) -> String {
+    symbol
+        .chars()
+        .filter_map(|c| match c {
+            '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}' => None,
+            // Fullwidth forms
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFF01 + 0x21),
+            'а' | 'А' | 'α' | 'Α' => Some('A'),
+            'в' | 'В' | 'β' | 'Β' => Some('B'),
+            'с' | 'С' | 'ϲ' => Some('C'),
+            'е' | 'Е' | 'ε' | 'Ε' => Some('E'),
+            'н' | 'Н' | 'η' | 'Η' => Some('H'),
+            'і' | 'І' | 'ι' | 'Ι' => Some('I'),
+            'к' | 'К' | 'κ' | 'Κ' => Some('K'),
+            'м' | 'М' | 'Μ' => Some('M'),
+            'п' | 'ν' | 'Ν' => Some('N'),
+            'о' | 'О' | 'ο' | 'Ο' => Some('O'),
+            'р' | 'Р' | 'ρ' | 'Ρ' => Some('P'),
+            'ѕ' | 'Ѕ' => Some('S'),
+            'т' | 'Т' | 'τ' | 'Τ' => Some('T'),
+            'у' | 'У' | 'υ' | 'Υ' => Some('Y'),
+            'х' | 'Х' | 'χ' | 'Χ' => Some('X'),
+            'ԁ' => Some('D'),
+            'ј' | 'Ј' => Some('J'),
+            'ԛ' => Some('Q'),
+            'ԝ' | 'Ԝ' => Some('W'),
+            c => Some(c),
+        })
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+fn is_lookalike(c: char) -> bool {
+    !c.is_ascii() && normalize_symbol(&c.to_string()) != c.to_uppercase().collect::<String>()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskReason {
+    /// Uses a symbol of a well-known token
+    SymbolCollision,
+    /// Name or symbol contains characters that look like ASCII letters
+    LookalikeUnicode,
+    ZeroDecimals,
+    HugeSupply,
+    /// Deployed by an account that was created recently
+    NewDeployer,
+    /// Created by a factory that is known for spam tokens, or deployed by its call
+    SpamFactory,
+}
+
+impl RiskReason {
+    pub fn weight(&self) -> u8 {
+        match self {
+            RiskReason::SymbolCollision => 50,
+            RiskReason::LookalikeUnicode => 30,
+            RiskReason::SpamFactory => 40,
+            RiskReason::NewDeployer => 20,
+            RiskReason::ZeroDecimals => 10,
+            RiskReason::HugeSupply => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RiskAssessment {
+    /// 0 to 100, higher is riskier
+    pub score: u8,
+    pub reasons: Vec<RiskReason>,
+}
+
+impl RiskAssessment {
+    pub fn new(reasons: Vec<RiskReason>) -> Self {
+        Self {
+            score: reasons
+                .iter()
+                .map(|reason| reason.weight())
+                .fold(0u8, u8::saturating_add)
+                .min(100),
+            reasons,
+        }
+    }
+}
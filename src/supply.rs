@@ -0,0 +1,267 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use inindexer::{
+    near_indexer_primitives::{
+        types::{AccountId, Balance, BlockHeight, BlockId, BlockReference},
+        views::QueryRequest,
+        StreamerMessage,
+    },
+    near_utils::{dec_format, EventLogData, FtBurnLog, FtMintLog},
+    TransactionReceipt,
+};
+use near_jsonrpc_client::{
+    methods::{self, query::QueryResponseKind},
+    JsonRpcClient,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{new_nep141::HandledTokensStorage, BlockContext, ContractEventHandler};
+
+pub struct SupplyTrackerOptions {
+    /// How often `token_supply_changed` events are emitted
+    pub interval_blocks: BlockHeight,
+    /// How many changed tokens are compared with `ft_total_supply` on each interval,
+    /// starting with the ones that haven't been checked for the longest time
+    pub rpc_checks_per_interval: usize,
+}
+
+impl Default for SupplyTrackerOptions {
+    fn default() -> Self {
+        Self {
+            interval_blocks: 100,
+            rpc_checks_per_interval: 5,
+        }
+    }
+}
+
+/// Follows mints and burns of known tokens. Changes are kept in memory and applied to
+/// the storage once per interval, so that busy tokens don't cause a write on every receipt.
+pub struct SupplyTracker {
+    tokens: Arc<dyn HandledTokensStorage>,
+    storage: Arc<dyn TokenSupplyStorage>,
+    rpc_client: JsonRpcClient,
+    options: SupplyTrackerOptions,
+    changes: HashMap<AccountId, SupplyChange>,
+    interval_start: Option<BlockHeight>,
+}
+
+impl SupplyTracker {
+    pub fn new(
+        tokens: Arc<dyn HandledTokensStorage>,
+        storage: impl TokenSupplyStorage + 'static,
+        rpc_client: JsonRpcClient,
+        options: SupplyTrackerOptions,
+    ) -> Self {
+        Self {
+            tokens,
+            storage: Arc::new(storage),
+            rpc_client,
+            options,
+            changes: HashMap::new(),
+            interval_start: None,
+        }
+    }
+
+    pub async fn track_supply(&mut self, receipt: &TransactionReceipt) {
+        let token_id = &receipt.receipt.receipt.receiver_id;
+        let change = SupplyChange::from_logs(&receipt.receipt.execution_outcome.outcome.logs);
+        if change == SupplyChange::default() || !self.tokens.is_already_indexed(token_id).await {
+            return;
+        }
+        self.changes
+            .entry(token_id.clone())
+            .or_default()
+            .add(&change);
+    }
+
+    pub async fn process_block_end<T: ContractEventHandler>(
+        &mut self,
+        block: &StreamerMessage,
+        handler: Arc<T>,
+    ) {
+        let block_height = block.block.header.height;
+        let interval_start = *self.interval_start.get_or_insert(block_height);
+        if block_height < interval_start + self.options.interval_blocks {
+            return;
+        }
+        self.interval_start = Some(block_height);
+        let context = BlockContext {
+            block_height,
+            block_timestamp_nanosec: block.block.header.timestamp_nanosec as u128,
+        };
+
+        let mut changed = Vec::new();
+        let mut supplies = Vec::new();
+        for (token_id, change) in std::mem::take(&mut self.changes) {
+            let supply = self.storage.get_supply(&token_id).await.unwrap_or_default();
+            changed.push((token_id, change, supply));
+        }
+        changed.sort_by_key(|(token_id, _, supply)| (supply.last_checked_block, token_id.clone()));
+
+        for (i, (token_id, change, mut supply)) in changed.into_iter().enumerate() {
+            supply.minted = supply.minted.saturating_add(change.minted);
+            supply.burned = supply.burned.saturating_add(change.burned);
+            let mut rpc_total_supply = None;
+            let mut diverged = false;
+            if i < self.options.rpc_checks_per_interval {
+                if let Some(total_supply) =
+                    ft_total_supply(&token_id, block_height, &self.rpc_client).await
+                {
+                    rpc_total_supply = Some(total_supply);
+                    // A supply that doesn't fit in i128 can't be tracked, so it's never equal
+                    diverged = supply
+                        .tracked_supply()
+                        .is_some_and(|tracked| tracked != total_supply)
+                        || i128::try_from(total_supply).is_err();
+                    if diverged {
+                        log::warn!(
+                            "Tracked supply of {token_id} is {:?}, but ft_total_supply is {total_supply}",
+                            supply.tracked_supply()
+                        );
+                    }
+                    // Calibrate on the first check, and after a divergence so that it's only reported once
+                    if supply.baseline.is_none() || diverged {
+                        supply.calibrate(total_supply);
+                    }
+                    supply.last_checked_block = Some(block_height);
+                }
+            }
+            handler
+                .handle_token_supply_changed(
+                    TokenSupplyChangedEvent {
+                        token_id: token_id.clone(),
+                        minted: change.minted,
+                        burned: change.burned,
+                        total_supply: supply.tracked_supply(),
+                        rpc_total_supply,
+                        diverged,
+                    },
+                    context.clone(),
+                )
+                .await;
+            supplies.push((token_id, supply));
+        }
+        self.storage.save_supplies(supplies).await;
+    }
+}
+
+pub(crate) async fn ft_total_supply(
+    account_id: &AccountId,
+    block_height: BlockHeight,
+    rpc_client: &JsonRpcClient,
+) -> Option<Balance> {
+    let response = rpc_client
+        .call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::BlockId(BlockId::Height(block_height)),
+            request: QueryRequest::CallFunction {
+                account_id: account_id.clone(),
+                method_name: "ft_total_supply".to_string(),
+                args: serde_json::to_vec(&serde_json::json!({})).unwrap().into(),
+            },
+        })
+        .await
+        .inspect_err(|err| log::warn!("Failed to get ft_total_supply of {account_id}: {err}"))
+        .ok()?;
+    let QueryResponseKind::CallResult(result) = response.kind else {
+        return None;
+    };
+    serde_json::from_slice::<String>(&result.result)
+        .ok()?
+        .parse()
+        .ok()
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SupplyChange {
+    pub minted: Balance,
+    pub burned: Balance,
+}
+
+impl SupplyChange {
+    pub fn from_logs(logs: &[String]) -> Self {
+        let mut change = Self::default();
+        for log in logs {
+            // Mint and burn events have the same format, so the event name has to be checked
+            if let Ok(event) = EventLogData::<FtMintLog>::deserialize(log) {
+                if event.standard == "nep141" && event.event == "ft_mint" {
+                    for mint in event.data {
+                        change.minted = change.minted.saturating_add(mint.amount);
+                    }
+                }
+            }
+            if let Ok(event) = EventLogData::<FtBurnLog>::deserialize(log) {
+                if event.standard == "nep141" && event.event == "ft_burn" {
+                    for burn in event.data {
+                        change.burned = change.burned.saturating_add(burn.amount);
+                    }
+                }
+            }
+        }
+        change
+    }
+
+    fn add(&mut self, other: &SupplyChange) {
+        self.minted = self.minted.saturating_add(other.minted);
+        self.burned = self.burned.saturating_add(other.burned);
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenSupply {
+    /// Total minted since the tracking started
+    #[serde(with = "dec_format")]
+    pub minted: Balance,
+    /// Total burned since the tracking started
+    #[serde(with = "dec_format")]
+    pub burned: Balance,
+    /// Supply before the tracking started, unknown until the first RPC check.
+    /// Can be negative if a mint was missed.
+    pub baseline: Option<i128>,
+    pub last_checked_block: Option<BlockHeight>,
+}
+
+impl TokenSupply {
+    pub fn tracked_supply(&self) -> Option<Balance> {
+        let baseline = self.baseline?;
+        let supply = baseline
+            .checked_add_unsigned(self.minted)?
+            .checked_sub_unsigned(self.burned)?;
+        supply.try_into().ok()
+    }
+
+    /// Makes the tracked supply equal to `total_supply`, or unknown if it's out of range
+    pub fn calibrate(&mut self, total_supply: Balance) {
+        self.baseline = i128::try_from(total_supply)
+            .ok()
+            .and_then(|total_supply| total_supply.checked_sub_unsigned(self.minted))
+            .and_then(|baseline| baseline.checked_add_unsigned(self.burned));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenSupplyChangedEvent {
+    pub token_id: AccountId,
+    /// Minted since the previous event
+    pub minted: Balance,
+    /// Burned since the previous event
+    pub burned: Balance,
+    /// Supply calculated from mints and burns, if it has been compared with RPC at least once
+    pub total_supply: Option<Balance>,
+    /// Result of `ft_total_supply`, if this token was sampled for a check on this interval
+    pub rpc_total_supply: Option<Balance>,
+    /// `total_supply` was different from `rpc_total_supply`. The tracked supply is reset
+    /// to the RPC value afterwards.
+    pub diverged: bool,
+}
+
+#[async_trait]
+pub trait TokenSupplyStorage: Send + Sync {
+    async fn get_supply(&self, token_id: &AccountId) -> Option<TokenSupply>;
+    async fn save_supply(&self, token_id: AccountId, supply: TokenSupply);
+    async fn save_supplies(&self, supplies: Vec<(AccountId, TokenSupply)>) {
+        for (token_id, supply) in supplies {
+            self.save_supply(token_id, supply).await;
+        }
+    }
+}
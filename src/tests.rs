@@ -1,18 +1,59 @@
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use inindexer::{
-    near_indexer_primitives::types::AccountId, neardata_server::NeardataServerProvider,
+    near_indexer_primitives::types::{AccountId, BlockHeight},
+    near_utils::EventLogData,
+    neardata_server::NeardataServerProvider,
     run_indexer, BlockIterator, IndexerOptions, PreprocessTransactionsSettings,
 };
 use near_jsonrpc_client::JsonRpcClient;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::{Mutex, RwLock};
 
 pub const RPC_URL: &str = "https://archival-rpc.mainnet.near.org";
 
-use crate::meme_cooking::MemeCookingCreateTokenEvent;
+use crate::bridge::{Bridge, BridgeFactory, BridgedTokenCreatedEvent, BridgedTokenOrigin};
+use crate::events::{NewContractNep141EventData, TokenSupplyChangedEventData};
+use crate::holders::{TokenHoldersSnapshotEvent, TrackedHolders};
+use crate::initial_distribution::{mints_from_logs, MintRecipient, TokenInitialDistributionEvent};
+use crate::json_file_storage::{JsonFileStorage, JsonlWebhookStorage};
+use crate::jsonl_file_handler::{JsonlEvent, JsonlFileHandler, JsonlFileOptions};
+use crate::launch_timeline::{meme_id_from_token_account, LaunchTimelines, MemeCookingOrigin};
+use crate::launchpad::{LaunchpadFieldMapping, LaunchpadRule, LaunchpadTokenCreatedEvent};
+use crate::meme_cooking::{
+    load_meme_cooking_schemas, parse_meme_cooking_contracts, MemeCookingCampaign,
+    MemeCookingCampaignOutcome, MemeCookingCampaignOutcomeEvent, MemeCookingCampaignStorage,
+    MemeCookingContract, MemeCookingCreateTokenEvent,
+};
+use crate::meme_cooking_reference::{
+    MemeCookingReference, ReferenceFetcher, ReferenceStatus, MAX_REFERENCE_BYTES,
+};
+use crate::metadata_updates::{
+    seed_metadata, MetadataChange, StoredMetadata, TokenMetadataStorage, TokenMetadataUpdatedEvent,
+};
+use crate::metadata_validation::{sanitize_svg, validate_metadata, MetadataIssue, MAX_ICON_BYTES};
+use crate::multi_handler::MultiHandler;
+use crate::new_nep141::{FtMetadata, KnownToken, NewNep141Event, TokenRegistry};
+use crate::ref_finance::{
+    PoolTokenAmount, RefPool, RefPoolKind, RefPoolStorage, RefPools, TokenFirstPoolEvent,
+};
+use crate::risk::{
+    load_allowlist, normalize_symbol, RiskAssessment, RiskReason, RiskScorer, RiskScorerOptions,
+};
+use crate::supply::{SupplyChange, TokenSupply, TokenSupplyChangedEvent};
+use crate::tkn_factory::TknTokenArgs;
+use crate::txt_file_storage::TxtFileStorage;
+use crate::webhook_handler::{
+    sign_webhook, UndeliveredWebhookStorage, WebhookBody, WebhookDelivery, WebhookEndpoint,
+    WebhookHandler, WebhookOptions,
+};
 use crate::{
-    meme_cooking::MemeCookingCreateMemeEvent, ContractEventHandler, EventContext,
+    meme_cooking::MemeCookingCreateMemeEvent, BlockContext, ContractEventHandler, EventContext,
     HandledTokensStorage, NewTokenIndexer,
 };
 
@@ -21,6 +62,25 @@ struct TestHandler {
     nep141_events: Mutex<HashMap<AccountId, Vec<EventContext>>>,
     memecooking_meme_events: Mutex<HashMap<u64, Vec<(MemeCookingCreateMemeEvent, EventContext)>>>,
     memecooking_token_events: Mutex<HashMap<u64, Vec<(MemeCookingCreateTokenEvent, EventContext)>>>,
+    #[allow(clippy::type_complexity)]
+    memecooking_outcome_events: Mutex<
+        HashMap<
+            u64,
+            Vec<(
+                MemeCookingCampaignOutcome,
+                MemeCookingCampaignOutcomeEvent,
+                BlockContext,
+            )>,
+        >,
+    >,
+    launchpad_events: Mutex<HashMap<AccountId, Vec<(LaunchpadTokenCreatedEvent, EventContext)>>>,
+    bridged_token_events: Mutex<HashMap<AccountId, Vec<(BridgedTokenCreatedEvent, EventContext)>>>,
+    first_pool_events: Mutex<HashMap<AccountId, Vec<(TokenFirstPoolEvent, EventContext)>>>,
+    initial_distribution_events:
+        Mutex<HashMap<AccountId, Vec<(TokenInitialDistributionEvent, EventContext)>>>,
+    supply_events: Mutex<HashMap<AccountId, Vec<(TokenSupplyChangedEvent, BlockContext)>>>,
+    holders_events: Mutex<HashMap<AccountId, Vec<(TokenHoldersSnapshotEvent, BlockContext)>>>,
+    metadata_events: Mutex<HashMap<AccountId, Vec<(TokenMetadataUpdatedEvent, BlockContext)>>>,
     testnet: bool,
 }
 
@@ -61,6 +121,136 @@ impl ContractEventHandler for TestHandler {
             .push((event, context));
     }
 
+    async fn handle_meme_cooking_reached_soft_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.memecooking_outcome_events
+            .lock()
+            .await
+            .entry(event.meme_id)
+            .or_default()
+            .push((MemeCookingCampaignOutcome::ReachedSoftCap, event, context));
+    }
+
+    async fn handle_meme_cooking_reached_hard_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.memecooking_outcome_events
+            .lock()
+            .await
+            .entry(event.meme_id)
+            .or_default()
+            .push((MemeCookingCampaignOutcome::ReachedHardCap, event, context));
+    }
+
+    async fn handle_meme_cooking_expired_without_token(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.memecooking_outcome_events
+            .lock()
+            .await
+            .entry(event.meme_id)
+            .or_default()
+            .push((
+                MemeCookingCampaignOutcome::ExpiredWithoutToken,
+                event,
+                context,
+            ));
+    }
+
+    async fn handle_launchpad_token_created(
+        &self,
+        event: LaunchpadTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.launchpad_events
+            .lock()
+            .await
+            .entry(event.token_id.clone())
+            .or_default()
+            .push((event, context));
+    }
+
+    async fn handle_bridged_token_created(
+        &self,
+        event: BridgedTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.bridged_token_events
+            .lock()
+            .await
+            .entry(event.token_id.clone())
+            .or_default()
+            .push((event, context));
+    }
+
+    async fn handle_token_first_pool(&self, event: TokenFirstPoolEvent, context: EventContext) {
+        self.first_pool_events
+            .lock()
+            .await
+            .entry(event.token_id.clone())
+            .or_default()
+            .push((event, context));
+    }
+
+    async fn handle_token_initial_distribution(
+        &self,
+        event: TokenInitialDistributionEvent,
+        context: EventContext,
+    ) {
+        self.initial_distribution_events
+            .lock()
+            .await
+            .entry(event.token_id.clone())
+            .or_default()
+            .push((event, context));
+    }
+
+    async fn handle_token_supply_changed(
+        &self,
+        event: TokenSupplyChangedEvent,
+        context: BlockContext,
+    ) {
+        self.supply_events
+            .lock()
+            .await
+            .entry(event.token_id.clone())
+            .or_default()
+            .push((event, context));
+    }
+
+    async fn handle_token_holders_snapshot(
+        &self,
+        event: TokenHoldersSnapshotEvent,
+        context: BlockContext,
+    ) {
+        self.holders_events
+            .lock()
+            .await
+            .entry(event.token_id.clone())
+            .or_default()
+            .push((event, context));
+    }
+
+    async fn handle_token_metadata_updated(
+        &self,
+        event: TokenMetadataUpdatedEvent,
+        context: BlockContext,
+    ) {
+        self.metadata_events
+            .lock()
+            .await
+            .entry(event.token_id.clone())
+            .or_default()
+            .push((event, context));
+    }
+
     fn is_testnet(&self) -> bool {
         self.testnet
     }
@@ -82,6 +272,29 @@ impl HandledTokensStorage for TestStorage {
     }
 }
 
+#[derive(Default, Clone)]
+struct TestWebhookStorage {
+    undelivered: Arc<Mutex<Vec<WebhookDelivery>>>,
+}
+
+#[async_trait]
+impl UndeliveredWebhookStorage for TestWebhookStorage {
+    async fn save_undelivered(&self, delivery: WebhookDelivery) {
+        self.undelivered.lock().await.push(delivery);
+    }
+
+    async fn remove_undelivered(&self, delivery: &WebhookDelivery) {
+        self.undelivered
+            .lock()
+            .await
+            .retain(|saved| saved.id != delivery.id || saved.endpoint != delivery.endpoint);
+    }
+
+    async fn undelivered(&self) -> Vec<WebhookDelivery> {
+        self.undelivered.lock().await.clone()
+    }
+}
+
 #[tokio::test]
 async fn detects_tkn_factory() {
     let handler = TestHandler::default();
@@ -125,6 +338,10 @@ async fn detects_tkn_factory() {
                     .unwrap(),
                 block_height: 114625057,
                 block_timestamp_nanosec: 1710328781107609847,
+                signer_id: "slimedragon.near".parse().unwrap(),
+                predecessor_id: "tkn.near".parse().unwrap(),
+                attached_deposit: 1_840_000_000_000_000_000_000_000,
+                factory_id: Some("tkn.near".parse().unwrap()),
             })
         ]
     );
@@ -173,6 +390,10 @@ async fn detects_custom_token_contracts() {
                     .unwrap(),
                 block_height: 124593978,
                 block_timestamp_nanosec: 1722328121254503873,
+                signer_id: "angryman.near".parse().unwrap(),
+                predecessor_id: "tfactory.near".parse().unwrap(),
+                attached_deposit: 3_000_000_000_000_000_000_000_000,
+                factory_id: Some("tfactory.near".parse().unwrap()),
             })
         ]
     );
@@ -258,6 +479,7 @@ async fn detects_meme_cooking_meme_creation() {
                 deposit_token_id: "wrap.testnet".parse().unwrap(),
                 soft_cap: 100000000000000000000000000,
                 hard_cap: Some(1000000000000000000000000000),
+                reference_document: None,
             },
             EventContext {
                 transaction_id: "9GecMwZehFzuJawDHaapEn2zfvz4nZ4yPKUrvPisga5b"
@@ -268,6 +490,10 @@ async fn detects_meme_cooking_meme_creation() {
                     .unwrap(),
                 block_height: 176213385,
                 block_timestamp_nanosec: 1728300532500808265,
+                signer_id: "marior.testnet".parse().unwrap(),
+                predecessor_id: "marior.testnet".parse().unwrap(),
+                attached_deposit: 1_000_000_000_000_000_000_000_000,
+                factory_id: None,
             }
         )]
     );
@@ -317,7 +543,11 @@ async fn detects_mitte_meme() {
                 .parse()
                 .unwrap(),
             block_height: 124682799,
-            block_timestamp_nanosec: 1722427998479776694
+            block_timestamp_nanosec: 1722427998479776694,
+            signer_id: "token0.near".parse().unwrap(),
+            predecessor_id: "token0.near".parse().unwrap(),
+            attached_deposit: 0,
+            factory_id: None
         }]
     );
 }
@@ -366,7 +596,11 @@ async fn detects_by_events() {
                 .parse()
                 .unwrap(),
             block_height: 124689356,
-            block_timestamp_nanosec: 1722435140007941002
+            block_timestamp_nanosec: 1722435140007941002,
+            signer_id: "honeybot.near".parse().unwrap(),
+            predecessor_id: "honeybot.near".parse().unwrap(),
+            attached_deposit: 0,
+            factory_id: None
         }]
     );
 }
@@ -422,8 +656,2258 @@ async fn detects_meme_cooking_token() {
                     .parse()
                     .unwrap(),
                 block_height: 174820333,
-                block_timestamp_nanosec: 1726907853133808278
+                block_timestamp_nanosec: 1726907853133808278,
+                signer_id: "lee.testnet".parse().unwrap(),
+                predecessor_id: "factory.v10.meme-cooking.testnet".parse().unwrap(),
+                attached_deposit: 0,
+                factory_id: None
             }
         )]
     );
 }
+
+#[test]
+fn tracks_meme_cooking_campaign() {
+    let mut campaign = MemeCookingCampaign::new(&MemeCookingCreateMemeEvent {
+        meme_id: 90,
+        owner: "marior.testnet".parse().unwrap(),
+        end_timestamp_ms: 1728386932500,
+        name: "test".to_string(),
+        symbol: "test".to_string(),
+        decimals: 18,
+        total_supply: 1000000000000000000000000000,
+        reference: "QmS33zcxEgb4QSfwA7tH9w7NVmiVaw6ZqkJFA5CiqVmm4W".to_string(),
+        reference_hash: "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string(),
+        deposit_token_id: "wrap.testnet".parse().unwrap(),
+        soft_cap: 100,
+        hard_cap: Some(1000),
+        reference_document: None,
+    });
+    assert_eq!(
+        campaign.outcome(),
+        MemeCookingCampaignOutcome::ExpiredWithoutToken
+    );
+
+    campaign.deposit("alice.testnet".parse().unwrap(), 60);
+    campaign.deposit("bob.testnet".parse().unwrap(), 60);
+    campaign.deposit("alice.testnet".parse().unwrap(), 10);
+    assert_eq!(campaign.total_deposited, 130);
+    assert_eq!(campaign.participants(), 2);
+    assert_eq!(
+        campaign.outcome(),
+        MemeCookingCampaignOutcome::ReachedSoftCap
+    );
+
+    campaign.withdraw(&"bob.testnet".parse().unwrap(), 60);
+    assert_eq!(campaign.total_deposited, 70);
+    assert_eq!(campaign.participants(), 1);
+    assert_eq!(
+        campaign.outcome(),
+        MemeCookingCampaignOutcome::ExpiredWithoutToken
+    );
+
+    campaign.deposit("carol.testnet".parse().unwrap(), 930);
+    assert_eq!(campaign.participants(), 2);
+    assert_eq!(
+        campaign.outcome(),
+        MemeCookingCampaignOutcome::ReachedHardCap
+    );
+
+    campaign.deposit("carol.testnet".parse().unwrap(), u128::MAX);
+    assert_eq!(campaign.total_deposited, u128::MAX);
+}
+
+#[tokio::test]
+async fn starts_empty_with_invalid_json_file() {
+    let path = std::env::temp_dir().join(format!(
+        "meme_cooking_campaigns_test_{}.json",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    std::fs::write(&path, "{\"1\":").unwrap();
+    let storage = JsonFileStorage::<HashMap<u64, MemeCookingCampaign>>::new(&path).await;
+    assert!(storage.get_campaign(1).await.is_none());
+    assert!(!path.exists());
+    let invalid_path = path.with_extension("invalid");
+    assert_eq!(std::fs::read_to_string(&invalid_path).unwrap(), "{\"1\":");
+    std::fs::remove_file(&invalid_path).unwrap();
+}
+
+#[tokio::test]
+async fn links_meme_cooking_launch() {
+    let launches = LaunchTimelines::default();
+    let token_id: AccountId = "lee-52.factory.v10.meme-cooking.testnet".parse().unwrap();
+    assert_eq!(
+        meme_id_from_token_account(&token_id, "factory.v10.meme-cooking.testnet"),
+        Some(52)
+    );
+    let context = EventContext {
+        transaction_id: "59rYXL82wYisbMf5xMm37mKJ1eeWyJTBQ3hMPjKn2huG"
+            .parse()
+            .unwrap(),
+        receipt_id: "51ScmrfA9r6J2hkCM5t9GyY9XeZW8gewqBXSju86NNYR"
+            .parse()
+            .unwrap(),
+        block_height: 174820333,
+        block_timestamp_nanosec: 1726907853133808278,
+        signer_id: "lee.testnet".parse().unwrap(),
+        predecessor_id: "meme-cooking.testnet".parse().unwrap(),
+        attached_deposit: 0,
+        factory_id: None,
+    };
+
+    launches
+        .record_meme(
+            &MemeCookingCreateMemeEvent {
+                meme_id: 52,
+                owner: "lee.testnet".parse().unwrap(),
+                end_timestamp_ms: 1726907000000,
+                name: "lee".to_string(),
+                symbol: "lee".to_string(),
+                decimals: 18,
+                total_supply: 1000000000000000000000000000,
+                reference: String::new(),
+                reference_hash: String::new(),
+                deposit_token_id: "wrap.testnet".parse().unwrap(),
+                soft_cap: 100,
+                hard_cap: None,
+                reference_document: None,
+            },
+            &context,
+        )
+        .await;
+    let origin = MemeCookingOrigin {
+        meme_id: 52,
+        owner: Some("lee.testnet".parse().unwrap()),
+        pool_id: None,
+    };
+    launches.record_nep141(&origin, &token_id, &context).await;
+    assert_eq!(launches.timeline(&token_id).await.unwrap().origin(), origin);
+
+    launches
+        .record_token(
+            &MemeCookingCreateTokenEvent {
+                meme_id: 52,
+                token_id: token_id.clone(),
+                total_supply: 1000000000000000000000000000,
+                pool_id: 2273,
+            },
+            &context,
+        )
+        .await;
+    let timeline = launches.timeline(&token_id).await.unwrap();
+    assert_eq!(timeline.pool_id, Some(2273));
+    assert_eq!(timeline.meme_created, Some(context.clone()));
+    assert_eq!(timeline.token_created, Some(context.clone()));
+    assert_eq!(timeline.nep141_detected, Some(context));
+}
+
+#[test]
+fn parses_meme_cooking_contracts() {
+    let contracts = parse_meme_cooking_contracts(
+        "meme-cooking.near, factory.v11.meme-cooking.testnet@1.0.0|1.1.0",
+    )
+    .unwrap();
+    assert_eq!(
+        contracts,
+        vec![
+            MemeCookingContract {
+                account_id: "meme-cooking.near".parse().unwrap(),
+                event_versions: vec![],
+            },
+            MemeCookingContract {
+                account_id: "factory.v11.meme-cooking.testnet".parse().unwrap(),
+                event_versions: vec!["1.0.0".to_string(), "1.1.0".to_string()],
+            },
+        ]
+    );
+    assert!(contracts[0].accepts_version("2.0.0"));
+    assert!(contracts[1].accepts_version("1.1.0"));
+    assert!(!contracts[1].accepts_version("2.0.0"));
+}
+
+#[tokio::test]
+async fn maps_meme_cooking_event_schemas() {
+    let path = std::env::temp_dir().join(format!(
+        "meme_cooking_schemas_test_{}.json",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    std::fs::write(
+        &path,
+        r#"[{
+            "contract_id": "factory.v11.meme-cooking.testnet",
+            "version": "2.0.0",
+            "events": {"token_created": "create_token"},
+            "fields": {"create_token": {"meme_id": "/meme/id", "pool_id": "/pool"}}
+        }]"#,
+    )
+    .unwrap();
+    let schemas = load_meme_cooking_schemas(&path).await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut event = EventLogData::<serde_json::Value>::deserialize(
+        r#"EVENT_JSON:{"standard":"meme-cooking","version":"2.0.0","event":"token_created","data":{"meme":{"id":52},"token_id":"lee-52.factory.v11.meme-cooking.testnet","total_supply":"1000000","pool":2273}}"#,
+    )
+    .unwrap();
+    schemas[0].apply(&mut event);
+    assert_eq!(event.event, "create_token");
+    assert_eq!(
+        serde_json::from_value::<MemeCookingCreateTokenEvent>(event.data).unwrap(),
+        MemeCookingCreateTokenEvent {
+            meme_id: 52,
+            token_id: "lee-52.factory.v11.meme-cooking.testnet".parse().unwrap(),
+            total_supply: 1_000_000,
+            pool_id: 2273,
+        }
+    );
+}
+
+/// Responds with `body` to every request, returns the base URL
+async fn serve_http(body: Vec<u8>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let body = body.clone();
+            tokio::spawn(async move {
+                let mut request = [0; 4096];
+                let _ = socket.read(&mut request).await;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(&body).await.unwrap();
+            });
+        }
+    });
+    format!("http://{address}")
+}
+
+#[tokio::test]
+async fn verifies_meme_cooking_reference() {
+    let document = br#"{"description":"test meme","image":"QmImage","twitterLink":"https://x.com/test","website":"https://test.com"}"#;
+    let reference_hash = BASE64.encode(Sha256::digest(document));
+    let gateway = serve_http(document.to_vec()).await;
+    let fetcher = ReferenceFetcher::new(format!("{gateway}/ipfs/"), Duration::from_secs(5));
+
+    assert_eq!(
+        fetcher.fetch("QmDocument", &reference_hash).await,
+        MemeCookingReference {
+            status: ReferenceStatus::Verified,
+            description: Some("test meme".to_string()),
+            image: Some(format!("{gateway}/ipfs/QmImage")),
+            twitter: Some("https://x.com/test".to_string()),
+            telegram: None,
+            website: Some("https://test.com".to_string()),
+        }
+    );
+    assert_eq!(
+        fetcher
+            .fetch(
+                "ipfs://QmDocument",
+                "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+            )
+            .await
+            .status,
+        ReferenceStatus::HashMismatch
+    );
+    // URLs of other gateways are downloaded through the configured one
+    assert_eq!(
+        fetcher
+            .fetch("http://169.254.169.254/ipfs/QmDocument", &reference_hash)
+            .await
+            .status,
+        ReferenceStatus::Verified
+    );
+    for reference in [
+        format!("{gateway}/document.json"),
+        "http://localhost/admin".to_string(),
+        "QmDocument/../../admin".to_string(),
+        "Qm Document".to_string(),
+    ] {
+        assert_eq!(
+            fetcher.fetch(&reference, &reference_hash).await.status,
+            ReferenceStatus::NotIpfs,
+            "{reference}"
+        );
+    }
+
+    let large_document = vec![b' '; MAX_REFERENCE_BYTES + 1];
+    let large_gateway = serve_http(large_document.clone()).await;
+    let fetcher = ReferenceFetcher::new(format!("{large_gateway}/ipfs"), Duration::from_secs(5));
+    assert_eq!(
+        fetcher
+            .fetch("QmLarge", &BASE64.encode(Sha256::digest(&large_document)))
+            .await
+            .status,
+        ReferenceStatus::FetchFailed
+    );
+}
+
+#[test]
+fn parses_launchpad_events() {
+    let rule: LaunchpadRule = serde_json::from_value(serde_json::json!({
+        "name": "meme.cooking",
+        "contract_id": "meme-cooking.near",
+        "standard": "meme-cooking",
+        "event": "create_token",
+        "fields": {
+            "token_id": "/token_id",
+            "total_supply": "/total_supply",
+        },
+    }))
+    .unwrap();
+    assert_eq!(
+        rule.fields,
+        LaunchpadFieldMapping {
+            token_id: "/token_id".to_string(),
+            owner: None,
+            total_supply: Some("/total_supply".to_string()),
+        }
+    );
+
+    assert_eq!(
+        rule.parse_log(r#"EVENT_JSON:{"standard":"meme-cooking","version":"1.0.0","event":"create_token","data":{"meme_id":52,"token_id":"lee-52.meme-cooking.near","total_supply":"1000000000000000000000000000","pool_id":2273}}"#),
+        vec![LaunchpadTokenCreatedEvent {
+            launchpad: "meme.cooking".to_string(),
+            contract_id: "meme-cooking.near".parse().unwrap(),
+            token_id: "lee-52.meme-cooking.near".parse().unwrap(),
+            owner: None,
+            total_supply: Some(1000000000000000000000000000),
+        }]
+    );
+    assert!(rule
+        .parse_log(r#"EVENT_JSON:{"standard":"meme-cooking","version":"1.0.0","event":"create_meme","data":{"meme_id":52}}"#)
+        .is_empty());
+}
+
+#[test]
+fn parses_tkn_factory_args() {
+    let args: TknTokenArgs = serde_json::from_value(serde_json::json!({
+        "owner_id": "slimedragon.near",
+        "total_supply": "1000000000000000000000000000",
+        "metadata": {
+            "spec": "ft-1.0.0",
+            "name": "Intear",
+            "symbol": "INTEAR",
+            "icon": "data:image/webp;base64,UklGRg==",
+            "reference": null,
+            "reference_hash": null,
+            "decimals": 18,
+        },
+    }))
+    .unwrap();
+    assert_eq!(
+        args,
+        TknTokenArgs {
+            owner_id: "slimedragon.near".parse().unwrap(),
+            total_supply: 1000000000000000000000000000,
+            metadata: FtMetadata {
+                spec: "ft-1.0.0".to_string(),
+                name: "Intear".to_string(),
+                symbol: "INTEAR".to_string(),
+                icon: Some("data:image/webp;base64,UklGRg==".to_string()),
+                reference: None,
+                reference_hash: None,
+                decimals: 18,
+            },
+        }
+    );
+    assert_eq!(args.token_id(), Some("intear.tkn.near".parse().unwrap()));
+}
+
+#[test]
+fn parses_bridged_token_origin() {
+    let factories = BridgeFactory::defaults(false);
+    let rainbow = factories
+        .iter()
+        .find(|factory| factory.bridge == Bridge::Rainbow)
+        .unwrap();
+    let omni = factories
+        .iter()
+        .find(|factory| factory.bridge == Bridge::Omni)
+        .unwrap();
+
+    assert_eq!(
+        rainbow.origin_from_account_id(
+            &"6b175474e89094c44da98b954eedeac495271d0f.factory.bridge.near"
+                .parse()
+                .unwrap()
+        ),
+        Some(BridgedTokenOrigin {
+            chain: "ethereum".to_string(),
+            address: Some("0x6b175474e89094c44da98b954eedeac495271d0f".to_string()),
+        })
+    );
+    assert_eq!(
+        rainbow.origin_from_account_id(&"aurora.factory.bridge.near".parse().unwrap()),
+        None
+    );
+    assert_eq!(
+        rainbow.origin_from_account_id(&"usdt.tether-token.near".parse().unwrap()),
+        None
+    );
+
+    assert_eq!(
+        omni.origin_from_account_id(
+            &"base-833589fcd6edb6e08f4c7c32d4f71b54bda02913.omdep.near"
+                .parse()
+                .unwrap()
+        ),
+        Some(BridgedTokenOrigin {
+            chain: "base".to_string(),
+            address: Some("0x833589fcd6edb6e08f4c7c32d4f71b54bda02913".to_string()),
+        })
+    );
+    assert_eq!(
+        omni.origin_from_account_id(
+            &"sol-57d087fd8c460f612f8701f5499ad8b2eec5ab68.omdep.near"
+                .parse()
+                .unwrap()
+        ),
+        Some(BridgedTokenOrigin {
+            chain: "solana".to_string(),
+            address: None,
+        })
+    );
+}
+
+#[test]
+fn matches_ref_pool_liquidity() {
+    let pool = RefPool {
+        pool_id: 5333,
+        kind: RefPoolKind::Simple,
+        tokens: vec![
+            "intel.tkn.near".parse().unwrap(),
+            "wrap.near".parse().unwrap(),
+        ],
+        created_at_block: 0,
+    };
+    assert_eq!(
+        pool.liquidity(&[
+            "1000000000000000000000000".to_string(),
+            "5000000000000000000000000".to_string()
+        ]),
+        Some(vec![
+            PoolTokenAmount {
+                token_id: "intel.tkn.near".parse().unwrap(),
+                amount: 1000000000000000000000000,
+            },
+            PoolTokenAmount {
+                token_id: "wrap.near".parse().unwrap(),
+                amount: 5000000000000000000000000,
+            },
+        ])
+    );
+    assert_eq!(pool.liquidity(&["1".to_string()]), None);
+    assert_eq!(
+        pool.liquidity(&["1".to_string(), "not a number".to_string()]),
+        None
+    );
+}
+
+#[tokio::test]
+async fn expires_pending_ref_pools() {
+    let storage = JsonFileStorage::<RefPools>::default();
+    for (pool_id, created_at_block) in [(1, 100), (2, 200)] {
+        storage
+            .save_pending_pool(RefPool {
+                pool_id,
+                kind: RefPoolKind::Simple,
+                tokens: vec!["intel.tkn.near".parse().unwrap()],
+                created_at_block,
+            })
+            .await;
+    }
+    storage.remove_pending_pools_before(150).await;
+    assert_eq!(storage.get_pending_pool(1).await, None);
+    // Reading a pool doesn't remove it, only emitting its event does
+    storage.get_pending_pool(2).await;
+    assert!(storage.get_pending_pool(2).await.is_some());
+    storage.remove_pending_pool(2).await;
+    assert_eq!(storage.get_pending_pool(2).await, None);
+}
+
+#[test]
+fn aggregates_initial_distribution() {
+    let token_id: AccountId = "intel.tkn.near".parse().unwrap();
+    let logs = [
+        r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_mint","data":[{"owner_id":"owner.near","amount":"700"},{"owner_id":"pool.near","amount":"200"}]}"#.to_string(),
+        r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_burn","data":[{"owner_id":"owner.near","amount":"100"}]}"#.to_string(),
+        r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_mint","data":[{"owner_id":"pool.near","amount":"100"}]}"#.to_string(),
+        "Some other log".to_string(),
+    ];
+    assert_eq!(
+        TokenInitialDistributionEvent::new(token_id.clone(), mints_from_logs(&logs)),
+        TokenInitialDistributionEvent {
+            token_id: token_id.clone(),
+            total_minted: 1000,
+            recipients: 2,
+            top_recipients: vec![
+                MintRecipient {
+                    account_id: "owner.near".parse().unwrap(),
+                    amount: 700,
+                },
+                MintRecipient {
+                    account_id: "pool.near".parse().unwrap(),
+                    amount: 300,
+                },
+            ],
+        }
+    );
+    assert!(mints_from_logs(&logs[1..2]).is_empty());
+}
+
+#[test]
+fn tracks_token_supply() {
+    let change = SupplyChange::from_logs(&[
+        r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_mint","data":[{"owner_id":"alice.near","amount":"700"},{"owner_id":"bob.near","amount":"300"}]}"#.to_string(),
+        r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_burn","data":[{"owner_id":"alice.near","amount":"100"}]}"#.to_string(),
+        r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"alice.near","new_owner_id":"bob.near","amount":"50"}]}"#.to_string(),
+    ]);
+    assert_eq!(
+        change,
+        SupplyChange {
+            minted: 1000,
+            burned: 100,
+        }
+    );
+
+    let mut supply = TokenSupply {
+        minted: change.minted,
+        burned: change.burned,
+        ..Default::default()
+    };
+    assert_eq!(supply.tracked_supply(), None);
+    // Tracking started after 5000 tokens were already minted
+    supply.calibrate(5900);
+    assert_eq!(supply.baseline, Some(5000));
+    assert_eq!(supply.tracked_supply(), Some(5900));
+
+    supply.burned += 900;
+    assert_eq!(supply.tracked_supply(), Some(5000));
+
+    // Doesn't wrap around to a negative baseline
+    supply.calibrate(u128::MAX);
+    assert_eq!(supply.baseline, None);
+    assert_eq!(supply.tracked_supply(), None);
+}
+
+#[test]
+fn counts_token_holders() {
+    let mut holders = TrackedHolders {
+        started_at: 100,
+        last_snapshot: 100,
+        balances: HashMap::from_iter([("owner.near".parse().unwrap(), 1000)]),
+    };
+    assert_eq!(holders.count(), 1);
+
+    holders.apply_log(r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"owner.near","new_owner_id":"alice.near","amount":"300"},{"old_owner_id":"owner.near","new_owner_id":"bob.near","amount":"200"}]}"#);
+    assert_eq!(holders.count(), 3);
+
+    holders.apply_log(r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"bob.near","new_owner_id":"alice.near","amount":"200"}]}"#);
+    assert_eq!(holders.count(), 2);
+
+    holders.apply_log(r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_burn","data":[{"owner_id":"owner.near","amount":"500"}]}"#);
+    holders.apply_log(r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_mint","data":[{"owner_id":"carol.near","amount":"1"}]}"#);
+    assert_eq!(holders.count(), 2);
+    assert_eq!(
+        holders.balances,
+        HashMap::from_iter([
+            ("alice.near".parse().unwrap(), 500),
+            ("carol.near".parse().unwrap(), 1),
+        ])
+    );
+}
+
+#[test]
+fn scores_token_risk() {
+    let scorer = RiskScorer::new(
+        JsonRpcClient::connect(RPC_URL),
+        RiskScorerOptions {
+            spam_factories: HashSet::from_iter(["spam-factory.near".parse().unwrap()]),
+            ..Default::default()
+        },
+    );
+    let metadata = FtMetadata {
+        spec: "ft-1.0.0".to_string(),
+        name: "USD Coin".to_string(),
+        symbol: "USDC".to_string(),
+        icon: None,
+        reference: None,
+        reference_hash: None,
+        decimals: 6,
+    };
+
+    let usdc = NewNep141Event::new(
+        "17208628f84f5d6ad33f0da3bbbeb27ffcb398eac501a31bd6ad2011e36133a1"
+            .parse()
+            .unwrap(),
+        None,
+        Some(metadata.clone()),
+    );
+    let context = EventContext {
+        transaction_id: "9SUSdf3rMfQi96znJ5DbjyMqhLud9G9bhVyMvogFaoNK"
+            .parse()
+            .unwrap(),
+        receipt_id: "7MiLFpVunJQKKjzY6o2b58GDqyi1wG3W8f51QFBa83fm"
+            .parse()
+            .unwrap(),
+        block_height: 124_000_000,
+        block_timestamp_nanosec: 1722000000000000000,
+        signer_id: "alice.near".parse().unwrap(),
+        predecessor_id: "alice.near".parse().unwrap(),
+        attached_deposit: 0,
+        factory_id: None,
+    };
+    assert!(scorer
+        .check(&usdc, &context, Some(1_000_000_000_000))
+        .is_empty());
+
+    let fake_usdc = NewNep141Event::new(
+        "usdc.spam-factory.near".parse().unwrap(),
+        None,
+        Some(FtMetadata {
+            symbol: "UЅDС".to_string(),
+            decimals: 0,
+            ..metadata
+        }),
+    );
+    let spam_context = EventContext {
+        predecessor_id: "spam-factory.near".parse().unwrap(),
+        factory_id: Some("spam-factory.near".parse().unwrap()),
+        ..context.clone()
+    };
+    let reasons = scorer.check(&fake_usdc, &spam_context, Some(10u128.pow(16)));
+    assert_eq!(
+        reasons,
+        vec![
+            RiskReason::SymbolCollision,
+            RiskReason::LookalikeUnicode,
+            RiskReason::ZeroDecimals,
+            RiskReason::HugeSupply,
+            RiskReason::SpamFactory,
+        ]
+    );
+    assert_eq!(RiskAssessment::new(reasons).score, 100);
+    // Deployed by a call from the factory to an account it didn't create
+    let deployed_by_factory = EventContext {
+        factory_id: None,
+        ..spam_context
+    };
+    assert_eq!(
+        scorer.check(&usdc, &deployed_by_factory, None),
+        vec![RiskReason::SpamFactory]
+    );
+
+    assert_eq!(normalize_symbol("wNEAR\u{200B}"), "WNEAR");
+    assert_eq!(normalize_symbol("ＵＳＤＴ"), "USDT");
+}
+
+#[tokio::test]
+async fn normalizes_allowlist_symbols() {
+    let path = std::env::temp_dir().join(format!(
+        "risk_allowlist_test_{}.json",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    std::fs::write(
+        &path,
+        r#"{"wNEAR": ["wrap.near"], "ｗｎｅａｒ": ["wrap.testnet"], "usdc": ["usdc.near"]}"#,
+    )
+    .unwrap();
+    let allowlist = load_allowlist(&path).await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        allowlist,
+        HashMap::from_iter([
+            (
+                "WNEAR".to_string(),
+                HashSet::from_iter([
+                    "wrap.near".parse().unwrap(),
+                    "wrap.testnet".parse().unwrap()
+                ])
+            ),
+            (
+                "USDC".to_string(),
+                HashSet::from_iter(["usdc.near".parse().unwrap()])
+            ),
+        ])
+    );
+}
+
+#[test]
+fn validates_metadata_icons() {
+    let mut metadata = FtMetadata {
+        spec: "ft-1.0.0".to_string(),
+        name: "Intear".to_string(),
+        symbol: "INTEAR".to_string(),
+        icon: Some(r#"data:image/svg+xml,%3Csvg xmlns="http://www.w3.org/2000/svg" onload="alert(1)"%3E%3Cscript%3Ealert(2)%3C/script%3E%3Ca href="javascript:alert(3)"%3E%3Ccircle r="1"/%3E%3C/a%3E%3C/svg%3E"#.to_string()),
+        reference: None,
+        reference_hash: None,
+        decimals: 18,
+    };
+    assert_eq!(
+        validate_metadata(&mut metadata),
+        vec![MetadataIssue::IconScriptRemoved]
+    );
+    assert_eq!(
+        metadata.icon,
+        Some(format!(
+            "data:image/svg+xml;base64,{}",
+            BASE64
+                .encode(r#"<svg xmlns="http://www.w3.org/2000/svg"><a><circle r="1"/></a></svg>"#)
+        ))
+    );
+    // Already normalized
+    assert!(validate_metadata(&mut metadata).is_empty());
+
+    metadata.icon = Some(format!(
+        "data:image/png;base64,{}",
+        BASE64.encode(b"GIF89a")
+    ));
+    assert_eq!(
+        validate_metadata(&mut metadata),
+        vec![MetadataIssue::IconTypeMismatch]
+    );
+    assert!(metadata.icon.is_some());
+
+    metadata.icon = Some(format!(
+        "data:image/png;base64,{}",
+        BASE64.encode(vec![0; MAX_ICON_BYTES + 1])
+    ));
+    assert_eq!(
+        validate_metadata(&mut metadata),
+        vec![MetadataIssue::IconTooLarge]
+    );
+    assert_eq!(metadata.icon, None);
+    assert_eq!(
+        validate_metadata(&mut metadata),
+        vec![MetadataIssue::MissingIcon]
+    );
+
+    metadata.icon = Some("https://example.com/icon.png".to_string());
+    assert_eq!(
+        validate_metadata(&mut metadata),
+        vec![MetadataIssue::IconNotDataUrl]
+    );
+    metadata.icon = Some("data:text/html;base64,PHNjcmlwdD4=".to_string());
+    assert_eq!(
+        validate_metadata(&mut metadata),
+        vec![MetadataIssue::UnsupportedIconType]
+    );
+}
+
+#[test]
+fn sanitizes_svg() {
+    let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"><style>.a{fill:red}</style><defs><linearGradient id="g"><stop offset="0" stop-color="#fff"/></linearGradient></defs><circle class="a" r="1" fill="url(#g)"/><use href="#g"/></svg>"##;
+    assert_eq!(sanitize_svg(svg), Some((svg.to_string(), false)));
+
+    let is_safe = |svg: &str| {
+        sanitize_svg(svg).is_none_or(|(svg, _)| {
+            !svg.contains("script") && !svg.contains("onload") && !svg.contains("alert")
+        })
+    };
+    for payload in [
+        "<scr<script></script>ipt>alert(1)</script>",
+        "<svg><scr<script></script>ipt>alert(1)</script></svg>",
+        "<svg/onload=alert(1)>",
+        "<svg/onload=alert(1)></svg>",
+        "<svg><script>alert(1)",
+        "<svg><script>alert(1)</svg>",
+        r#"<svg><a href="jav&#x61;script:alert(1)"><circle r="1"/></a></svg>"#,
+        r#"<svg><a xlink:href="jav&#x61;script:alert(1)"/></svg>"#,
+        r#"<svg><circle style="fill:url(jav&#x61;script:alert(1))"/></svg>"#,
+        "<svg><style>@import url(jav&#x61;script:alert(1))</style></svg>",
+        r#"<svg><foreignObject><iframe src="javascript:alert(1)"/></foreignObject></svg>"#,
+    ] {
+        assert!(is_safe(payload), "{payload}");
+    }
+
+    assert_eq!(sanitize_svg("<svg><script>alert(1)"), None);
+    assert_eq!(sanitize_svg("<svg><script>alert(1)</svg>"), None);
+    assert_eq!(sanitize_svg("<script>alert(1)</script>"), None);
+    assert_eq!(
+        sanitize_svg(r#"<svg><a href="jav&#x61;script:alert(1)"><circle r="1"/></a></svg>"#),
+        Some((r#"<svg><a><circle r="1"/></a></svg>"#.to_string(), true))
+    );
+}
+
+#[test]
+fn diffs_token_metadata() {
+    let metadata = FtMetadata {
+        spec: "ft-1.0.0".to_string(),
+        name: "Intear".to_string(),
+        symbol: "INTEAR".to_string(),
+        icon: Some("data:image/svg+xml;base64,PHN2Zy8+".to_string()),
+        reference: None,
+        reference_hash: None,
+        decimals: 18,
+    };
+    let old = StoredMetadata::new(metadata.clone());
+    assert_eq!(old, StoredMetadata::new(metadata.clone()));
+    assert!(old.diff(&old).is_empty());
+
+    let new = StoredMetadata::new(FtMetadata {
+        name: "Intear Token".to_string(),
+        icon: Some("data:image/svg+xml;base64,PHN2ZyAvPg==".to_string()),
+        ..metadata
+    });
+    assert_ne!(old.hash, new.hash);
+    assert_eq!(
+        old.diff(&new),
+        vec![
+            MetadataChange {
+                field: "icon".to_string(),
+                old: serde_json::to_value(&old.metadata.icon).unwrap(),
+                new: serde_json::to_value(&new.metadata.icon).unwrap(),
+            },
+            MetadataChange {
+                field: "name".to_string(),
+                old: serde_json::json!("Intear"),
+                new: serde_json::json!("Intear Token"),
+            },
+        ]
+    );
+    assert!(new.metadata.icon.unwrap().starts_with("sha256:"));
+}
+
+#[tokio::test]
+async fn seeds_token_metadata() {
+    let storage = JsonFileStorage::<HashMap<AccountId, StoredMetadata>>::default();
+    let token_id: AccountId = "intel.tkn.near".parse().unwrap();
+    let metadata = FtMetadata {
+        spec: "ft-1.0.0".to_string(),
+        name: "Intel".to_string(),
+        symbol: "INTEL".to_string(),
+        icon: None,
+        reference: None,
+        reference_hash: None,
+        decimals: 18,
+    };
+    seed_metadata(&storage, &token_id, &metadata).await;
+    assert_eq!(
+        storage.get_metadata(&token_id).await,
+        Some(StoredMetadata::new(metadata.clone()))
+    );
+    // Metadata that is already known is not replaced
+    let renamed = FtMetadata {
+        name: "Intel Token".to_string(),
+        ..metadata.clone()
+    };
+    seed_metadata(&storage, &token_id, &renamed).await;
+    assert_eq!(
+        storage.get_metadata(&token_id).await,
+        Some(StoredMetadata::new(metadata))
+    );
+}
+
+#[tokio::test]
+async fn persists_token_metadata_on_flush() {
+    let path = std::env::temp_dir().join(format!(
+        "token_metadata_test_{}.json",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    let storage = JsonFileStorage::<HashMap<AccountId, StoredMetadata>>::new(&path).await;
+    let token_id: AccountId = "intel.tkn.near".parse().unwrap();
+    let metadata = StoredMetadata::new(FtMetadata {
+        spec: "ft-1.0.0".to_string(),
+        name: "Intel".to_string(),
+        symbol: "INTEL".to_string(),
+        icon: None,
+        reference: None,
+        reference_hash: None,
+        decimals: 18,
+    });
+    storage
+        .save_metadata(token_id.clone(), metadata.clone())
+        .await;
+    assert!(!path.exists());
+
+    TokenMetadataStorage::flush(&storage).await;
+    let reloaded = JsonFileStorage::<HashMap<AccountId, StoredMetadata>>::new(&path).await;
+    assert_eq!(reloaded.get_metadata(&token_id).await, Some(metadata));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn flattens_event_context() {
+    let data = NewContractNep141EventData::new(
+        NewNep141Event::new("intel.tkn.near".parse().unwrap(), None, None),
+        EventContext {
+            transaction_id: "9SUSdf3rMfQi96znJ5DbjyMqhLud9G9bhVyMvogFaoNK"
+                .parse()
+                .unwrap(),
+            receipt_id: "7MiLFpVunJQKKjzY6o2b58GDqyi1wG3W8f51QFBa83fm"
+                .parse()
+                .unwrap(),
+            block_height: 114625057,
+            block_timestamp_nanosec: 1710328781107609847,
+            signer_id: "owner.near".parse().unwrap(),
+            predecessor_id: "tkn.near".parse().unwrap(),
+            attached_deposit: 1_000_000_000_000_000_000_000_000,
+            factory_id: Some("tkn.near".parse().unwrap()),
+        },
+    );
+    let json = serde_json::to_value(&data).unwrap();
+    assert_eq!(json["account_id"], "intel.tkn.near");
+    assert_eq!(json["block_timestamp_nanosec"], 1710328781107609847u64);
+    assert_eq!(json["attached_deposit"], "1000000000000000000000000");
+    assert_eq!(json["factory_id"], "tkn.near");
+    assert_eq!(
+        serde_json::from_value::<NewContractNep141EventData>(json).unwrap(),
+        data
+    );
+}
+
+struct HttpRequest {
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+async fn read_http_request(socket: &mut tokio::net::TcpStream) -> HttpRequest {
+    let mut buffer = Vec::new();
+    let header_end = loop {
+        let mut chunk = [0; 1024];
+        let read = socket.read(&mut chunk).await.unwrap();
+        assert_ne!(read, 0, "Connection closed before the request was read");
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+    let headers = String::from_utf8(buffer[..header_end].to_vec())
+        .unwrap()
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect::<HashMap<_, _>>();
+    let content_length: usize = headers["content-length"].parse().unwrap();
+    let mut body = buffer[header_end..].to_vec();
+    while body.len() < content_length {
+        let mut chunk = [0; 1024];
+        let read = socket.read(&mut chunk).await.unwrap();
+        body.extend_from_slice(&chunk[..read]);
+    }
+    HttpRequest {
+        headers,
+        body: String::from_utf8(body).unwrap(),
+    }
+}
+
+#[tokio::test]
+async fn delivers_signed_webhooks() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (requests_sender, mut requests) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        // The first attempt fails, so the event has to be retried
+        for status in ["500 Internal Server Error", "200 OK"] {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let request = read_http_request(&mut socket).await;
+            socket
+                .write_all(
+                    format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                        .as_bytes(),
+                )
+                .await
+                .unwrap();
+            requests_sender.send(request).unwrap();
+        }
+    });
+    let unreachable_url = {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("http://{}/hook", listener.local_addr().unwrap())
+    };
+
+    let storage = TestWebhookStorage::default();
+    let handler = WebhookHandler::new(
+        vec![
+            WebhookEndpoint {
+                url,
+                secret: "partner secret".to_string(),
+                events: Some(HashSet::from(["token_supply_changed".to_string()])),
+            },
+            WebhookEndpoint {
+                url: unreachable_url.clone(),
+                secret: "other secret".to_string(),
+                events: None,
+            },
+        ],
+        storage.clone(),
+        WebhookOptions {
+            max_attempts: 2,
+            initial_backoff: Duration::from_millis(10),
+            ..Default::default()
+        },
+        false,
+    )
+    .await;
+    let context = BlockContext {
+        block_height: 124_000_000,
+        block_timestamp_nanosec: 1722000000000000000,
+    };
+    // Only sent to the unreachable endpoint
+    handler
+        .handle_token_holders_snapshot(
+            TokenHoldersSnapshotEvent {
+                token_id: "intel.tkn.near".parse().unwrap(),
+                holders: 10,
+                blocks_since_launch: 100,
+                is_final: false,
+            },
+            context.clone(),
+        )
+        .await;
+    handler
+        .handle_token_supply_changed(
+            TokenSupplyChangedEvent {
+                token_id: "intel.tkn.near".parse().unwrap(),
+                minted: 1000,
+                burned: 0,
+                total_supply: Some(1000),
+                rpc_total_supply: None,
+                diverged: false,
+            },
+            context,
+        )
+        .await;
+
+    let failed = requests.recv().await.unwrap();
+    let delivered = requests.recv().await.unwrap();
+    assert_eq!(failed.body, delivered.body);
+    assert_eq!(
+        failed.headers["x-webhook-id"],
+        delivered.headers["x-webhook-id"]
+    );
+    assert_eq!(delivered.headers["x-webhook-event"], "token_supply_changed");
+    let timestamp = delivered.headers["x-webhook-timestamp"].parse().unwrap();
+    assert_eq!(
+        delivered.headers["x-webhook-signature"],
+        sign_webhook("partner secret", timestamp, &delivered.body)
+    );
+    assert_ne!(
+        delivered.headers["x-webhook-signature"],
+        sign_webhook("other secret", timestamp, &delivered.body)
+    );
+    let body: WebhookBody<TokenSupplyChangedEventData> =
+        serde_json::from_str(&delivered.body).unwrap();
+    assert_eq!(body.id, delivered.headers["x-webhook-id"]);
+    assert_eq!(body.event, "token_supply_changed");
+    assert!(!body.testnet);
+    assert_eq!(body.data.token_id, "intel.tkn.near");
+    assert_eq!(body.data.minted, 1000);
+    assert_eq!(body.data.total_supply, Some(1000));
+
+    let undelivered = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let undelivered = storage.undelivered.lock().await.clone();
+            if undelivered.len() == 2 {
+                return undelivered;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Failed deliveries were not saved");
+    assert!(undelivered
+        .iter()
+        .all(|delivery| delivery.endpoint == 1 && delivery.url == unreachable_url));
+    let mut events = undelivered
+        .iter()
+        .map(|delivery| delivery.event.as_str())
+        .collect::<Vec<_>>();
+    events.sort();
+    assert_eq!(
+        events,
+        vec!["token_holders_snapshot", "token_supply_changed"]
+    );
+}
+
+#[tokio::test]
+async fn drops_expired_webhooks() {
+    let storage = TestWebhookStorage::default();
+    let delivery = WebhookDelivery {
+        id: "expired".to_string(),
+        endpoint: 0,
+        url: "http://127.0.0.1:1/hook".to_string(),
+        event: "token_supply_changed".to_string(),
+        body: "{}".to_string(),
+        created_at: 0,
+    };
+    storage.save_undelivered(delivery).await;
+    let _handler = WebhookHandler::new(
+        vec![WebhookEndpoint {
+            url: "http://127.0.0.1:1/hook".to_string(),
+            secret: "secret".to_string(),
+            events: None,
+        }],
+        storage.clone(),
+        WebhookOptions::default(),
+        false,
+    )
+    .await;
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !storage.undelivered().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Expired delivery was not dropped");
+}
+
+#[tokio::test]
+async fn reloads_undelivered_webhooks() {
+    let path = std::env::temp_dir().join(format!(
+        "undelivered_webhooks_test_{}.jsonl",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    let delivery = |id: &str| WebhookDelivery {
+        id: id.to_string(),
+        endpoint: 0,
+        url: "http://127.0.0.1:1/hook".to_string(),
+        event: "token_supply_changed".to_string(),
+        body: "{}".to_string(),
+        created_at: 1722000000,
+    };
+    let storage = JsonlWebhookStorage::new(&path).await.unwrap();
+    storage.save_undelivered(delivery("first")).await;
+    storage.save_undelivered(delivery("second")).await;
+    storage.remove_undelivered(&delivery("first")).await;
+    drop(storage);
+    // Cut off by a crash in the middle of a line
+    let mut contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(contents.lines().count(), 3);
+    contents.push_str("{\"op\":\"save\",");
+    std::fs::write(&path, contents).unwrap();
+
+    let storage = JsonlWebhookStorage::new(&path).await.unwrap();
+    assert_eq!(storage.undelivered().await, vec![delivery("second")]);
+    // Compacted when opened
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn writes_rotated_gzipped_jsonl() {
+    use std::io::Read;
+
+    let directory = std::env::temp_dir().join(format!(
+        "jsonl_file_handler_test_{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    std::fs::create_dir_all(&directory).unwrap();
+    let read_gzip = |name: &str| {
+        let mut lines = String::new();
+        flate2::read::MultiGzDecoder::new(std::fs::File::open(directory.join(name)).unwrap())
+            .read_to_string(&mut lines)
+            .unwrap();
+        lines
+    };
+    let parse = |lines: String| {
+        lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect::<Vec<JsonlEvent<BlockContext, TokenSupplyChangedEventData>>>()
+    };
+    // Left by a previous run
+    let mut previous = flate2::write::GzEncoder::new(
+        std::fs::File::create(directory.join("events.jsonl.gz")).unwrap(),
+        flate2::Compression::default(),
+    );
+    std::io::Write::write_all(&mut previous, b"previous run\n").unwrap();
+    previous.finish().unwrap();
+
+    let handler = JsonlFileHandler::new(
+        directory.join("events.jsonl"),
+        JsonlFileOptions {
+            // One event per file
+            max_file_bytes: 1,
+            gzip: true,
+        },
+        false,
+    )
+    .unwrap();
+    for minted in 1..=3 {
+        handler
+            .handle_token_supply_changed(
+                TokenSupplyChangedEvent {
+                    token_id: "intel.tkn.near".parse().unwrap(),
+                    minted,
+                    burned: 0,
+                    total_supply: None,
+                    rpc_total_supply: None,
+                    diverged: false,
+                },
+                BlockContext {
+                    block_height: 124_000_000 + minted as u64,
+                    block_timestamp_nanosec: 1722000000000000000,
+                },
+            )
+            .await;
+    }
+    // Flushed lines can be read before the file is finished, the stream just ends early
+    let mut unfinished = Vec::new();
+    let result = flate2::read::MultiGzDecoder::new(
+        std::fs::File::open(directory.join("events.jsonl.gz")).unwrap(),
+    )
+    .read_to_end(&mut unfinished);
+    assert!(result.is_err());
+    assert_eq!(parse(String::from_utf8(unfinished).unwrap()).len(), 1);
+    drop(handler);
+
+    assert_eq!(read_gzip("events.1.jsonl.gz"), "previous run\n");
+    let rotated = parse(read_gzip("events.2.jsonl.gz"));
+    assert_eq!(rotated.len(), 1);
+    assert_eq!(rotated[0].event, "token_supply_changed");
+    assert_eq!(rotated[0].context.block_height, 124_000_001);
+    assert_eq!(rotated[0].data.token_id, "intel.tkn.near");
+    assert_eq!(parse(read_gzip("events.3.jsonl.gz"))[0].data.minted, 2);
+    assert_eq!(parse(read_gzip("events.jsonl.gz"))[0].data.minted, 3);
+    // A number, like in `intear_events`
+    assert!(
+        read_gzip("events.jsonl.gz").contains(r#""block_timestamp_nanosec":1722000000000000000"#)
+    );
+    assert!(!directory.join("events.4.jsonl.gz").exists());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn appends_to_previous_jsonl() {
+    let path = std::env::temp_dir().join(format!(
+        "jsonl_file_handler_append_test_{}.jsonl",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    // Left by a previous run that was killed in the middle of a line
+    std::fs::write(&path, "{\"event\":\"previous\"}\n{\"event\":").unwrap();
+
+    let handler = JsonlFileHandler::new(&path, JsonlFileOptions::default(), false).unwrap();
+    handler
+        .handle_token_supply_changed(
+            TokenSupplyChangedEvent {
+                token_id: "intel.tkn.near".parse().unwrap(),
+                minted: 1,
+                burned: 0,
+                total_supply: None,
+                rpc_total_supply: None,
+                diverged: false,
+            },
+            BlockContext {
+                block_height: 124_000_000,
+                block_timestamp_nanosec: 1722000000000000000,
+            },
+        )
+        .await;
+    drop(handler);
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let lines = contents.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], r#"{"event":"previous"}"#);
+    assert_eq!(lines[1], r#"{"event":"#);
+    let event: JsonlEvent<BlockContext, TokenSupplyChangedEventData> =
+        serde_json::from_str(lines[2]).unwrap();
+    assert_eq!(event.data.minted, 1);
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Fails to write every supply event, like a sink whose server is down
+struct FailingHandler;
+
+#[async_trait]
+impl ContractEventHandler for FailingHandler {
+    async fn handle_new_nep141(&self, _account_id: AccountId, _context: EventContext) {}
+
+    async fn handle_meme_cooking_new_meme(
+        &self,
+        _event: MemeCookingCreateMemeEvent,
+        _context: EventContext,
+    ) {
+    }
+
+    async fn handle_meme_cooking_new_token(
+        &self,
+        _event: MemeCookingCreateTokenEvent,
+        _context: EventContext,
+    ) {
+    }
+
+    async fn handle_token_supply_changed(
+        &self,
+        _event: TokenSupplyChangedEvent,
+        _context: BlockContext,
+    ) {
+        panic!("Failed to write supply event");
+    }
+
+    fn is_testnet(&self) -> bool {
+        false
+    }
+}
+
+#[tokio::test]
+async fn isolates_failing_sinks() {
+    let test_handler = Arc::new(TestHandler::default());
+    let handler = MultiHandler::new(false)
+        .with_optional_handler("optional", Arc::new(FailingHandler), None)
+        .with_handler(
+            "test",
+            test_handler.clone(),
+            Some(HashSet::from(["token_supply_changed".to_string()])),
+        );
+    let token_id: AccountId = "intel.tkn.near".parse().unwrap();
+    let context = BlockContext {
+        block_height: 124_000_000,
+        block_timestamp_nanosec: 1722000000000000000,
+    };
+    let event = TokenSupplyChangedEvent {
+        token_id: token_id.clone(),
+        minted: 1000,
+        burned: 0,
+        total_supply: Some(1000),
+        rpc_total_supply: None,
+        diverged: false,
+    };
+    handler
+        .handle_token_supply_changed(event.clone(), context.clone())
+        .await;
+    handler
+        .handle_token_holders_snapshot(
+            TokenHoldersSnapshotEvent {
+                token_id: token_id.clone(),
+                holders: 10,
+                blocks_since_launch: 100,
+                is_final: false,
+            },
+            context.clone(),
+        )
+        .await;
+    assert_eq!(test_handler.supply_events.lock().await[&token_id].len(), 1);
+    assert_eq!(
+        test_handler.supply_events.lock().await[&token_id][0].1,
+        context
+    );
+    assert!(test_handler.holders_events.lock().await.is_empty());
+
+    // A required sink still lets the others handle the event, then stops indexing
+    let handler = MultiHandler::new(false)
+        .with_handler("required", Arc::new(FailingHandler), None)
+        .with_handler("test", test_handler.clone(), None);
+    let result =
+        tokio::spawn(async move { handler.handle_token_supply_changed(event, context).await })
+            .await;
+    assert!(result.unwrap_err().is_panic());
+    assert_eq!(test_handler.supply_events.lock().await[&token_id].len(), 2);
+
+    // A single required sink is called without a task, and its failure is raised the same way
+    let result = tokio::spawn(async move {
+        MultiHandler::new(false)
+            .with_handler("required", Arc::new(FailingHandler), None)
+            .handle_token_supply_changed(
+                TokenSupplyChangedEvent {
+                    token_id: "intel.tkn.near".parse().unwrap(),
+                    minted: 1,
+                    burned: 0,
+                    total_supply: None,
+                    rpc_total_supply: None,
+                    diverged: false,
+                },
+                BlockContext {
+                    block_height: 124_000_001,
+                    block_timestamp_nanosec: 1722000000000000000,
+                },
+            )
+            .await
+    })
+    .await;
+    assert!(result.unwrap_err().is_panic());
+}
+
+#[cfg(feature = "websocket")]
+#[tokio::test]
+async fn streams_filtered_events_to_websocket_clients() {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    use crate::websocket_handler::{LiveEvent, WebSocketHandler, WebSocketOptions};
+
+    let handler = WebSocketHandler::new(
+        WebSocketOptions {
+            replay_events: 2,
+            ..Default::default()
+        },
+        false,
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let router = handler.router();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    let context = EventContext {
+        transaction_id: "59rYXL82wYisbMf5xMm37mKJ1eeWyJTBQ3hMPjKn2huG"
+            .parse()
+            .unwrap(),
+        receipt_id: "51ScmrfA9r6J2hkCM5t9GyY9XeZW8gewqBXSju86NNYR"
+            .parse()
+            .unwrap(),
+        block_height: 124_000_000,
+        block_timestamp_nanosec: 1722000000000000000,
+        signer_id: "owner.near".parse().unwrap(),
+        predecessor_id: "tkn.near".parse().unwrap(),
+        attached_deposit: 0,
+        factory_id: Some("tkn.near".parse().unwrap()),
+    };
+    let new_token = |account_id: &str, symbol: &str| NewNep141Event {
+        account_id: account_id.parse().unwrap(),
+        meme_cooking: None,
+        metadata: Some(FtMetadata {
+            spec: "ft-1.0.0".to_string(),
+            name: symbol.to_string(),
+            symbol: symbol.to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 18,
+        }),
+        owner_id: None,
+        total_supply: None,
+        risk: None,
+        metadata_issues: Vec::new(),
+    };
+    // Falls out of the replay buffer
+    handler
+        .handle_new_nep141_with_metadata(new_token("old.tkn.near", "INTOLD"), context.clone())
+        .await;
+    handler
+        .handle_new_nep141_with_metadata(new_token("intel.tkn.near", "INTEL"), context.clone())
+        .await;
+    // Doesn't have a symbol
+    handler
+        .handle_token_supply_changed(
+            TokenSupplyChangedEvent {
+                token_id: "intel.tkn.near".parse().unwrap(),
+                minted: 1000,
+                burned: 0,
+                total_supply: Some(1000),
+                rpc_total_supply: None,
+                diverged: false,
+            },
+            BlockContext {
+                block_height: 124_000_001,
+                block_timestamp_nanosec: 1722000001000000000,
+            },
+        )
+        .await;
+
+    async fn next_message(
+        socket: &mut tokio_tungstenite::WebSocketStream<
+            tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+        >,
+    ) -> serde_json::Value {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+                .await
+                .expect("No message from the server")
+                .unwrap()
+                .unwrap();
+            if let Message::Text(text) = message {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+        .await
+        .unwrap();
+    socket
+        .send(Message::Text(r#"{"symbol": "("#.into()))
+        .await
+        .unwrap();
+    assert!(next_message(&mut socket).await.get("error").is_some());
+    socket
+        .send(Message::Text(
+            r#"{"factory": "tkn.near", "symbol": "^INT"}"#.into(),
+        ))
+        .await
+        .unwrap();
+    let replayed: LiveEvent = serde_json::from_value(next_message(&mut socket).await).unwrap();
+    assert_eq!(replayed.event, "newcontract_nep141");
+    assert_eq!(replayed.data["account_id"], "intel.tkn.near");
+    assert_eq!(
+        replayed.data["receipt_id"],
+        serde_json::to_value(context.receipt_id).unwrap()
+    );
+
+    // Not from the factory
+    handler
+        .handle_new_nep141_with_metadata(
+            new_token("intel.near", "INTX"),
+            EventContext {
+                predecessor_id: "owner.near".parse().unwrap(),
+                factory_id: None,
+                ..context.clone()
+            },
+        )
+        .await;
+    handler
+        .handle_new_nep141_with_metadata(
+            new_token("intel.xtkn.near", "INTY"),
+            EventContext {
+                predecessor_id: "owner.near".parse().unwrap(),
+                factory_id: None,
+                ..context.clone()
+            },
+        )
+        .await;
+    handler
+        .handle_new_nep141_with_metadata(new_token("dragon.tkn.near", "INTD"), context.clone())
+        .await;
+    let live: LiveEvent = serde_json::from_value(next_message(&mut socket).await).unwrap();
+    assert_eq!(live.data["account_id"], "dragon.tkn.near");
+    assert_eq!(live.data["metadata"]["symbol"], "INTD");
+
+    // SSE replays the same buffer, filtered by the query string
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            b"GET /sse?events=newcontract_nep141&symbol=%5EINT HTTP/1.1\r\nhost: localhost\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut buffer = [0; 4096];
+        while !response.contains("dragon.tkn.near") {
+            let read = stream.read(&mut buffer).await.unwrap();
+            assert_ne!(read, 0, "Connection closed: {response}");
+            response.push_str(&String::from_utf8_lossy(&buffer[..read]));
+        }
+    })
+    .await
+    .expect("No events from /sse");
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.contains("event: newcontract_nep141"));
+    assert!(response.contains("intel.xtkn.near"));
+}
+
+#[tokio::test]
+async fn queries_known_tokens() {
+    let path = std::env::temp_dir().join(format!(
+        "known_tokens_test_{}.txt",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    // Written before first-seen blocks were recorded, with lines that can't be parsed
+    std::fs::write(
+        &path,
+        b"wrap.near\n\nNot an account\nbad.near\tnot a block\t1\tBAD\n\xff.near\n",
+    )
+    .unwrap();
+    let known_token = |account_id: &str, block_height: BlockHeight, symbol: &str| KnownToken {
+        account_id: account_id.parse().unwrap(),
+        first_seen_block_height: Some(block_height),
+        first_seen_timestamp_nanosec: Some(1722000000000000000 + block_height as u128),
+        symbol: Some(symbol.to_string()),
+    };
+    let storage = TxtFileStorage::new(&path).await;
+    storage
+        .mark_handled_token(known_token("intel.tkn.near", 124_000_000, "INTEL"))
+        .await;
+    storage
+        .mark_handled_token(known_token(
+            "intelligence.tkn.near",
+            123_000_000,
+            "INTELLIGENCE",
+        ))
+        .await;
+    storage
+        .mark_handled_token(known_token(
+            "blackdragon.tkn.near",
+            125_000_000,
+            "BLACKDRAGON",
+        ))
+        .await;
+    // Marked again, only the last entry is kept
+    storage
+        .mark_handled_token(known_token(
+            "blackdragon.tkn.near",
+            126_000_000,
+            "BLACKDRAGON",
+        ))
+        .await;
+
+    let storage = TxtFileStorage::new(&path).await;
+    assert_eq!(storage.token_count().await, 4);
+    assert!(
+        storage
+            .is_already_indexed(&"wrap.near".parse().unwrap())
+            .await
+    );
+    let wrap = storage
+        .get_token(&"wrap.near".parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(wrap.first_seen_block_height, None);
+    assert_eq!(wrap.symbol, None);
+    assert_eq!(
+        storage.get_token(&"intel.tkn.near".parse().unwrap()).await,
+        Some(known_token("intel.tkn.near", 124_000_000, "INTEL"))
+    );
+    let in_range = storage
+        .tokens_first_seen_between(123_000_000, 124_000_000, 10)
+        .await;
+    assert_eq!(
+        in_range
+            .iter()
+            .map(|token| token.account_id.as_str())
+            .collect::<Vec<_>>(),
+        vec!["intelligence.tkn.near", "intel.tkn.near"]
+    );
+    assert_eq!(
+        storage
+            .tokens_first_seen_between(0, BlockHeight::MAX, 1)
+            .await
+            .len(),
+        1
+    );
+    assert_eq!(
+        storage
+            .tokens_first_seen_between(0, BlockHeight::MAX, 10)
+            .await
+            .iter()
+            .map(|token| (token.account_id.as_str(), token.first_seen_block_height))
+            .collect::<Vec<_>>(),
+        vec![
+            ("intelligence.tkn.near", Some(123_000_000)),
+            ("intel.tkn.near", Some(124_000_000)),
+            ("blackdragon.tkn.near", Some(126_000_000)),
+        ]
+    );
+    // The exact match goes first, although it was seen later
+    let found = storage.search_by_symbol("intel", 10).await;
+    assert_eq!(
+        found
+            .iter()
+            .map(|token| token.account_id.as_str())
+            .collect::<Vec<_>>(),
+        vec!["intel.tkn.near", "intelligence.tkn.near"]
+    );
+
+    #[cfg(feature = "api")]
+    {
+        use crate::api::{api_router, StatusResponse};
+        use crate::IndexerStatus;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let router = api_router(Arc::new(storage), Arc::new(IndexerStatus::default()), false);
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        let get = |path: &str| {
+            let request = reqwest::get(format!("{url}{path}"));
+            async move {
+                let response = request.await.unwrap();
+                (response.status(), response.text().await.unwrap())
+            }
+        };
+
+        let (status, body) = get("/tokens/intel.tkn.near").await;
+        assert_eq!(status, reqwest::StatusCode::OK);
+        let token: KnownToken = serde_json::from_str(&body).unwrap();
+        assert_eq!(token.first_seen_block_height, Some(124_000_000));
+        let (status, _) = get("/tokens/unknown.near").await;
+        assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+        let (_, body) = get("/tokens?from_block=124000000").await;
+        let tokens: Vec<KnownToken> = serde_json::from_str(&body).unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].account_id, "blackdragon.tkn.near");
+        let (_, body) = get("/tokens/search?symbol=DRAGON").await;
+        let tokens: Vec<KnownToken> = serde_json::from_str(&body).unwrap();
+        assert_eq!(tokens.len(), 1);
+        let (_, body) = get("/status").await;
+        let status: StatusResponse = serde_json::from_str(&body).unwrap();
+        assert_eq!(status.known_tokens, 4);
+        assert_eq!(status.last_block_height, None);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "kafka")]
+#[test]
+fn routes_kafka_topics() {
+    use crate::kafka_handler::{parse_kafka_topics, KafkaHandler, KafkaHandlerOptions};
+
+    let topics =
+        parse_kafka_topics("newcontract_nep141=tokens.new, token_supply_changed=tokens.supply")
+            .unwrap();
+    assert_eq!(topics.len(), 2);
+    assert_eq!(topics["newcontract_nep141"], "tokens.new");
+    assert!(parse_kafka_topics("newcontract_nep141").is_err());
+    assert!(parse_kafka_topics("").unwrap().is_empty());
+
+    // The producer doesn't connect until the first message is sent
+    let mut options = KafkaHandlerOptions::new("localhost:9092");
+    options.topics = topics;
+    let handler = KafkaHandler::new(options, true).unwrap();
+    assert_eq!(handler.topic("newcontract_nep141"), "tokens.new");
+    assert_eq!(
+        handler.topic("token_holders_snapshot"),
+        "token_holders_snapshot_testnet"
+    );
+}
+
+#[cfg(feature = "kafka")]
+#[tokio::test]
+#[ignore = "needs a Kafka broker at $KAFKA_BROKERS"]
+async fn writes_events_to_kafka() {
+    use rdkafka::{
+        consumer::{Consumer, StreamConsumer},
+        message::Headers,
+        ClientConfig, Message,
+    };
+
+    use crate::kafka_handler::{KafkaHandler, KafkaHandlerOptions, BLOCK_HEIGHT_HEADER};
+
+    let brokers = std::env::var("KAFKA_BROKERS").expect("No $KAFKA_BROKERS set");
+    let topic = format!(
+        "token_supply_changed_test_{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    );
+    let mut options = KafkaHandlerOptions::new(&brokers);
+    options
+        .topics
+        .insert("token_supply_changed".to_string(), topic.clone());
+    let handler = KafkaHandler::new(options, false).unwrap();
+    handler
+        .handle_token_supply_changed(
+            TokenSupplyChangedEvent {
+                token_id: "intel.tkn.near".parse().unwrap(),
+                minted: 1000,
+                burned: 10,
+                total_supply: Some(990),
+                rpc_total_supply: None,
+                diverged: false,
+            },
+            BlockContext {
+                block_height: 124_000_000,
+                block_timestamp_nanosec: 1722000000000000000,
+            },
+        )
+        .await;
+
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &brokers)
+        .set("group.id", &topic)
+        .set("auto.offset.reset", "earliest")
+        .create()
+        .unwrap();
+    consumer.subscribe(&[&topic]).unwrap();
+    let message = tokio::time::timeout(Duration::from_secs(30), consumer.recv())
+        .await
+        .expect("No message received")
+        .unwrap();
+    assert_eq!(message.key(), Some("intel.tkn.near".as_bytes()));
+    let block_height = message
+        .headers()
+        .unwrap()
+        .iter()
+        .find(|header| header.key == BLOCK_HEIGHT_HEADER)
+        .and_then(|header| header.value)
+        .unwrap();
+    assert_eq!(block_height, b"124000000");
+    let data: TokenSupplyChangedEventData =
+        serde_json::from_slice(message.payload().unwrap()).unwrap();
+    assert_eq!(data.token_id, "intel.tkn.near");
+    assert_eq!(data.total_supply, Some(990));
+}
+
+#[cfg(feature = "nats")]
+#[test]
+fn derives_nats_subjects() {
+    use crate::nats_handler::{nats_message_id, nats_stream_name, nats_subject};
+
+    assert_eq!(
+        nats_subject("newcontract_nep141", false),
+        "newcontract.nep141"
+    );
+    assert_eq!(
+        nats_subject("newcontract_meme_cooking_meme", false),
+        "newcontract.meme_cooking_meme"
+    );
+    assert_eq!(
+        nats_subject("token_supply_changed", true),
+        "testnet.token.supply_changed"
+    );
+    assert_eq!(nats_stream_name("NEW_TOKENS", false), "NEW_TOKENS");
+    assert_eq!(nats_stream_name("NEW_TOKENS", true), "NEW_TOKENS_TESTNET");
+    assert_ne!(
+        nats_message_id("token_first_pool", "receipt", "a.near"),
+        nats_message_id("token_first_pool", "receipt", "b.near")
+    );
+}
+
+#[cfg(feature = "nats")]
+#[tokio::test]
+#[ignore = "needs a NATS server with JetStream at $NATS_URL"]
+async fn deduplicates_nats_messages() {
+    use crate::nats_handler::{NatsHandler, NatsHandlerOptions};
+
+    let client = async_nats::connect(std::env::var("NATS_URL").expect("No $NATS_URL set"))
+        .await
+        .unwrap();
+    let stream = format!(
+        "NEW_TOKENS_TEST_{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    );
+    let handler = NatsHandler::new(
+        client.clone(),
+        NatsHandlerOptions {
+            stream: stream.clone(),
+            ..Default::default()
+        },
+        true,
+    )
+    .await
+    .unwrap();
+    let event = || TokenHoldersSnapshotEvent {
+        token_id: "intel.tkn.near".parse().unwrap(),
+        holders: 10,
+        blocks_since_launch: 100,
+        is_final: false,
+    };
+    let context = BlockContext {
+        block_height: 124_000_000,
+        block_timestamp_nanosec: 1722000000000000000,
+    };
+    // The same block is indexed twice
+    handler
+        .handle_token_holders_snapshot(event(), context.clone())
+        .await;
+    handler
+        .handle_token_holders_snapshot(event(), context.clone())
+        .await;
+    handler
+        .handle_token_holders_snapshot(
+            event(),
+            BlockContext {
+                block_height: 124_000_100,
+                ..context
+            },
+        )
+        .await;
+
+    let jetstream = async_nats::jetstream::new(client);
+    let mut stream = jetstream.get_stream(&stream).await.unwrap();
+    assert_eq!(stream.info().await.unwrap().state.messages, 2);
+    stream.delete_message(1).await.unwrap();
+    jetstream
+        .delete_stream(&stream.cached_info().config.name)
+        .await
+        .unwrap();
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+#[ignore = "needs a PostgreSQL database at $DATABASE_URL"]
+async fn upserts_tokens_to_postgres() {
+    use sqlx::Row;
+
+    use crate::postgres_handler::PostgresHandler;
+
+    let pool = sqlx::PgPool::connect(&std::env::var("DATABASE_URL").expect("No $DATABASE_URL set"))
+        .await
+        .unwrap();
+    let handler = PostgresHandler::new(pool.clone(), false).await.unwrap();
+    let token_id: AccountId = format!(
+        "test{}.tkn.near",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    )
+    .parse()
+    .unwrap();
+    let metadata = |name: &str| FtMetadata {
+        spec: "ft-1.0.0".to_string(),
+        name: name.to_string(),
+        symbol: "TEST".to_string(),
+        icon: None,
+        reference: None,
+        reference_hash: None,
+        decimals: 18,
+    };
+    let event = |name: &str| NewNep141Event {
+        account_id: token_id.clone(),
+        meme_cooking: None,
+        metadata: Some(metadata(name)),
+        owner_id: Some("owner.near".parse().unwrap()),
+        total_supply: Some(u128::MAX),
+        risk: None,
+        metadata_issues: Vec::new(),
+    };
+    let context = |receipt_id: &str, block_height: BlockHeight| EventContext {
+        transaction_id: "59rYXL82wYisbMf5xMm37mKJ1eeWyJTBQ3hMPjKn2huG"
+            .parse()
+            .unwrap(),
+        receipt_id: receipt_id.parse().unwrap(),
+        block_height,
+        block_timestamp_nanosec: 1726907853133808278,
+        signer_id: "owner.near".parse().unwrap(),
+        predecessor_id: "tkn.near".parse().unwrap(),
+        attached_deposit: 0,
+        factory_id: Some("tkn.near".parse().unwrap()),
+    };
+    let receipt_id = "51ScmrfA9r6J2hkCM5t9GyY9XeZW8gewqBXSju86NNYR";
+    handler
+        .handle_new_nep141_with_metadata(event("First"), context(receipt_id, 124_000_000))
+        .await;
+    // The same receipt is indexed again
+    handler
+        .handle_new_nep141_with_metadata(event("Replayed"), context(receipt_id, 124_000_000))
+        .await;
+    // An older receipt doesn't overwrite a newer one
+    handler
+        .handle_new_nep141_with_metadata(
+            event("Older"),
+            context("59rYXL82wYisbMf5xMm37mKJ1eeWyJTBQ3hMPjKn2huG", 123_000_000),
+        )
+        .await;
+    let row = sqlx::query(
+        "SELECT name, receipt_id, total_supply::text AS total_supply FROM tokens WHERE account_id = $1",
+    )
+    .bind(token_id.to_string())
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(row.get::<String, _>("name"), "First");
+    assert_eq!(row.get::<String, _>("receipt_id"), receipt_id);
+    assert_eq!(row.get::<String, _>("total_supply"), u128::MAX.to_string());
+
+    handler
+        .handle_token_metadata_updated(
+            TokenMetadataUpdatedEvent {
+                token_id: token_id.clone(),
+                changes: Vec::new(),
+                previous_hash: String::new(),
+                hash: String::new(),
+                metadata: metadata("Renamed"),
+            },
+            BlockContext {
+                block_height: 124_000_100,
+                block_timestamp_nanosec: 1726907953133808278,
+            },
+        )
+        .await;
+    let row = sqlx::query("SELECT name, metadata_block_height FROM tokens WHERE account_id = $1")
+        .bind(token_id.to_string())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(row.get::<String, _>("name"), "Renamed");
+    assert_eq!(row.get::<i64, _>("metadata_block_height"), 124_000_100);
+
+    sqlx::query("DELETE FROM tokens WHERE account_id = $1")
+        .bind(token_id.to_string())
+        .execute(&pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs Redis 6.2+ at $REDIS_URL"]
+async fn reclaims_unacknowledged_redis_events() {
+    use redis::aio::ConnectionManager;
+
+    use crate::redis_consumer::{RedisConsumerOptions, RedisEventConsumer};
+    use crate::redis_handler::{PushToRedisStream, RedisRetention, StreamRetention};
+
+    let client =
+        redis::Client::open(std::env::var("REDIS_URL").expect("No $REDIS_URL set")).unwrap();
+    let connection = ConnectionManager::new(client).await.unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let group = format!("supply_test_{now}");
+    let options = |consumer: &str| RedisConsumerOptions {
+        block_timeout: Duration::from_millis(100),
+        min_idle_time: Duration::from_millis(100),
+        start_id: "$".to_string(),
+        ..RedisConsumerOptions::new(&group, consumer)
+    };
+    let mut first = RedisEventConsumer::<TokenSupplyChangedEventData>::new(
+        connection.clone(),
+        "token_supply_changed",
+        true,
+        options("first"),
+    )
+    .await
+    .unwrap();
+    let mut second = RedisEventConsumer::<TokenSupplyChangedEventData>::new(
+        connection.clone(),
+        "token_supply_changed",
+        true,
+        options("second"),
+    )
+    .await
+    .unwrap();
+
+    // Block heights above anything already in the stream
+    let block_height = now as BlockHeight;
+    let handler = PushToRedisStream::new(
+        connection.clone(),
+        RedisRetention::new(StreamRetention::MaxLen(1_000)),
+        true,
+    )
+    .await;
+    for (i, token_id) in ["intel.tkn.near", "blackdragon.tkn.near"]
+        .into_iter()
+        .enumerate()
+    {
+        handler
+            .handle_token_supply_changed(
+                TokenSupplyChangedEvent {
+                    token_id: token_id.parse().unwrap(),
+                    minted: 1000,
+                    burned: 10,
+                    total_supply: Some(990),
+                    rpc_total_supply: None,
+                    diverged: false,
+                },
+                BlockContext {
+                    block_height: block_height + i as BlockHeight,
+                    block_timestamp_nanosec: 1722000000000000000,
+                },
+            )
+            .await;
+    }
+    redis::cmd("XADD")
+        .arg("token_supply_changed_testnet")
+        .arg(format!("{}-0", block_height + 2))
+        .arg("event")
+        .arg("not json")
+        .query_async::<_, String>(&mut connection.clone())
+        .await
+        .unwrap();
+
+    // The invalid entry is skipped
+    let events = first.next_batch().await.unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].block_height, block_height);
+    assert_eq!(events[0].data.token_id, "intel.tkn.near");
+    assert_eq!(events[1].data.token_id, "blackdragon.tkn.near");
+    first.ack(&[events[0].id.clone()]).await.unwrap();
+
+    // After a restart, unacknowledged events are read once, not again when they fail
+    let mut first = RedisEventConsumer::<TokenSupplyChangedEventData>::new(
+        connection.clone(),
+        "token_supply_changed",
+        true,
+        options("first"),
+    )
+    .await
+    .unwrap();
+    let pending = first.next_batch().await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id, events[1].id);
+    assert!(first.next_batch().await.unwrap().is_empty());
+
+    // The first consumer "crashed" before acknowledging the second event
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let reclaimed = second.next_batch().await.unwrap();
+    assert_eq!(reclaimed.len(), 1);
+    assert_eq!(reclaimed[0].id, events[1].id);
+    second.ack(&[reclaimed[0].id.clone()]).await.unwrap();
+    assert!(second.next_batch().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs Redis 6.2+ at $REDIS_URL"]
+async fn writes_redis_entries_like_inevents() {
+    use inevents_redis::RedisEventStream;
+    use redis::{aio::ConnectionManager, streams::StreamRangeReply, AsyncCommands};
+
+    use crate::redis_consumer::{RedisConsumerOptions, RedisEventConsumer};
+    use crate::redis_handler::{PushToRedisStream, RedisRetention, StreamRetention};
+
+    let client =
+        redis::Client::open(std::env::var("REDIS_URL").expect("No $REDIS_URL set")).unwrap();
+    let mut connection = ConnectionManager::new(client).await.unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let mut consumer = RedisEventConsumer::<TokenSupplyChangedEventData>::new(
+        connection.clone(),
+        "token_supply_changed",
+        true,
+        RedisConsumerOptions {
+            block_timeout: Duration::from_millis(100),
+            start_id: "$".to_string(),
+            ..RedisConsumerOptions::new(format!("inevents_test_{now}"), "test")
+        },
+    )
+    .await
+    .unwrap();
+
+    let block_height = now as BlockHeight;
+    let event = TokenSupplyChangedEvent {
+        token_id: "intel.tkn.near".parse().unwrap(),
+        minted: 1000,
+        burned: 10,
+        total_supply: Some(990),
+        rpc_total_supply: None,
+        diverged: false,
+    };
+    let context = |block_height| BlockContext {
+        block_height,
+        block_timestamp_nanosec: 1722000000000000000,
+    };
+    PushToRedisStream::new(
+        connection.clone(),
+        RedisRetention::new(StreamRetention::MaxLen(1_000)),
+        true,
+    )
+    .await
+    .handle_token_supply_changed(event.clone(), context(block_height))
+    .await;
+    // The same event, written the way releases before the retention options did
+    RedisEventStream::new(connection.clone(), "token_supply_changed_testnet")
+        .emit_event(
+            block_height + 1,
+            TokenSupplyChangedEventData::new(event.clone(), context(block_height + 1)),
+            1_000,
+        )
+        .await
+        .unwrap();
+
+    let entries: StreamRangeReply = connection
+        .xrange("token_supply_changed_testnet", block_height, "+")
+        .await
+        .unwrap();
+    assert_eq!(entries.ids.len(), 2);
+    let (ours, theirs) = (&entries.ids[0], &entries.ids[1]);
+    assert!(ours.id.starts_with(&format!("{block_height}-")));
+    assert!(theirs.id.starts_with(&format!("{}-", block_height + 1)));
+    let mut our_fields = ours.map.keys().collect::<Vec<_>>();
+    let mut their_fields = theirs.map.keys().collect::<Vec<_>>();
+    our_fields.sort();
+    their_fields.sort();
+    assert_eq!(our_fields, their_fields);
+
+    let events = consumer.next_batch().await.unwrap();
+    assert_eq!(events.len(), 2);
+    for (consumed, block_height) in events.iter().zip([block_height, block_height + 1]) {
+        assert_eq!(consumed.block_height, block_height);
+        assert_eq!(
+            consumed.data,
+            TokenSupplyChangedEventData::new(event.clone(), context(block_height))
+        );
+    }
+}
+
+#[test]
+fn parses_redis_retention() {
+    use crate::redis_handler::{
+        parse_redis_retention, parse_stream_retention, RedisRetention, StreamRetention,
+    };
+
+    assert_eq!(
+        parse_stream_retention("maxlen:1000"),
+        Ok(StreamRetention::MaxLen(1000))
+    );
+    assert!(parse_stream_retention("minid:1000").is_err());
+    assert!(parse_stream_retention("blocks").is_err());
+    let mut retention = RedisRetention::new(StreamRetention::MaxLen(1000));
+    retention.streams = parse_redis_retention(
+        "newcontract_nep141=maxlen:100000, newcontract_meme_cooking_meme=blocks:2000000",
+    )
+    .unwrap();
+    assert_eq!(
+        retention.get("newcontract_nep141"),
+        StreamRetention::MaxLen(100_000)
+    );
+    assert_eq!(
+        retention.get("newcontract_meme_cooking_meme"),
+        StreamRetention::MaxBlocks(2_000_000)
+    );
+    assert_eq!(
+        retention.get("token_supply_changed"),
+        StreamRetention::MaxLen(1000)
+    );
+    assert!(parse_redis_retention("newcontract_nep141").is_err());
+    assert!(parse_redis_retention("=maxlen:10").is_err());
+    assert!(parse_redis_retention("").unwrap().is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs Redis at $REDIS_URL"]
+async fn trims_redis_streams_by_block_age() {
+    use redis::{aio::ConnectionManager, AsyncCommands};
+
+    use crate::redis_handler::{PushToRedisStream, RedisRetention, StreamRetention};
+
+    let client =
+        redis::Client::open(std::env::var("REDIS_URL").expect("No $REDIS_URL set")).unwrap();
+    let mut connection = ConnectionManager::new(client).await.unwrap();
+    let mut retention = RedisRetention::new(StreamRetention::MaxLen(1_000));
+    retention.streams.insert(
+        "token_holders_snapshot".to_string(),
+        StreamRetention::MaxBlocks(15),
+    );
+    let handler = PushToRedisStream::new(connection.clone(), retention, true).await;
+    // Block heights above anything already in the stream
+    let block_height = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as BlockHeight;
+    for blocks_since_launch in [0, 10, 20] {
+        handler
+            .handle_token_holders_snapshot(
+                TokenHoldersSnapshotEvent {
+                    token_id: "intel.tkn.near".parse().unwrap(),
+                    holders: 10,
+                    blocks_since_launch,
+                    is_final: false,
+                },
+                BlockContext {
+                    block_height: block_height + blocks_since_launch,
+                    block_timestamp_nanosec: 1722000000000000000,
+                },
+            )
+            .await;
+    }
+
+    // Only events of the last 15 blocks are kept, older events from previous runs are removed too
+    let len: usize = connection
+        .xlen("token_holders_snapshot_testnet")
+        .await
+        .unwrap();
+    assert_eq!(len, 2);
+}
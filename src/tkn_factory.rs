@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use inindexer::{
+    near_indexer_primitives::{
+        types::{AccountId, Balance},
+        views::{ActionView, ReceiptEnumView},
+        StreamerMessage,
+    },
+    near_utils::dec_format,
+    IncompleteTransaction, TransactionReceipt,
+};
+use serde::Deserialize;
+
+use crate::{
+    initial_distribution::InitialDistributions,
+    metadata_updates::{seed_metadata, TokenMetadataStorage},
+    new_nep141::{FtMetadata, HandledTokensStorage, KnownToken, NewNep141Event},
+    risk::RiskScorer,
+    ContractEventHandler, EventContext,
+};
+
+pub const TKN_FACTORY: &str = "tkn.near";
+
+/// Detects tokens created by tkn.near without calling `ft_metadata` on RPC, since
+/// everything is already in the arguments of `create_token`.
+pub struct TknFactoryIndexer {
+    storage: Arc<dyn HandledTokensStorage>,
+    pub risk_scorer: Option<Arc<RiskScorer>>,
+    /// Metadata of new tokens is saved here, so that updates are compared with it
+    pub metadata_storage: Option<Arc<dyn TokenMetadataStorage>>,
+}
+
+impl TknFactoryIndexer {
+    pub fn new(storage: Arc<dyn HandledTokensStorage>) -> Self {
+        Self {
+            storage,
+            risk_scorer: None,
+            metadata_storage: None,
+        }
+    }
+
+    pub async fn detect_tkn_factory<T: ContractEventHandler>(
+        &mut self,
+        receipt: &TransactionReceipt,
+        tx: &IncompleteTransaction,
+        block: &StreamerMessage,
+        handler: Arc<T>,
+        distributions: &InitialDistributions,
+    ) {
+        if handler.is_testnet() || receipt.receipt.receipt.predecessor_id != TKN_FACTORY {
+            return;
+        }
+        let ReceiptEnumView::Action { actions, .. } = &receipt.receipt.receipt.receipt else {
+            return;
+        };
+        if !actions
+            .iter()
+            .any(|action| matches!(action, ActionView::CreateAccount))
+        {
+            return;
+        }
+
+        let token_id = &receipt.receipt.receipt.receiver_id;
+        // The sub-account is created by the factory in a receipt that follows `create_token`
+        let Some(args) = tx
+            .receipts
+            .values()
+            .flatten()
+            .filter(|receipt| receipt.receipt.receipt.receiver_id == TKN_FACTORY)
+            .find_map(|receipt| find_create_token_args(receipt, token_id))
+        else {
+            log::warn!("tkn.near created {token_id}, but create_token call was not found");
+            return;
+        };
+        if self.storage.is_already_indexed(token_id).await {
+            return;
+        }
+
+        log::info!("Found tkn.near token: {token_id}");
+        let context = EventContext::new(receipt, tx, block);
+        let mut event = NewNep141Event::new(token_id.clone(), None, Some(args.metadata));
+        event.owner_id = Some(args.owner_id);
+        event.total_supply = Some(args.total_supply);
+        self.storage
+            .mark_handled_token(KnownToken::new(&event, &context))
+            .await;
+        if let (Some(storage), Some(metadata)) = (&self.metadata_storage, &event.metadata) {
+            seed_metadata(&**storage, token_id, metadata).await;
+        }
+        if let Some(risk_scorer) = &self.risk_scorer {
+            event.risk = Some(
+                risk_scorer
+                    .assess(&event, &tx.transaction.transaction.signer_id, &context)
+                    .await,
+            );
+        }
+        handler
+            .handle_new_nep141_with_metadata(event, context)
+            .await;
+        distributions
+            .expect_mint(token_id, block.block.header.height, &*handler)
+            .await;
+    }
+}
+
+fn find_create_token_args(
+    receipt: &TransactionReceipt,
+    token_id: &AccountId,
+) -> Option<TknTokenArgs> {
+    let ReceiptEnumView::Action { actions, .. } = &receipt.receipt.receipt.receipt else {
+        return None;
+    };
+    actions.iter().find_map(|action| match action {
+        ActionView::FunctionCall {
+            method_name, args, ..
+        } if method_name == "create_token" => {
+            match serde_json::from_slice::<TknCreateTokenArgs>(args) {
+                Ok(TknCreateTokenArgs { args }) if args.token_id().as_ref() == Some(token_id) => {
+                    Some(args)
+                }
+                Ok(_) => None,
+                Err(err) => {
+                    log::warn!("Failed to parse tkn.near create_token arguments: {err}");
+                    None
+                }
+            }
+        }
+        _ => None,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct TknCreateTokenArgs {
+    args: TknTokenArgs,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TknTokenArgs {
+    pub owner_id: AccountId,
+    #[serde(with = "dec_format")]
+    pub total_supply: Balance,
+    pub metadata: FtMetadata,
+}
+
+impl TknTokenArgs {
+    /// tkn.near creates `{symbol}.tkn.near` with the symbol in lowercase
+    pub fn token_id(&self) -> Option<AccountId> {
+        format!(
+            "{}.{TKN_FACTORY}",
+            self.metadata.symbol.to_ascii_lowercase()
+        )
+        .parse()
+        .ok()
+    }
+}
@@ -1,48 +1,161 @@
+use crate::new_nep141::{KnownToken, TokenRegistry};
 use crate::HandledTokensStorage;
 
 use async_trait::async_trait;
-use inindexer::near_indexer_primitives::types::AccountId;
-use std::collections::HashSet;
+use inindexer::near_indexer_primitives::types::{AccountId, BlockHeight};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::path::PathBuf;
 use tokio::fs::{File, OpenOptions};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::sync::RwLock;
 
+/// One token per line: `account_id`, then tab-separated first-seen block height, timestamp
+/// and symbol. Files with only account ids (written before first-seen blocks were recorded)
+/// are still read.
 pub struct TxtFileStorage {
     path: PathBuf,
-    handled_accounts: RwLock<HashSet<AccountId>>,
+    handled_accounts: RwLock<KnownTokens>,
+}
+
+#[derive(Default)]
+struct KnownTokens {
+    tokens: HashMap<AccountId, KnownToken>,
+    by_first_seen_block: BTreeMap<BlockHeight, BTreeSet<AccountId>>,
+}
+
+impl KnownTokens {
+    /// A token that is inserted again replaces the previous entry, including its first-seen block
+    fn insert(&mut self, token: KnownToken) {
+        if let Some(block_height) = self
+            .tokens
+            .get(&token.account_id)
+            .and_then(|previous| previous.first_seen_block_height)
+        {
+            if let Some(accounts) = self.by_first_seen_block.get_mut(&block_height) {
+                accounts.remove(&token.account_id);
+                if accounts.is_empty() {
+                    self.by_first_seen_block.remove(&block_height);
+                }
+            }
+        }
+        if let Some(block_height) = token.first_seen_block_height {
+            self.by_first_seen_block
+                .entry(block_height)
+                .or_default()
+                .insert(token.account_id.clone());
+        }
+        self.tokens.insert(token.account_id.clone(), token);
+    }
 }
 
 impl TxtFileStorage {
     pub async fn new(path: impl Into<PathBuf>) -> Self {
         let path = path.into();
-        let mut set = HashSet::new();
+        let mut tokens = KnownTokens::default();
         if let Ok(file) = File::open(&path).await {
             let mut reader = BufReader::new(file);
-            let mut line = String::new();
-            while reader.read_line(&mut line).await.unwrap() != 0 {
-                set.insert(line.trim().parse().unwrap());
+            let mut line = Vec::new();
+            let mut line_number = 0;
+            loop {
                 line.clear();
+                line_number += 1;
+                match reader.read_until(b'\n', &mut line).await {
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(err) => {
+                        log::error!("Failed to read {}: {err}", path.display());
+                        break;
+                    }
+                }
+                let parsed = std::str::from_utf8(&line)
+                    .map_err(|err| err.to_string())
+                    .and_then(|line| parse_line(line.trim_end_matches(['\n', '\r'])));
+                match parsed {
+                    Ok(token) => tokens.insert(token),
+                    Err(err) => {
+                        log::warn!("Skipping line {line_number} of {}: {err}", path.display())
+                    }
+                }
             }
         }
         Self {
             path,
-            handled_accounts: RwLock::new(set),
+            handled_accounts: RwLock::new(tokens),
         }
     }
 }
 
+fn parse_line(line: &str) -> Result<KnownToken, String> {
+    let mut fields = line.split('\t');
+    let account_id = fields.next().unwrap_or_default().trim();
+    Ok(KnownToken {
+        account_id: account_id
+            .parse()
+            .map_err(|err| format!("Invalid account id {account_id:?}: {err}"))?,
+        first_seen_block_height: fields
+            .next()
+            .map(|height| {
+                height
+                    .parse()
+                    .map_err(|err| format!("Invalid block height {height:?}: {err}"))
+            })
+            .transpose()?,
+        first_seen_timestamp_nanosec: fields
+            .next()
+            .map(|timestamp| {
+                timestamp
+                    .parse()
+                    .map_err(|err| format!("Invalid timestamp {timestamp:?}: {err}"))
+            })
+            .transpose()?,
+        symbol: fields
+            .next()
+            .filter(|symbol| !symbol.is_empty())
+            .map(|symbol| symbol.to_string()),
+    })
+}
+
+fn format_line(token: &KnownToken) -> String {
+    match (
+        token.first_seen_block_height,
+        token.first_seen_timestamp_nanosec,
+    ) {
+        (Some(block_height), Some(timestamp)) => format!(
+            "{}\t{block_height}\t{timestamp}\t{}\n",
+            token.account_id,
+            token
+                .symbol
+                .as_deref()
+                .unwrap_or_default()
+                .replace(['\t', '\n', '\r'], " ")
+        ),
+        _ => format!("{}\n", token.account_id),
+    }
+}
+
 #[async_trait]
 impl HandledTokensStorage for TxtFileStorage {
     async fn is_already_indexed(&self, account_id: &AccountId) -> bool {
-        self.handled_accounts.read().await.contains(account_id)
+        self.handled_accounts
+            .read()
+            .await
+            .tokens
+            .contains_key(account_id)
     }
 
     async fn mark_handled(&self, account_id: AccountId) {
-        self.handled_accounts
-            .write()
-            .await
-            .insert(account_id.clone());
+        self.mark_handled_token(KnownToken {
+            account_id,
+            first_seen_block_height: None,
+            first_seen_timestamp_nanosec: None,
+            symbol: None,
+        })
+        .await
+    }
+
+    async fn mark_handled_token(&self, token: KnownToken) {
+        let line = format_line(&token);
+        self.handled_accounts.write().await.insert(token);
         let mut file = OpenOptions::new()
             .create(true)
             .write(true)
@@ -50,9 +163,67 @@ impl HandledTokensStorage for TxtFileStorage {
             .open(&self.path)
             .await
             .unwrap();
-        file.write_all((account_id.to_string() + "\n").as_bytes())
-            .await
-            .unwrap();
+        file.write_all(line.as_bytes()).await.unwrap();
         file.flush().await.unwrap();
     }
 }
+
+#[async_trait]
+impl TokenRegistry for TxtFileStorage {
+    async fn get_token(&self, account_id: &AccountId) -> Option<KnownToken> {
+        self.handled_accounts
+            .read()
+            .await
+            .tokens
+            .get(account_id)
+            .cloned()
+    }
+
+    async fn tokens_first_seen_between(
+        &self,
+        from: BlockHeight,
+        to: BlockHeight,
+        limit: usize,
+    ) -> Vec<KnownToken> {
+        if from > to {
+            return Vec::new();
+        }
+        let known = self.handled_accounts.read().await;
+        known
+            .by_first_seen_block
+            .range(from..=to)
+            .flat_map(|(_, account_ids)| account_ids)
+            .take(limit)
+            .map(|account_id| known.tokens[account_id].clone())
+            .collect()
+    }
+
+    async fn search_by_symbol(&self, query: &str, limit: usize) -> Vec<KnownToken> {
+        let query = query.to_lowercase();
+        let known = self.handled_accounts.read().await;
+        let mut found = known
+            .tokens
+            .values()
+            .filter_map(|token| {
+                let symbol = token.symbol.as_ref()?.to_lowercase();
+                symbol.contains(&query).then_some((symbol != query, token))
+            })
+            .collect::<Vec<_>>();
+        found.sort_by_key(|(inexact, token)| {
+            (
+                *inexact,
+                token.first_seen_block_height.unwrap_or(BlockHeight::MAX),
+                token.account_id.clone(),
+            )
+        });
+        found
+            .into_iter()
+            .take(limit)
+            .map(|(_, token)| token.clone())
+            .collect()
+    }
+
+    async fn token_count(&self) -> usize {
+        self.handled_accounts.read().await.tokens.len()
+    }
+}
@@ -0,0 +1,498 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use inindexer::near_indexer_primitives::types::AccountId;
+use intear_events::events::newcontract::{
+    meme_cooking_meme::NewMemeCookingMemeEvent, meme_cooking_token::NewMemeCookingTokenEvent,
+    nep141::NewContractNep141Event,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::bridge;
+use crate::events::{
+    BridgedTokenCreatedEvent, BridgedTokenCreatedEventData, LaunchpadTokenCreatedEvent,
+    LaunchpadTokenCreatedEventData, MemeCookingCampaignOutcomeEventData,
+    MemeExpiredWithoutTokenEvent, MemeReachedHardCapEvent, MemeReachedSoftCapEvent,
+    NewContractNep141EventData, NewMemeCookingMemeEventData, NewMemeCookingTokenEventData,
+    TokenFirstPoolEvent, TokenFirstPoolEventData, TokenHoldersSnapshotEvent,
+    TokenHoldersSnapshotEventData, TokenInitialDistributionEvent,
+    TokenInitialDistributionEventData, TokenMetadataUpdatedEvent, TokenMetadataUpdatedEventData,
+    TokenSupplyChangedEvent, TokenSupplyChangedEventData,
+};
+use crate::holders;
+use crate::initial_distribution;
+use crate::launchpad;
+use crate::meme_cooking::{
+    MemeCookingCampaignOutcomeEvent, MemeCookingCreateMemeEvent, MemeCookingCreateTokenEvent,
+};
+use crate::metadata_updates;
+use crate::new_nep141::NewNep141Event;
+use crate::ref_finance;
+use crate::supply;
+use crate::{BlockContext, ContractEventHandler, EventContext};
+
+pub const ID_HEADER: &str = "X-Webhook-Id";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// An HTTP endpoint that receives events
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    /// Key of the HMAC signature, shared with the receiver
+    pub secret: String,
+    /// Event ids that are sent to this endpoint, all events if not set
+    #[serde(default)]
+    pub events: Option<HashSet<String>>,
+}
+
+impl WebhookEndpoint {
+    pub fn accepts(&self, event_id: &str) -> bool {
+        self.events
+            .as_ref()
+            .is_none_or(|events| events.contains(event_id))
+    }
+}
+
+pub async fn load_webhook_endpoints(
+    path: impl AsRef<Path>,
+) -> anyhow::Result<Vec<WebhookEndpoint>> {
+    Ok(serde_json::from_slice(&tokio::fs::read(path).await?)?)
+}
+
+pub struct WebhookOptions {
+    /// Attempts before a delivery is saved for redelivery
+    pub max_attempts: u32,
+    /// Delay after the first failed attempt, doubled after each next one
+    pub initial_backoff: Duration,
+    /// How often saved deliveries are retried
+    pub redelivery_interval: Duration,
+    pub request_timeout: Duration,
+    /// Deliveries that are sent to one endpoint at the same time. When an endpoint is
+    /// busy, new deliveries wait in the storage until the next redelivery.
+    pub max_concurrent_deliveries: usize,
+    /// Deliveries that still haven't succeeded this long after the event are dropped
+    pub max_age: Duration,
+}
+
+impl Default for WebhookOptions {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            redelivery_interval: Duration::from_secs(60),
+            request_timeout: Duration::from_secs(10),
+            max_concurrent_deliveries: 16,
+            max_age: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// A request body that is waiting to be delivered to one endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    /// Index of the endpoint in the configuration, since several endpoints can have the same URL
+    #[serde(default)]
+    pub endpoint: usize,
+    pub url: String,
+    pub event: String,
+    pub body: String,
+    /// Unix timestamp in seconds of the first attempt
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookBody<T> {
+    /// Stays the same across retries, so receivers can skip duplicates
+    pub id: String,
+    pub event: String,
+    pub testnet: bool,
+    pub data: T,
+}
+
+/// Deliveries that haven't succeeded yet. They're saved before the first attempt and
+/// removed once delivered, so that a restart doesn't lose them.
+#[async_trait]
+pub trait UndeliveredWebhookStorage: Send + Sync {
+    async fn save_undelivered(&self, delivery: WebhookDelivery);
+    async fn remove_undelivered(&self, delivery: &WebhookDelivery);
+    async fn undelivered(&self) -> Vec<WebhookDelivery>;
+}
+
+/// POSTs every event as JSON to all endpoints that accept it. Deliveries run in the
+/// background, so a slow endpoint doesn't hold back indexing, but their order is not
+/// guaranteed.
+pub struct WebhookHandler {
+    sender: Arc<WebhookSender>,
+    testnet: bool,
+}
+
+struct WebhookSender {
+    client: reqwest::Client,
+    endpoints: Vec<WebhookEndpoint>,
+    /// Limits deliveries to each endpoint, by its index in `endpoints`
+    permits: Vec<Arc<Semaphore>>,
+    /// Ids and endpoints of deliveries that are being sent, so that redelivery doesn't send them twice
+    in_flight: Mutex<HashSet<(String, usize)>>,
+    storage: Arc<dyn UndeliveredWebhookStorage>,
+    options: WebhookOptions,
+}
+
+impl WebhookHandler {
+    pub async fn new(
+        endpoints: Vec<WebhookEndpoint>,
+        storage: impl UndeliveredWebhookStorage + 'static,
+        options: WebhookOptions,
+        testnet: bool,
+    ) -> Self {
+        let sender = Arc::new(WebhookSender {
+            client: reqwest::Client::builder()
+                .timeout(options.request_timeout)
+                .build()
+                .expect("Failed to create HTTP client"),
+            permits: endpoints
+                .iter()
+                .map(|_| Arc::new(Semaphore::new(options.max_concurrent_deliveries)))
+                .collect(),
+            endpoints,
+            in_flight: Mutex::new(HashSet::new()),
+            storage: Arc::new(storage),
+            options,
+        });
+        tokio::spawn(redeliver(Arc::downgrade(&sender)));
+        Self { sender, testnet }
+    }
+
+    async fn deliver(&self, event_id: &str, data: impl Serialize) {
+        let data = serde_json::to_value(data).unwrap();
+        let id = hex::encode(Sha256::digest(format!("{event_id}:{data}")));
+        let body = serde_json::to_string(&WebhookBody {
+            id: id.clone(),
+            event: event_id.to_string(),
+            testnet: self.testnet,
+            data,
+        })
+        .unwrap();
+        for (index, endpoint) in self
+            .sender
+            .endpoints
+            .iter()
+            .enumerate()
+            .filter(|(_, endpoint)| endpoint.accepts(event_id))
+        {
+            let delivery = WebhookDelivery {
+                id: id.clone(),
+                endpoint: index,
+                url: endpoint.url.clone(),
+                event: event_id.to_string(),
+                body: body.clone(),
+                created_at: unix_timestamp(),
+            };
+            self.sender.storage.save_undelivered(delivery.clone()).await;
+            self.sender.start(delivery).await;
+        }
+    }
+}
+
+impl WebhookSender {
+    /// Sends the delivery in the background, unless it's already being sent or the endpoint is busy
+    async fn start(self: &Arc<Self>, delivery: WebhookDelivery) {
+        let Some(endpoint) = self
+            .endpoints
+            .get(delivery.endpoint)
+            .filter(|endpoint| endpoint.url == delivery.url)
+        else {
+            log::warn!(
+                "Dropping webhook {} to {}, the endpoint is no longer configured",
+                delivery.id,
+                delivery.url
+            );
+            self.storage.remove_undelivered(&delivery).await;
+            return;
+        };
+        if unix_timestamp().saturating_sub(delivery.created_at) > self.options.max_age.as_secs() {
+            log::error!(
+                "Dropping webhook {} to {}, it wasn't delivered in {:?}",
+                delivery.id,
+                delivery.url,
+                self.options.max_age
+            );
+            self.storage.remove_undelivered(&delivery).await;
+            return;
+        }
+        let Ok(permit) = Arc::clone(&self.permits[delivery.endpoint]).try_acquire_owned() else {
+            return;
+        };
+        if !self
+            .in_flight
+            .lock()
+            .unwrap()
+            .insert((delivery.id.clone(), delivery.endpoint))
+        {
+            return;
+        }
+        let secret = endpoint.secret.clone();
+        tokio::spawn(Arc::clone(self).send(delivery, secret, permit));
+    }
+
+    async fn send(
+        self: Arc<Self>,
+        delivery: WebhookDelivery,
+        secret: String,
+        _permit: OwnedSemaphorePermit,
+    ) {
+        self.attempt(&delivery, &secret).await;
+        self.in_flight
+            .lock()
+            .unwrap()
+            .remove(&(delivery.id, delivery.endpoint));
+    }
+
+    async fn attempt(&self, delivery: &WebhookDelivery, secret: &str) {
+        let mut backoff = self.options.initial_backoff;
+        for attempt in 1..=self.options.max_attempts {
+            let timestamp = unix_timestamp();
+            let result = self
+                .client
+                .post(&delivery.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(ID_HEADER, &delivery.id)
+                .header(EVENT_HEADER, &delivery.event)
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(
+                    SIGNATURE_HEADER,
+                    sign_webhook(secret, timestamp, &delivery.body),
+                )
+                .body(delivery.body.clone())
+                .send()
+                .await;
+            match result {
+                Ok(response) if response.status().is_success() => {
+                    self.storage.remove_undelivered(delivery).await;
+                    return;
+                }
+                Ok(response) => log::warn!(
+                    "Webhook {} to {} failed with {} (attempt {attempt})",
+                    delivery.id,
+                    delivery.url,
+                    response.status()
+                ),
+                Err(err) => log::warn!(
+                    "Webhook {} to {} failed: {err} (attempt {attempt})",
+                    delivery.id,
+                    delivery.url
+                ),
+            }
+            if attempt < self.options.max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+    }
+}
+
+/// Retries saved deliveries until the handler is dropped, including the ones
+/// that were saved before a restart
+async fn redeliver(sender: Weak<WebhookSender>) {
+    loop {
+        let Some(sender) = sender.upgrade() else {
+            return;
+        };
+        for delivery in sender.storage.undelivered().await {
+            sender.start(delivery).await;
+        }
+        let interval = sender.options.redelivery_interval;
+        drop(sender);
+        tokio::time::sleep(interval).await;
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Value of the signature header: `sha256=` and a hex-encoded HMAC-SHA256 of
+/// `{timestamp}.{body}`, keyed with the endpoint's secret
+pub fn sign_webhook(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[async_trait]
+impl ContractEventHandler for WebhookHandler {
+    async fn handle_new_nep141(&self, account_id: AccountId, context: EventContext) {
+        self.handle_new_nep141_with_metadata(NewNep141Event::new(account_id, None, None), context)
+            .await
+    }
+
+    async fn handle_new_nep141_with_metadata(&self, event: NewNep141Event, context: EventContext) {
+        self.deliver(
+            NewContractNep141Event::ID,
+            NewContractNep141EventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_new_meme(
+        &self,
+        event: MemeCookingCreateMemeEvent,
+        context: EventContext,
+    ) {
+        self.deliver(
+            NewMemeCookingMemeEvent::ID,
+            NewMemeCookingMemeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_new_token(
+        &self,
+        event: MemeCookingCreateTokenEvent,
+        context: EventContext,
+    ) {
+        self.deliver(
+            NewMemeCookingTokenEvent::ID,
+            NewMemeCookingTokenEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_reached_soft_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.deliver(
+            MemeReachedSoftCapEvent::ID,
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_reached_hard_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.deliver(
+            MemeReachedHardCapEvent::ID,
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_meme_cooking_expired_without_token(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.deliver(
+            MemeExpiredWithoutTokenEvent::ID,
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_launchpad_token_created(
+        &self,
+        event: launchpad::LaunchpadTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.deliver(
+            LaunchpadTokenCreatedEvent::ID,
+            LaunchpadTokenCreatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_bridged_token_created(
+        &self,
+        event: bridge::BridgedTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.deliver(
+            BridgedTokenCreatedEvent::ID,
+            BridgedTokenCreatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_first_pool(
+        &self,
+        event: ref_finance::TokenFirstPoolEvent,
+        context: EventContext,
+    ) {
+        self.deliver(
+            TokenFirstPoolEvent::ID,
+            TokenFirstPoolEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_initial_distribution(
+        &self,
+        event: initial_distribution::TokenInitialDistributionEvent,
+        context: EventContext,
+    ) {
+        self.deliver(
+            TokenInitialDistributionEvent::ID,
+            TokenInitialDistributionEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_supply_changed(
+        &self,
+        event: supply::TokenSupplyChangedEvent,
+        context: BlockContext,
+    ) {
+        self.deliver(
+            TokenSupplyChangedEvent::ID,
+            TokenSupplyChangedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_holders_snapshot(
+        &self,
+        event: holders::TokenHoldersSnapshotEvent,
+        context: BlockContext,
+    ) {
+        self.deliver(
+            TokenHoldersSnapshotEvent::ID,
+            TokenHoldersSnapshotEventData::new(event, context),
+        )
+        .await;
+    }
+
+    async fn handle_token_metadata_updated(
+        &self,
+        event: metadata_updates::TokenMetadataUpdatedEvent,
+        context: BlockContext,
+    ) {
+        self.deliver(
+            TokenMetadataUpdatedEvent::ID,
+            TokenMetadataUpdatedEventData::new(event, context),
+        )
+        .await;
+    }
+
+    fn is_testnet(&self) -> bool {
+        self.testnet
+    }
+}
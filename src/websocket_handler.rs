@@ -0,0 +1,488 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        Query, State, WebSocketUpgrade,
+    },
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Router,
+};
+use futures_util::{stream, Stream};
+use inindexer::near_indexer_primitives::types::AccountId;
+use intear_events::events::newcontract::{
+    meme_cooking_meme::NewMemeCookingMemeEvent, meme_cooking_token::NewMemeCookingTokenEvent,
+    nep141::NewContractNep141Event,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::bridge;
+use crate::events::{
+    BridgedTokenCreatedEvent, BridgedTokenCreatedEventData, LaunchpadTokenCreatedEvent,
+    LaunchpadTokenCreatedEventData, MemeCookingCampaignOutcomeEventData,
+    MemeExpiredWithoutTokenEvent, MemeReachedHardCapEvent, MemeReachedSoftCapEvent,
+    NewContractNep141EventData, NewMemeCookingMemeEventData, NewMemeCookingTokenEventData,
+    TokenFirstPoolEvent, TokenFirstPoolEventData, TokenHoldersSnapshotEvent,
+    TokenHoldersSnapshotEventData, TokenInitialDistributionEvent,
+    TokenInitialDistributionEventData, TokenMetadataUpdatedEvent, TokenMetadataUpdatedEventData,
+    TokenSupplyChangedEvent, TokenSupplyChangedEventData,
+};
+use crate::holders;
+use crate::initial_distribution;
+use crate::launchpad;
+use crate::meme_cooking::{
+    MemeCookingCampaignOutcomeEvent, MemeCookingCreateMemeEvent, MemeCookingCreateTokenEvent,
+};
+use crate::metadata_updates;
+use crate::new_nep141::NewNep141Event;
+use crate::ref_finance;
+use crate::supply;
+use crate::{BlockContext, ContractEventHandler, EventContext};
+
+pub struct WebSocketOptions {
+    /// Recent events that are sent to every client when it subscribes
+    pub replay_events: usize,
+    /// Events that a slow client can fall behind by before it starts missing them
+    pub channel_capacity: usize,
+}
+
+impl Default for WebSocketOptions {
+    fn default() -> Self {
+        Self {
+            replay_events: 100,
+            channel_capacity: 1024,
+        }
+    }
+}
+
+/// Sent to clients, `data` is the same as in Redis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiveEvent {
+    pub event: String,
+    pub data: Value,
+}
+
+impl LiveEvent {
+    /// `token_id`, or `account_id` for new NEP-141 tokens
+    fn token_id(&self) -> Option<&str> {
+        self.data
+            .get("token_id")
+            .or_else(|| self.data.get("account_id"))
+            .and_then(Value::as_str)
+    }
+
+    /// Symbol of a new token or meme, or the new metadata of a token
+    fn symbol(&self) -> Option<&str> {
+        self.data
+            .get("symbol")
+            .or_else(|| self.data.get("metadata")?.get("symbol"))
+            .and_then(Value::as_str)
+    }
+}
+
+/// The first message that a WebSocket client sends, or query parameters of `/sse`
+/// (with `events` separated by commas). All filters are optional.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Event ids like `newcontract_nep141`
+    pub events: Option<HashSet<String>>,
+    /// Only events about tokens whose account id or factory is this account or one of its
+    /// sub-accounts, like `tkn.near`
+    pub factory: Option<String>,
+    /// Regex that the token's symbol must match. Events that don't have a symbol
+    /// (everything except new tokens, memes and metadata updates) are skipped.
+    pub symbol: Option<String>,
+}
+
+struct Filter {
+    events: Option<HashSet<String>>,
+    factory: Option<String>,
+    symbol: Option<Regex>,
+}
+
+impl Filter {
+    fn new(subscription: Subscription) -> Result<Self, regex::Error> {
+        Ok(Self {
+            events: subscription.events,
+            factory: subscription.factory,
+            symbol: subscription
+                .symbol
+                .map(|symbol| Regex::new(&symbol))
+                .transpose()?,
+        })
+    }
+
+    fn matches(&self, event: &LiveEvent) -> bool {
+        if let Some(events) = &self.events {
+            if !events.contains(&event.event) {
+                return false;
+            }
+        }
+        if let Some(factory) = &self.factory {
+            let factory_id = event.data.get("factory_id").and_then(Value::as_str);
+            if ![event.token_id(), factory_id]
+                .into_iter()
+                .flatten()
+                .any(|account_id| {
+                    account_id == factory || account_id.ends_with(&format!(".{factory}"))
+                })
+            {
+                return false;
+            }
+        }
+        if let Some(symbol) = &self.symbol {
+            if !event.symbol().is_some_and(|s| symbol.is_match(s)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Deserialize)]
+struct SseQuery {
+    events: Option<String>,
+    factory: Option<String>,
+    symbol: Option<String>,
+}
+
+struct LiveEvents {
+    sender: broadcast::Sender<Arc<LiveEvent>>,
+    recent: Mutex<VecDeque<Arc<LiveEvent>>>,
+    replay_events: usize,
+}
+
+impl LiveEvents {
+    /// Recent events and a receiver of the ones after them, without gaps or duplicates
+    fn subscribe(&self) -> (Vec<Arc<LiveEvent>>, broadcast::Receiver<Arc<LiveEvent>>) {
+        let recent = self.recent.lock().unwrap();
+        (recent.iter().cloned().collect(), self.sender.subscribe())
+    }
+}
+
+/// Broadcasts every event to clients connected to [`WebSocketHandler::router`]. WebSocket
+/// clients connect to `/ws` and send a [`Subscription`] as JSON, then get the recent events
+/// that match it followed by new ones. `/sse` does the same with server-sent events, with the
+/// subscription in the query string. Clients that fall too far behind miss events.
+pub struct WebSocketHandler {
+    events: Arc<LiveEvents>,
+    testnet: bool,
+}
+
+impl WebSocketHandler {
+    pub fn new(options: WebSocketOptions, testnet: bool) -> Self {
+        Self {
+            events: Arc::new(LiveEvents {
+                sender: broadcast::channel(options.channel_capacity).0,
+                recent: Mutex::new(VecDeque::with_capacity(options.replay_events)),
+                replay_events: options.replay_events,
+            }),
+            testnet,
+        }
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/ws", get(websocket))
+            .route("/sse", get(sse))
+            .with_state(Arc::clone(&self.events))
+    }
+
+    fn broadcast(&self, event_id: &str, data: impl Serialize) {
+        let event = Arc::new(LiveEvent {
+            event: event_id.to_string(),
+            data: serde_json::to_value(data).unwrap(),
+        });
+        let mut recent = self.events.recent.lock().unwrap();
+        if self.events.replay_events > 0 {
+            if recent.len() == self.events.replay_events {
+                recent.pop_front();
+            }
+            recent.push_back(Arc::clone(&event));
+        }
+        // Fails only if nobody is connected
+        let _ = self.events.sender.send(event);
+    }
+}
+
+async fn websocket(State(events): State<Arc<LiveEvents>>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(|socket| handle_websocket(socket, events))
+}
+
+async fn handle_websocket(mut socket: WebSocket, events: Arc<LiveEvents>) {
+    let filter = loop {
+        let Some(Ok(message)) = socket.recv().await else {
+            return;
+        };
+        let Message::Text(text) = message else {
+            continue;
+        };
+        let filter = serde_json::from_str::<Subscription>(&text)
+            .map_err(|err| err.to_string())
+            .and_then(|subscription| Filter::new(subscription).map_err(|err| err.to_string()));
+        match filter {
+            Ok(filter) => break filter,
+            Err(err) => {
+                let error = serde_json::json!({ "error": err }).to_string();
+                if socket.send(Message::Text(error.into())).await.is_err() {
+                    return;
+                }
+            }
+        }
+    };
+    let (recent, mut receiver) = events.subscribe();
+    for event in recent {
+        if filter.matches(&event) && send_event(&mut socket, &event).await.is_err() {
+            return;
+        }
+    }
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => {
+                    if filter.matches(&event) && send_event(&mut socket, &event).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    log::warn!("WebSocket client fell behind and missed {missed} events");
+                }
+                Err(RecvError::Closed) => return,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(_)) => {}
+                _ => return,
+            },
+        }
+    }
+}
+
+async fn send_event(socket: &mut WebSocket, event: &LiveEvent) -> Result<(), axum::Error> {
+    socket
+        .send(Message::Text(serde_json::to_string(event).unwrap().into()))
+        .await
+}
+
+async fn sse(State(events): State<Arc<LiveEvents>>, Query(query): Query<SseQuery>) -> Response {
+    let subscription = Subscription {
+        events: query.events.map(|events| {
+            events
+                .split(',')
+                .map(|event_id| event_id.trim().to_string())
+                .filter(|event_id| !event_id.is_empty())
+                .collect()
+        }),
+        factory: query.factory,
+        symbol: query.symbol,
+    };
+    let filter = match Filter::new(subscription) {
+        Ok(filter) => Arc::new(filter),
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+    let (recent, receiver) = events.subscribe();
+    Sse::new(sse_events(filter, recent, receiver))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn sse_events(
+    filter: Arc<Filter>,
+    recent: Vec<Arc<LiveEvent>>,
+    receiver: broadcast::Receiver<Arc<LiveEvent>>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    let replayed = stream::iter(
+        recent
+            .into_iter()
+            .filter(|event| filter.matches(event))
+            .map(|event| Ok(sse_event(&event)))
+            .collect::<Vec<_>>(),
+    );
+    let live = stream::unfold(receiver, move |mut receiver| {
+        let filter = Arc::clone(&filter);
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if filter.matches(&event) => {
+                        return Some((Ok(sse_event(&event)), receiver));
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        log::warn!("SSE client fell behind and missed {missed} events");
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+    futures_util::StreamExt::chain(replayed, live)
+}
+
+fn sse_event(event: &LiveEvent) -> Event {
+    Event::default()
+        .event(&event.event)
+        .data(serde_json::to_string(&event.data).unwrap())
+}
+
+#[async_trait]
+impl ContractEventHandler for WebSocketHandler {
+    async fn handle_new_nep141(&self, account_id: AccountId, context: EventContext) {
+        self.handle_new_nep141_with_metadata(NewNep141Event::new(account_id, None, None), context)
+            .await
+    }
+
+    async fn handle_new_nep141_with_metadata(&self, event: NewNep141Event, context: EventContext) {
+        self.broadcast(
+            NewContractNep141Event::ID,
+            NewContractNep141EventData::new(event, context),
+        );
+    }
+
+    async fn handle_meme_cooking_new_meme(
+        &self,
+        event: MemeCookingCreateMemeEvent,
+        context: EventContext,
+    ) {
+        self.broadcast(
+            NewMemeCookingMemeEvent::ID,
+            NewMemeCookingMemeEventData::new(event, context),
+        );
+    }
+
+    async fn handle_meme_cooking_new_token(
+        &self,
+        event: MemeCookingCreateTokenEvent,
+        context: EventContext,
+    ) {
+        self.broadcast(
+            NewMemeCookingTokenEvent::ID,
+            NewMemeCookingTokenEventData::new(event, context),
+        );
+    }
+
+    async fn handle_meme_cooking_reached_soft_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.broadcast(
+            MemeReachedSoftCapEvent::ID,
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        );
+    }
+
+    async fn handle_meme_cooking_reached_hard_cap(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.broadcast(
+            MemeReachedHardCapEvent::ID,
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        );
+    }
+
+    async fn handle_meme_cooking_expired_without_token(
+        &self,
+        event: MemeCookingCampaignOutcomeEvent,
+        context: BlockContext,
+    ) {
+        self.broadcast(
+            MemeExpiredWithoutTokenEvent::ID,
+            MemeCookingCampaignOutcomeEventData::new(event, context),
+        );
+    }
+
+    async fn handle_launchpad_token_created(
+        &self,
+        event: launchpad::LaunchpadTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.broadcast(
+            LaunchpadTokenCreatedEvent::ID,
+            LaunchpadTokenCreatedEventData::new(event, context),
+        );
+    }
+
+    async fn handle_bridged_token_created(
+        &self,
+        event: bridge::BridgedTokenCreatedEvent,
+        context: EventContext,
+    ) {
+        self.broadcast(
+            BridgedTokenCreatedEvent::ID,
+            BridgedTokenCreatedEventData::new(event, context),
+        );
+    }
+
+    async fn handle_token_first_pool(
+        &self,
+        event: ref_finance::TokenFirstPoolEvent,
+        context: EventContext,
+    ) {
+        self.broadcast(
+            TokenFirstPoolEvent::ID,
+            TokenFirstPoolEventData::new(event, context),
+        );
+    }
+
+    async fn handle_token_initial_distribution(
+        &self,
+        event: initial_distribution::TokenInitialDistributionEvent,
+        context: EventContext,
+    ) {
+        self.broadcast(
+            TokenInitialDistributionEvent::ID,
+            TokenInitialDistributionEventData::new(event, context),
+        );
+    }
+
+    async fn handle_token_supply_changed(
+        &self,
+        event: supply::TokenSupplyChangedEvent,
+        context: BlockContext,
+    ) {
+        self.broadcast(
+            TokenSupplyChangedEvent::ID,
+            TokenSupplyChangedEventData::new(event, context),
+        );
+    }
+
+    async fn handle_token_holders_snapshot(
+        &self,
+        event: holders::TokenHoldersSnapshotEvent,
+        context: BlockContext,
+    ) {
+        self.broadcast(
+            TokenHoldersSnapshotEvent::ID,
+            TokenHoldersSnapshotEventData::new(event, context),
+        );
+    }
+
+    async fn handle_token_metadata_updated(
+        &self,
+        event: metadata_updates::TokenMetadataUpdatedEvent,
+        context: BlockContext,
+    ) {
+        self.broadcast(
+            TokenMetadataUpdatedEvent::ID,
+            TokenMetadataUpdatedEventData::new(event, context),
+        );
+    }
+
+    fn is_testnet(&self) -> bool {
+        self.testnet
+    }
+}